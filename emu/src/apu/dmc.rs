@@ -0,0 +1,207 @@
+use {
+    super::*,
+};
+
+// Output unit periods in CPU cycles
+pub const NTSC_DMC_RATES: [Word; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+pub const PAL_DMC_RATES: [Word; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+pub const DMC_SAMPLE_BASE: Word = 0xC000;
+
+// Delta modulation channel. The memory reader can't read the bus on its
+// own, it asks for a DMA fetch and is handed the byte once the CPU has
+// been stalled for it, see `Memory::tick`
+#[derive(Debug, Clone, Copy)]
+pub struct Dmc {
+    irq_enabled: bool,
+    looped: bool,
+    irq: bool,
+
+    rate: Word,
+    timer: Word,
+
+    sample_addr: Word,
+    sample_length: Word,
+
+    // Memory reader
+    current_addr: Word,
+    bytes_remaining: Word,
+    buffer: Option<Byte>,
+    fetching: bool,
+
+    // Output unit
+    shift: Byte,
+    bits_remaining: Byte,
+    silence: bool,
+    level: Byte,
+}
+
+savestate!(Dmc {
+    irq_enabled, looped, irq, rate, timer, sample_addr, sample_length, current_addr,
+    bytes_remaining, buffer, fetching, shift, bits_remaining, silence, level,
+});
+
+impl Dmc {
+    pub fn write(&mut self, register: Word, data: Byte, region: Region) {
+        match register {
+            0 => {
+                self.irq_enabled = (data & 0x80) != 0;
+                self.looped = (data & 0x40) != 0;
+                self.rate = dmc_rates(region)[(data & 0x0F) as usize];
+
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+
+            1 => self.level = data & 0x7F,
+            2 => self.sample_addr = DMC_SAMPLE_BASE | (data as Word) << 6,
+            3 => self.sample_length = (data as Word) << 4 | 1,
+
+            _ => {}
+        }
+    }
+
+    // $4015 bit 4, also acknowledges the DMC interrupt
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // Address of the next sample byte once the buffer has run dry, the
+    // request is only handed out once until `fill` answers it
+    pub fn take_fetch(&mut self) -> Option<Word> {
+        if self.fetching || self.buffer.is_some() || self.bytes_remaining == 0 {
+            return None;
+        }
+
+        self.fetching = true;
+        Some(self.current_addr)
+    }
+
+    #[inline(always)]
+    pub fn fetching(&self) -> bool {
+        self.fetching
+    }
+
+    pub fn fill(&mut self, sample: Byte) {
+        self.fetching = false;
+        self.buffer = Some(sample);
+
+        // Wraps around to $8000 rather than $0000
+        self.current_addr = self.current_addr.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looped {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.rate - 1;
+            self.clock_output();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_output(&mut self) {
+        if !self.silence {
+            if (self.shift & 0x01) != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+
+            match self.buffer.take() {
+                Some(sample) => {
+                    self.shift = sample;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    #[inline(always)]
+    pub fn bytes_remaining(&self) -> Word {
+        self.bytes_remaining
+    }
+
+    #[inline(always)]
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    #[inline(always)]
+    pub fn output(&self) -> Byte {
+        self.level
+    }
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self { irq_enabled: false,
+               looped: false,
+               irq: false,
+
+               rate: NTSC_DMC_RATES[0],
+               timer: 0,
+
+               sample_addr: DMC_SAMPLE_BASE,
+               sample_length: 1,
+
+               current_addr: DMC_SAMPLE_BASE,
+               bytes_remaining: 0,
+               buffer: None,
+               fetching: false,
+
+               shift: 0,
+               bits_remaining: 8,
+               silence: true,
+               level: 0 }
+    }
+}
+
+// Dendy uses the NTSC rates
+#[inline(always)]
+pub fn dmc_rates(region: Region) -> &'static [Word; 16] {
+    match region {
+        Region::Pal => &PAL_DMC_RATES,
+        Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+    }
+}
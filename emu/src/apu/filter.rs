@@ -0,0 +1,54 @@
+use {
+    std::f32::consts::PI,
+};
+
+// The console's output stage, two high-passes and a low-pass
+pub const HIGH_PASS_CUTOFFS: [f32; 2] = [90.0, 440.0];
+pub const LOW_PASS_CUTOFF: f32        = 14_000.0;
+
+// First order RC filters running at the host sample rate
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HighPass {
+    alpha: f32,
+
+    prev_input: f32,
+    prev_output: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LowPass {
+    alpha: f32,
+    prev_output: f32,
+}
+
+impl HighPass {
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.prev_output = self.alpha * (self.prev_output + input - self.prev_input);
+        self.prev_input = input;
+
+        self.prev_output
+    }
+
+    pub fn new(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+
+        Self { alpha: rc / (rc + dt),
+               ..Default::default() }
+    }
+}
+
+impl LowPass {
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.prev_output += self.alpha * (input - self.prev_output);
+        self.prev_output
+    }
+
+    pub fn new(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+
+        Self { alpha: dt / (rc + dt),
+               ..Default::default() }
+    }
+}
@@ -0,0 +1,111 @@
+use {
+    super::*,
+};
+
+// CPU cycles of the sequencer steps. The 4-step sequence starts over
+// right after its fourth step, the 5-step one skips that step and ends
+// after the fifth
+pub const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+pub const PAL_FRAME_STEPS: [u32; 5]  = [8313, 16627, 24939, 33253, 41565];
+
+pub const FRAME_FIVE_STEP: Byte   = 1 << 7;
+pub const FRAME_IRQ_INHIBIT: Byte = 1 << 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameClock {
+    None,
+
+    // Envelopes and the triangle's linear counter
+    Quarter,
+    // Everything clocked on quarter frames plus length counters and sweeps
+    Half,
+}
+
+// Frame sequencer, counting CPU cycles
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCounter {
+    cycle: u32,
+    five_step: bool,
+
+    irq_inhibit: bool,
+    irq: bool,
+
+    // A $4017 write resets the sequence 3 or 4 cycles later
+    pending: Option<(Byte, u8)>,
+}
+
+savestate!(FrameCounter { cycle, five_step, irq_inhibit, irq, pending });
+
+impl FrameCounter {
+    // `odd_cycle` is whether the write lands between two APU cycles
+    pub fn write(&mut self, data: Byte, odd_cycle: bool) {
+        self.irq_inhibit = (data & FRAME_IRQ_INHIBIT) != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+
+        self.pending = Some((data, if odd_cycle { 4 } else { 3 }));
+    }
+
+    pub fn tick(&mut self, region: Region) -> FrameClock {
+        if let Some((data, delay)) = self.pending {
+            if delay > 1 {
+                self.pending = Some((data, delay - 1));
+            } else {
+                self.pending = None;
+                self.cycle = 0;
+                self.five_step = (data & FRAME_FIVE_STEP) != 0;
+
+                // Entering the 5-step mode clocks everything right away
+                return if self.five_step { FrameClock::Half } else { FrameClock::None };
+            }
+        }
+
+        let steps = frame_steps(region);
+        self.cycle += 1;
+
+        let last = if self.five_step { steps[4] } else { steps[3] };
+        let clock = match self.cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => FrameClock::Quarter,
+            cycle if cycle == steps[1] || cycle == last => FrameClock::Half,
+
+            _ => FrameClock::None,
+        };
+
+        // The interrupt flag is raised over the last three cycles of the
+        // 4-step sequence, the final one being the first of the next
+        if !self.five_step && !self.irq_inhibit && (last - 1..=last + 1).contains(&self.cycle) {
+            self.irq = true;
+        }
+
+        if self.cycle > last {
+            self.cycle = 0;
+        }
+
+        clock
+    }
+
+    #[inline(always)]
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    #[inline(always)]
+    pub fn acknowledge(&mut self) {
+        self.irq = false;
+    }
+
+    #[inline(always)]
+    pub fn five_step(&self) -> bool {
+        self.five_step
+    }
+}
+
+// Dendy clocks its APU like NTSC
+#[inline(always)]
+pub fn frame_steps(region: Region) -> &'static [u32; 5] {
+    match region {
+        Region::Pal => &PAL_FRAME_STEPS,
+        Region::Ntsc | Region::Dendy => &NTSC_FRAME_STEPS,
+    }
+}
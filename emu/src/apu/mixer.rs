@@ -0,0 +1,53 @@
+use {
+    super::*,
+};
+
+pub const PULSE_LEVELS: usize = 31;
+pub const TND_LEVELS: usize   = 203;
+
+// The pulse DAC for the sum of both pulse outputs, MMC5 pulses go through
+// the same curve
+pub fn pulse_level(pulses: Byte) -> f32 {
+    match pulses {
+        0 => 0.0,
+        n => 95.52 / (8128.0 / n as f32 + 100.0),
+    }
+}
+
+// Lookup-table version of the 2A03's nonlinear DAC mixing, the pulses
+// share one resistor network and the triangle, noise and DMC another
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    pulse_table: [f32; PULSE_LEVELS],
+    tnd_table: [f32; TND_LEVELS],
+}
+
+impl Mixer {
+    pub fn mix(&self, pulse1: Byte, pulse2: Byte, triangle: Byte, noise: Byte, dmc: Byte) -> f32 {
+        let pulse = (pulse1 + pulse2) as usize;
+        let tnd = 3 * triangle as usize + 2 * noise as usize + dmc as usize;
+
+        self.pulse_table[pulse] + self.tnd_table[tnd]
+    }
+
+    pub fn new() -> Self {
+        let mut pulse_table = [0.0; PULSE_LEVELS];
+        let mut tnd_table = [0.0; TND_LEVELS];
+
+        for (n, level) in pulse_table.iter_mut().enumerate() {
+            *level = pulse_level(n as Byte);
+        }
+
+        for (n, level) in tnd_table.iter_mut().enumerate().skip(1) {
+            *level = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        Self { pulse_table, tnd_table }
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
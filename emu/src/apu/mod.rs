@@ -0,0 +1,262 @@
+use {
+    crate::{
+        mem::*,
+        region::*,
+        state::*,
+    },
+};
+
+pub mod units;
+pub mod pulse;
+pub mod triangle;
+pub mod noise;
+pub mod dmc;
+pub mod frame;
+
+pub mod mixer;
+pub mod filter;
+pub mod resampler;
+
+#[cfg(test)]
+mod tests;
+
+pub use {
+    units::*,
+    pulse::*,
+    triangle::*,
+    noise::*,
+    dmc::*,
+    frame::*,
+    mixer::*,
+    filter::*,
+    resampler::*,
+};
+
+pub const PULSE1_SPACE: Word      = 0x4000;
+pub const PULSE2_SPACE: Word      = 0x4004;
+pub const TRIANGLE_SPACE: Word    = 0x4008;
+pub const NOISE_SPACE: Word       = 0x400C;
+pub const DMC_SPACE: Word         = 0x4010;
+
+pub const APU_STATUS: Word        = 0x4015;
+pub const APU_FRAME_COUNTER: Word = 0x4017;
+
+pub const STATUS_PULSE1: Byte     = 1 << 0;
+pub const STATUS_PULSE2: Byte     = 1 << 1;
+pub const STATUS_TRIANGLE: Byte   = 1 << 2;
+pub const STATUS_NOISE: Byte      = 1 << 3;
+pub const STATUS_DMC: Byte        = 1 << 4;
+pub const STATUS_FRAME_IRQ: Byte  = 1 << 6;
+pub const STATUS_DMC_IRQ: Byte    = 1 << 7;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+pub struct Apu {
+    pub pulses: [Pulse; 2],
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+
+    pub frame_counter: FrameCounter,
+    pub region: Region,
+
+    odd_cycle: bool,
+
+    mixer: Mixer,
+    resampler: Resampler,
+    sample_rate: u32,
+
+    high_passes: [HighPass; 2],
+    low_pass: LowPass,
+}
+
+savestate!(Apu { pulses, triangle, noise, dmc, frame_counter, odd_cycle });
+
+impl Apu {
+    // Reading $4015 acknowledges the frame interrupt, but not the DMC's
+    pub fn cpu_read(&mut self, addr: Word) -> Byte {
+        let data = self.cpu_peek(addr);
+
+        if addr == APU_STATUS {
+            self.frame_counter.acknowledge();
+        }
+
+        data
+    }
+
+    pub fn cpu_peek(&self, addr: Word) -> Byte {
+        if addr != APU_STATUS {
+            return 0;
+        }
+
+        let flag = |set: bool, bit: Byte| if set { bit } else { 0 };
+
+        flag(self.pulses[0].length.active(), STATUS_PULSE1)
+            | flag(self.pulses[1].length.active(), STATUS_PULSE2)
+            | flag(self.triangle.length.active(), STATUS_TRIANGLE)
+            | flag(self.noise.length.active(), STATUS_NOISE)
+            | flag(self.dmc.active(), STATUS_DMC)
+            | flag(self.frame_counter.irq(), STATUS_FRAME_IRQ)
+            | flag(self.dmc.irq(), STATUS_DMC_IRQ)
+    }
+
+    pub fn cpu_write(&mut self, addr: Word, data: Byte) {
+        match addr {
+            0x4000..=0x4003 => self.pulses[0].write(addr - PULSE1_SPACE, data),
+            0x4004..=0x4007 => self.pulses[1].write(addr - PULSE2_SPACE, data),
+            0x4008..=0x400B => self.triangle.write(addr - TRIANGLE_SPACE, data),
+            0x400C..=0x400F => self.noise.write(addr - NOISE_SPACE, data, self.region),
+            0x4010..=0x4013 => self.dmc.write(addr - DMC_SPACE, data, self.region),
+
+            APU_STATUS => {
+                self.pulses[0].length.set_enabled((data & STATUS_PULSE1) != 0);
+                self.pulses[1].length.set_enabled((data & STATUS_PULSE2) != 0);
+                self.triangle.length.set_enabled((data & STATUS_TRIANGLE) != 0);
+                self.noise.length.set_enabled((data & STATUS_NOISE) != 0);
+                self.dmc.set_enabled((data & STATUS_DMC) != 0);
+            }
+
+            APU_FRAME_COUNTER => self.frame_counter.write(data, self.odd_cycle),
+
+            _ => {}
+        }
+    }
+
+    // One CPU cycle, the pulse and noise timers run at half the rate
+    pub fn tick(&mut self) {
+        match self.frame_counter.tick(self.region) {
+            FrameClock::Quarter => self.clock_quarter_frame(),
+            FrameClock::Half => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+
+            FrameClock::None => {}
+        }
+
+        self.triangle.clock_timer();
+        self.dmc.clock_timer();
+
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            for pulse in &mut self.pulses {
+                pulse.clock_timer();
+            }
+
+            self.noise.clock_timer();
+        }
+    }
+
+    // Feeds this cycle's level to the resampler, `expansion` being the
+    // cartridge's own audio
+    pub fn mix(&mut self, expansion: f32) {
+        let level = self.mixer.mix(self.pulses[0].output(),
+                                   self.pulses[1].output(),
+                                   self.triangle.output(),
+                                   self.noise.output(),
+                                   self.dmc.output());
+
+        self.resampler.push(level + expansion);
+
+        // Keep at most a second around when nobody drains the samples
+        let excess = self.resampler.available().saturating_sub(self.sample_rate as usize);
+        if excess > 0 {
+            self.resampler.discard(excess);
+        }
+    }
+
+    // Moves finished samples into `out` and returns how many were written
+    pub fn drain_samples(&mut self, out: &mut [f32]) -> usize {
+        let count = self.resampler.read(out);
+
+        for sample in &mut out[..count] {
+            let mut output = *sample;
+            for filter in &mut self.high_passes {
+                output = filter.process(output);
+            }
+
+            *sample = self.low_pass.process(output);
+        }
+
+        count
+    }
+
+    #[inline(always)]
+    pub fn samples_available(&self) -> usize {
+        self.resampler.available()
+    }
+
+    #[inline(always)]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler.set_rates(self.region.cpu_clock() as f64, sample_rate as f64);
+
+        self.high_passes = HIGH_PASS_CUTOFFS.map(|cutoff| HighPass::new(cutoff, sample_rate as f32));
+        self.low_pass = LowPass::new(LOW_PASS_CUTOFF, sample_rate as f32);
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    #[inline(always)]
+    pub fn irq(&self) -> bool {
+        self.frame_counter.irq() || self.dmc.irq()
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        for pulse in &mut self.pulses {
+            pulse.envelope.clock();
+        }
+
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        for pulse in &mut self.pulses {
+            pulse.length.clock();
+            pulse.clock_sweep();
+        }
+
+        self.triangle.length.clock();
+        self.noise.length.clock();
+    }
+
+    pub fn new() -> Self {
+        let region = Region::default();
+        let sample_rate = DEFAULT_SAMPLE_RATE;
+
+        Self { pulses: [Pulse::new(true), Pulse::new(false)],
+               triangle: Triangle::default(),
+               noise: Noise::default(),
+               dmc: Dmc::default(),
+               frame_counter: FrameCounter::default(),
+               region,
+               odd_cycle: false,
+
+               mixer: Mixer::new(),
+               resampler: Resampler::new(region.cpu_clock() as f64, sample_rate as f64),
+               sample_rate,
+
+               high_passes: HIGH_PASS_CUTOFFS.map(|cutoff| HighPass::new(cutoff, sample_rate as f32)),
+               low_pass: LowPass::new(LOW_PASS_CUTOFF, sample_rate as f32) }
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// $4014 and $4016 sit in the middle but belong to DMA and the controllers
+#[inline(always)]
+pub fn is_register(addr: Word) -> bool {
+    matches!(addr, 0x4000..=0x4013 | APU_STATUS | APU_FRAME_COUNTER)
+}
@@ -0,0 +1,98 @@
+use {
+    super::*,
+};
+
+// Timer periods in CPU cycles
+pub const NTSC_NOISE_PERIODS: [Word; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_NOISE_PERIODS: [Word; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    // Short mode taps bit 6 instead of bit 1, giving a 93 or 31 step loop
+    short: bool,
+    shift: Word,
+
+    period: Word,
+    timer: Word,
+
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+}
+
+savestate!(Noise { short, shift, period, timer, length, envelope });
+
+impl Noise {
+    pub fn write(&mut self, register: Word, data: Byte, region: Region) {
+        match register {
+            0 => {
+                self.envelope.write(data);
+                self.length.set_halted((data & 0x20) != 0);
+            }
+
+            2 => {
+                self.short = (data & 0x80) != 0;
+                self.period = noise_periods(region)[(data & 0x0F) as usize];
+            }
+            3 => {
+                self.envelope.restart();
+                self.length.load(data >> 3);
+            }
+
+            _ => {}
+        }
+    }
+
+    // Clocked every APU cycle, i.e. every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period / 2 - 1;
+            self.clock_shift();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_shift(&mut self) {
+        let tap = if self.short { 6 } else { 1 };
+        let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+
+        self.shift = (self.shift >> 1) | (feedback << 14);
+    }
+
+    #[inline(always)]
+    pub fn shift_register(&self) -> Word {
+        self.shift
+    }
+
+    pub fn output(&self) -> Byte {
+        if !self.length.active() || (self.shift & 0x01) != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self { short: false,
+               shift: 1,
+               period: NTSC_NOISE_PERIODS[0],
+               timer: 0,
+               length: LengthCounter::default(),
+               envelope: Envelope::default() }
+    }
+}
+
+// Dendy uses the NTSC tables
+#[inline(always)]
+pub fn noise_periods(region: Region) -> &'static [Word; 16] {
+    match region {
+        Region::Pal => &PAL_NOISE_PERIODS,
+        Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+    }
+}
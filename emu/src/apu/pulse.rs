@@ -0,0 +1,141 @@
+use {
+    super::*,
+};
+
+pub const DUTY_TABLE: [[Byte; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+pub const MAX_PERIOD: Word = 0x07FF;
+pub const MIN_PERIOD: Word = 8;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sweep {
+    enabled: bool,
+    negate: bool,
+    reload: bool,
+
+    period: Byte,
+    shift: Byte,
+    divider: Byte,
+}
+
+savestate!(Sweep { enabled, negate, reload, period, shift, divider });
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pulse {
+    // Pulse 1 negates with ones' complement, so its sweep goes down one
+    // further than pulse 2's
+    ones_complement: bool,
+
+    duty: usize,
+    step: usize,
+
+    period: Word,
+    timer: Word,
+
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    pub sweep: Sweep,
+}
+
+savestate!(Pulse { duty, step, period, timer, length, envelope, sweep });
+
+impl Sweep {
+    fn write(&mut self, data: Byte) {
+        self.enabled = (data & 0x80) != 0;
+        self.period = (data >> 4) & 0x07;
+        self.negate = (data & 0x08) != 0;
+        self.shift = data & 0x07;
+        self.reload = true;
+    }
+}
+
+impl Pulse {
+    pub fn write(&mut self, register: Word, data: Byte) {
+        match register {
+            0 => {
+                self.duty = (data >> 6) as usize;
+                self.envelope.write(data);
+
+                // Envelope loop doubles as the length counter halt flag
+                self.length.set_halted((data & 0x20) != 0);
+            }
+
+            1 => self.sweep.write(data),
+            2 => self.period = (self.period & 0x0700) | data as Word,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((data as Word & 0x07) << 8);
+                self.step = 0;
+
+                self.envelope.restart();
+                self.length.load(data >> 3);
+            }
+
+            _ => {}
+        }
+    }
+
+    // Clocked every APU cycle, i.e. every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let sweep = self.sweep;
+
+        if sweep.divider == 0 && sweep.enabled && sweep.shift != 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+
+        if sweep.divider == 0 || sweep.reload {
+            self.sweep.divider = sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    // Computed all the time, even with the sweep disabled
+    pub fn sweep_target(&self) -> Word {
+        let change = self.period >> self.sweep.shift;
+
+        match (self.sweep.negate, self.ones_complement) {
+            (false, _) => self.period + change,
+            (true, true) => self.period.saturating_sub(change + 1),
+            (true, false) => self.period.saturating_sub(change),
+        }
+    }
+
+    // Periods below 8 and sweep targets past $7FF silence the channel
+    #[inline(always)]
+    pub fn muted(&self) -> bool {
+        self.period < MIN_PERIOD || self.sweep_target() > MAX_PERIOD
+    }
+
+    #[inline(always)]
+    pub fn period(&self) -> Word {
+        self.period
+    }
+
+    pub fn output(&self) -> Byte {
+        if !self.length.active() || self.muted() || DUTY_TABLE[self.duty][self.step] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    pub fn new(ones_complement: bool) -> Self {
+        Self { ones_complement,
+               ..Default::default() }
+    }
+}
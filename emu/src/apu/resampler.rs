@@ -0,0 +1,131 @@
+use {
+    std::f64::consts::PI,
+};
+
+pub const KERNEL_TAPS: usize   = 16;
+pub const KERNEL_PHASES: usize = 64;
+
+// Passband edge as a fraction of the output rate
+pub const KERNEL_CUTOFF: f64 = 0.45;
+
+// Band-limited step synthesis. Input arrives at the CPU clock but rarely
+// changes, so instead of filtering every input sample each change in
+// level is drawn into the output as a windowed-sinc step. The buffer
+// holds differences, reading integrates them back into samples
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    kernel: Vec<[f32; KERNEL_TAPS]>,
+
+    // Output samples per input clock
+    step: f64,
+    // Position of the next input clock, in output samples
+    time: f64,
+
+    buffer: Vec<f32>,
+    integrator: f32,
+    level: f32,
+}
+
+impl Resampler {
+    // One input clock at `level`
+    #[inline(always)]
+    pub fn push(&mut self, level: f32) {
+        let delta = level - self.level;
+        if delta != 0.0 {
+            self.add_delta(delta);
+            self.level = level;
+        }
+
+        self.time += self.step;
+    }
+
+    fn add_delta(&mut self, delta: f32) {
+        let whole = self.time as usize;
+        let phase = ((self.time - whole as f64) * KERNEL_PHASES as f64) as usize;
+
+        if self.buffer.len() < whole + KERNEL_TAPS {
+            self.buffer.resize(whole + KERNEL_TAPS, 0.0);
+        }
+
+        let taps = &self.kernel[phase.min(KERNEL_PHASES - 1)];
+        for (sample, tap) in self.buffer[whole..].iter_mut().zip(taps) {
+            *sample += delta * tap;
+        }
+    }
+
+    // Samples no future input can change anymore
+    #[inline(always)]
+    pub fn available(&self) -> usize {
+        self.time as usize
+    }
+
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.available());
+
+        for (index, sample) in out[..count].iter_mut().enumerate() {
+            self.integrator += self.buffer.get(index).copied().unwrap_or_default();
+            *sample = self.integrator;
+        }
+
+        self.consume(count);
+        count
+    }
+
+    // Drops the oldest samples, e.g. when nobody is reading them
+    pub fn discard(&mut self, count: usize) {
+        let count = count.min(self.available());
+
+        self.integrator += self.buffer.iter().take(count).sum::<f32>();
+        self.consume(count);
+    }
+
+    fn consume(&mut self, count: usize) {
+        self.buffer.drain(..count.min(self.buffer.len()));
+        self.time -= count as f64;
+    }
+
+    pub fn set_rates(&mut self, input_rate: f64, output_rate: f64) {
+        self.step = output_rate / input_rate;
+    }
+
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        Self { kernel: step_kernel(),
+               step: output_rate / input_rate,
+               time: 0.0,
+               buffer: Vec::new(),
+               integrator: 0.0,
+               level: 0.0 }
+    }
+}
+
+// Blackman-windowed sinc impulses for every sub-sample phase, each
+// normalized to sum to one so a step integrates to its full height
+fn step_kernel() -> Vec<[f32; KERNEL_TAPS]> {
+    let half = KERNEL_TAPS as f64 / 2.0;
+
+    (0..KERNEL_PHASES).map(|phase| {
+        let offset = phase as f64 / KERNEL_PHASES as f64;
+        let mut taps = [0.0; KERNEL_TAPS];
+
+        for (tap, value) in taps.iter_mut().enumerate() {
+            let x = tap as f64 - (half - 1.0) - offset;
+
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                let arg = 2.0 * KERNEL_CUTOFF * PI * x;
+                arg.sin() / arg
+            };
+
+            let position = (x + half) / KERNEL_TAPS as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * position).cos() + 0.08 * (4.0 * PI * position).cos();
+
+            *value = (sinc * window.max(0.0)) as f32;
+        }
+
+        let sum: f32 = taps.iter().sum();
+        taps.iter_mut().for_each(|value| *value /= sum);
+
+        taps
+    }).collect()
+}
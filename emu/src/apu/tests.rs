@@ -0,0 +1,540 @@
+use {
+    super::*,
+    crate::{
+        cpu::*,
+        nes::*,
+    },
+};
+
+fn run(apu: &mut Apu, cycles: u32) {
+    for _ in 0..cycles {
+        apu.tick();
+    }
+}
+
+// One pass of the NTSC 4-step sequence
+const FRAME: u32 = NTSC_FRAME_STEPS[3] + 1;
+
+// Output levels seen over `cycles`, one entry per CPU cycle
+fn pulse_wave(apu: &mut Apu, channel: usize, cycles: u32) -> Vec<Byte> {
+    (0..cycles).map(|_| {
+        apu.tick();
+        apu.pulses[channel].output()
+    }).collect()
+}
+
+// PULSE
+
+#[test]
+fn pulse_duty() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_PULSE1);
+    apu.cpu_write(0x4000, 0x7F);  // 25% duty, halt, constant volume 15
+    apu.cpu_write(0x4002, 0x08);
+    apu.cpu_write(0x4003, 0x08);
+
+    // Each step lasts (period + 1) APU cycles, 18 CPU cycles
+    let wave = pulse_wave(&mut apu, 0, 18 * 8);
+    let high = wave.iter().filter(|&&level| level == 15).count();
+
+    assert_eq!(high, 18 * 2);
+    assert!(wave.iter().all(|&level| level == 0 || level == 15));
+}
+
+#[test]
+fn pulse_length_counter() {
+    let mut apu = Apu::new();
+
+    // Disabled channels ignore length loads
+    apu.cpu_write(0x4003, 0x08);
+    assert_eq!(apu.pulses[0].length.counter(), 0);
+
+    apu.cpu_write(APU_STATUS, STATUS_PULSE1 | STATUS_PULSE2);
+    apu.cpu_write(0x4000, 0x1F);
+    apu.cpu_write(0x4003, 0x00);  // 10
+    apu.cpu_write(0x4004, 0x3F);  // halted
+    apu.cpu_write(0x4007, 0x00);
+
+    // Two half frames per frame
+    run(&mut apu, FRAME * 4);
+    assert_eq!(apu.pulses[0].length.counter(), 2);
+    assert_eq!(apu.pulses[1].length.counter(), 10);
+
+    run(&mut apu, FRAME);
+    assert!(!apu.pulses[0].length.active());
+
+    apu.cpu_write(APU_STATUS, 0);
+    assert!(!apu.pulses[1].length.active());
+}
+
+#[test]
+fn pulse_envelope() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_PULSE1);
+    apu.cpu_write(0x4000, 0x21);  // loop, decay every 2 quarter frames
+    apu.cpu_write(0x4003, 0x00);
+
+    run(&mut apu, NTSC_FRAME_STEPS[0]);
+    assert_eq!(apu.pulses[0].envelope.output(), 15);
+
+    run(&mut apu, FRAME * 7 + NTSC_FRAME_STEPS[2] - NTSC_FRAME_STEPS[0]);
+    assert_eq!(apu.pulses[0].envelope.output(), 0);
+
+    // Looping starts over at 15
+    run(&mut apu, FRAME);
+    assert_eq!(apu.pulses[0].envelope.output(), 14);
+}
+
+#[test]
+fn pulse_sweep_negate() {
+    let mut apu = Apu::new();
+
+    for (base, channel) in [(0x4000, 0), (0x4004, 1)] {
+        apu.cpu_write(base + 1, 0x89);  // enabled, period 0, negate, shift 1
+        apu.cpu_write(base + 2, 0x00);
+        apu.cpu_write(base + 3, 0x01);
+        assert_eq!(apu.pulses[channel].period(), 0x100);
+    }
+
+    // Pulse 1 subtracts one more
+    assert_eq!(apu.pulses[0].sweep_target(), 0x7F);
+    assert_eq!(apu.pulses[1].sweep_target(), 0x80);
+
+    run(&mut apu, NTSC_FRAME_STEPS[1]);
+    assert_eq!(apu.pulses[0].period(), 0x7F);
+    assert_eq!(apu.pulses[1].period(), 0x80);
+}
+
+#[test]
+fn pulse_sweep_muting() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_PULSE1);
+    apu.cpu_write(0x4000, 0xBF);
+    apu.cpu_write(0x4002, 0x07);
+    apu.cpu_write(0x4003, 0x08);
+    assert!(apu.pulses[0].muted());
+
+    // Even a disabled sweep mutes once its target overflows
+    apu.cpu_write(0x4001, 0x00);
+    apu.cpu_write(0x4002, 0x00);
+    apu.cpu_write(0x4003, 0x0C);
+    assert_eq!(apu.pulses[0].period(), 0x400);
+    assert!(apu.pulses[0].muted());
+    assert!(pulse_wave(&mut apu, 0, 1000).iter().all(|&level| level == 0));
+
+    apu.cpu_write(0x4001, 0x01);
+    assert!(!apu.pulses[0].muted());
+}
+
+// TRIANGLE
+
+#[test]
+fn triangle_sequence() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_TRIANGLE);
+    apu.cpu_write(0x4008, 0x81);  // control, linear reload 1
+    apu.cpu_write(0x400A, 0x01);
+    apu.cpu_write(0x400B, 0x08);
+
+    // Nothing moves until the linear counter is loaded on a quarter frame
+    run(&mut apu, NTSC_FRAME_STEPS[0] - 1);
+    assert_eq!(apu.triangle.output(), 15);
+
+    apu.tick();
+    assert_eq!(apu.triangle.linear_counter(), 1);
+
+    // Timer clocked every CPU cycle, a step every (period + 1) cycles and
+    // the idle timer moves on straight away
+    let levels: Vec<Byte> = (0..64).map(|_| {
+        apu.tick();
+        apu.triangle.output()
+    }).step_by(2).collect();
+
+    assert_eq!(&levels[..17], &[14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1]);
+}
+
+#[test]
+fn triangle_linear_counter() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_TRIANGLE);
+    apu.cpu_write(0x4008, 0x02);  // no control, reload 2
+    apu.cpu_write(0x400B, 0x08);
+
+    run(&mut apu, NTSC_FRAME_STEPS[0]);
+    assert_eq!(apu.triangle.linear_counter(), 2);
+
+    run(&mut apu, NTSC_FRAME_STEPS[2] - NTSC_FRAME_STEPS[0]);
+    assert_eq!(apu.triangle.linear_counter(), 0);
+
+    // Silenced, the level holds
+    let level = apu.triangle.output();
+    run(&mut apu, 100);
+    assert_eq!(apu.triangle.output(), level);
+}
+
+// NOISE
+
+fn noise_loop(apu: &mut Apu) -> usize {
+    let start = apu.noise.shift_register();
+    let mut steps = 0;
+
+    // Period 4 CPU cycles, the LFSR moves every other APU cycle
+    loop {
+        run(apu, 4);
+        steps += 1;
+
+        if apu.noise.shift_register() == start {
+            return steps;
+        }
+    }
+}
+
+#[test]
+fn noise_modes() {
+    let mut apu = Apu::new();
+    apu.cpu_write(0x400E, 0x00);
+    run(&mut apu, 1);
+    assert_eq!(noise_loop(&mut apu), 32767);
+
+    apu.cpu_write(0x400E, 0x80);
+    assert_eq!(noise_loop(&mut apu), 93);
+}
+
+#[test]
+fn noise_output() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_NOISE);
+    apu.cpu_write(0x400C, 0x3A);  // halt, constant volume 10
+    apu.cpu_write(0x400E, 0x03);
+    apu.cpu_write(0x400F, 0x08);
+
+    let levels: Vec<Byte> = (0..2000).map(|_| {
+        apu.tick();
+        apu.noise.output()
+    }).collect();
+
+    assert!(levels.contains(&0) && levels.contains(&10));
+    assert!(levels.iter().all(|&level| level == 0 || level == 10));
+}
+
+#[test]
+fn noise_periods_by_region() {
+    assert_eq!(noise_periods(Region::Ntsc)[15], 4068);
+    assert_eq!(noise_periods(Region::Dendy)[15], 4068);
+    assert_eq!(noise_periods(Region::Pal)[15], 3778);
+
+    // The timer reloads with the period of the region the write happened in
+    let mut apu = Apu::new();
+    apu.set_region(Region::Pal);
+    apu.cpu_write(0x400E, 0x02);
+
+    let start = apu.noise.shift_register();
+    run(&mut apu, 1);
+    assert_ne!(apu.noise.shift_register(), start);
+
+    let start = apu.noise.shift_register();
+    run(&mut apu, 13);
+    assert_eq!(apu.noise.shift_register(), start);
+    run(&mut apu, 1);
+    assert_ne!(apu.noise.shift_register(), start);
+}
+
+// DMC
+
+#[test]
+fn dmc_output_unit() {
+    let mut apu = Apu::new();
+    apu.cpu_write(0x4010, 0x0F);  // rate 54
+    apu.cpu_write(0x4011, 0x40);
+    apu.cpu_write(0x4013, 0x00);  // one byte
+    apu.cpu_write(APU_STATUS, STATUS_DMC);
+
+    assert_eq!(apu.dmc.take_fetch(), Some(DMC_SAMPLE_BASE));
+    assert_eq!(apu.dmc.take_fetch(), None);
+    apu.dmc.fill(0b1111_0000);
+    assert!(!apu.dmc.active());
+
+    // Silent until the current 8 bits run out and the buffer is loaded
+    run(&mut apu, 54 * 8);
+    assert_eq!(apu.dmc.output(), 0x40);
+
+    run(&mut apu, 54 * 4);
+    assert_eq!(apu.dmc.output(), 0x40 - 8);
+    run(&mut apu, 54 * 4);
+    assert_eq!(apu.dmc.output(), 0x40);
+
+    // Steps that would leave the 7-bit range are dropped
+    apu.cpu_write(0x4011, 0x7C);
+    apu.cpu_write(APU_STATUS, STATUS_DMC);
+    apu.dmc.take_fetch();
+    apu.dmc.fill(0xFF);
+    run(&mut apu, 54 * 16);
+    assert_eq!(apu.dmc.output(), 0x7E);
+}
+
+#[test]
+fn dmc_sample_address_and_length() {
+    let mut apu = Apu::new();
+    apu.cpu_write(0x4012, 0xFF);
+    apu.cpu_write(0x4013, 0x01);
+    apu.cpu_write(APU_STATUS, STATUS_DMC);
+
+    assert_eq!(apu.dmc.bytes_remaining(), 17);
+    assert_eq!(apu.dmc.take_fetch(), Some(0xFFC0));
+
+    // Disabling drops the rest of the sample
+    apu.cpu_write(APU_STATUS, 0);
+    assert!(!apu.dmc.active());
+}
+
+#[test]
+fn dmc_loop_and_irq() {
+    let mut apu = Apu::new();
+    apu.cpu_write(0x4010, 0x40);
+    apu.cpu_write(APU_STATUS, STATUS_DMC);
+    apu.dmc.take_fetch();
+    apu.dmc.fill(0);
+
+    // Looping samples start over instead of interrupting
+    assert!(apu.dmc.active());
+    assert!(!apu.irq());
+
+    apu.cpu_write(0x4010, 0x80);
+    run(&mut apu, 428 * 8);
+    assert_eq!(apu.dmc.take_fetch(), Some(DMC_SAMPLE_BASE));
+    apu.dmc.fill(0);
+    assert!(apu.irq());
+
+    // Acknowledged through $4015, or by disabling the interrupt
+    apu.cpu_write(APU_STATUS, 0);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn dmc_fetch_stalls_cpu() {
+    let mut cpu = Cpu::default();
+    cpu.mem.write(0xC000, 0xAA);
+    cpu.mem.write(0x4010, 0x80);
+    cpu.mem.write(APU_STATUS, STATUS_DMC);
+
+    cpu.reset_load_rom(&[
+        0xA9, 0x00,  // lda 0x00 (imm)
+    ]);
+    cpu.exec_next().unwrap();
+
+    // 2 cycles for the instruction, 3 or 4 for the DMA
+    let cycles = cpu.mem.cycles();
+    assert!((5..=6).contains(&cycles));
+
+    cpu.mem.tick();
+    assert!(!cpu.mem.apu().dmc.active());
+    assert!(cpu.mem.irq_asserted());
+}
+
+// FRAME COUNTER
+
+#[test]
+fn frame_irq() {
+    let mut apu = Apu::new();
+
+    run(&mut apu, FRAME - 3);
+    assert!(!apu.irq());
+    run(&mut apu, 1);
+    assert!(apu.irq());
+    run(&mut apu, 2);
+
+    // Reading $4015 acknowledges it
+    assert_ne!(apu.cpu_read(APU_STATUS) & STATUS_FRAME_IRQ, 0);
+    assert_eq!(apu.cpu_read(APU_STATUS) & STATUS_FRAME_IRQ, 0);
+    assert!(!apu.irq());
+
+    // Setting the inhibit flag clears it too, and keeps it clear
+    run(&mut apu, FRAME);
+    assert!(apu.irq());
+    apu.cpu_write(APU_FRAME_COUNTER, FRAME_IRQ_INHIBIT);
+    assert!(!apu.irq());
+
+    run(&mut apu, FRAME * 2);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn frame_five_step() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, STATUS_PULSE1);
+    apu.cpu_write(0x4000, 0x10);
+    apu.cpu_write(0x4003, 0x00);  // 10
+
+    // Takes effect a few cycles later, with an immediate half frame
+    apu.cpu_write(APU_FRAME_COUNTER, FRAME_FIVE_STEP);
+    run(&mut apu, 2);
+    assert_eq!(apu.pulses[0].length.counter(), 10);
+    run(&mut apu, 2);
+    assert_eq!(apu.pulses[0].length.counter(), 9);
+    assert!(apu.frame_counter.five_step());
+
+    // Half frames on the second and fifth steps only, and no interrupt
+    run(&mut apu, NTSC_FRAME_STEPS[3]);
+    assert_eq!(apu.pulses[0].length.counter(), 8);
+    run(&mut apu, NTSC_FRAME_STEPS[4] - NTSC_FRAME_STEPS[3]);
+    assert_eq!(apu.pulses[0].length.counter(), 7);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn frame_write_delay() {
+    // 3 cycles when written on an APU cycle, 4 in between
+    for (offset, delay) in [(0, 3), (1, 4)] {
+        let mut apu = Apu::new();
+        apu.cpu_write(APU_STATUS, STATUS_PULSE1);
+        apu.cpu_write(0x4003, 0x00);
+
+        run(&mut apu, offset);
+        apu.cpu_write(APU_FRAME_COUNTER, FRAME_FIVE_STEP);
+
+        run(&mut apu, delay - 1);
+        assert_eq!(apu.pulses[0].length.counter(), 10);
+        run(&mut apu, 1);
+        assert_eq!(apu.pulses[0].length.counter(), 9);
+    }
+}
+
+#[test]
+fn status_register() {
+    let mut apu = Apu::new();
+    apu.cpu_write(APU_STATUS, 0x0F);
+    apu.cpu_write(0x4003, 0x08);
+    apu.cpu_write(0x400B, 0x08);
+    apu.cpu_write(0x4013, 0x01);
+
+    assert_eq!(apu.cpu_peek(APU_STATUS), STATUS_PULSE1 | STATUS_TRIANGLE);
+
+    apu.cpu_write(APU_STATUS, 0x1F);
+    assert_eq!(apu.cpu_peek(APU_STATUS), STATUS_PULSE1 | STATUS_TRIANGLE | STATUS_DMC);
+
+    // The DMC interrupt survives status reads
+    apu.cpu_write(0x4010, 0x80);
+    apu.cpu_write(0x4013, 0x00);
+    apu.cpu_write(APU_STATUS, 0x00);
+    apu.cpu_write(APU_STATUS, STATUS_DMC);
+    apu.dmc.take_fetch();
+    apu.dmc.fill(0);
+
+    assert_eq!(apu.cpu_read(APU_STATUS), STATUS_DMC_IRQ);
+    assert_eq!(apu.cpu_read(APU_STATUS), STATUS_DMC_IRQ);
+}
+
+// OUTPUT
+
+#[test]
+fn mixer_levels() {
+    let mixer = Mixer::new();
+
+    assert_eq!(mixer.mix(0, 0, 0, 0, 0), 0.0);
+    assert!((mixer.mix(15, 15, 0, 0, 0) - 0.2575).abs() < 1e-3);
+    assert!((mixer.mix(0, 0, 15, 15, 127) - 0.7416).abs() < 1e-3);
+
+    // Nonlinear, two channels are quieter than twice one
+    assert!(mixer.mix(15, 15, 0, 0, 0) < 2.0 * mixer.mix(15, 0, 0, 0, 0));
+}
+
+#[test]
+fn output_filters() {
+    let mut high_pass = HighPass::new(HIGH_PASS_CUTOFFS[0], 48_000.0);
+    let mut low_pass = LowPass::new(LOW_PASS_CUTOFF, 48_000.0);
+
+    let mut high = 0.0;
+    let mut low = 0.0;
+    for _ in 0..48_000 {
+        high = high_pass.process(1.0);
+        low = low_pass.process(1.0);
+    }
+
+    // DC is blocked by the high-pass and kept by the low-pass
+    assert!(high.abs() < 1e-3);
+    assert!((low - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn resampler_step() {
+    let mut resampler = Resampler::new(NTSC_CPU_CLOCK as f64, 48_000.0);
+    for _ in 0..NTSC_CPU_CLOCK / 10 {
+        resampler.push(0.5);
+    }
+
+    assert_eq!(resampler.available(), 4799);
+
+    let mut out = vec![0.0; 6000];
+    assert_eq!(resampler.read(&mut out), 4799);
+    assert_eq!(resampler.available(), 0);
+
+    // Rings a little around the edge, which sits half the kernel in
+    assert!(out[..7].iter().all(|sample| sample.abs() < 0.05));
+    assert!((out[7] - 0.5).abs() < 0.05);
+    assert!(out[16..4799].iter().all(|sample| (sample - 0.5).abs() < 1e-4));
+}
+
+#[test]
+fn pulse_tone() {
+    let mut nes = Nes::default();
+    nes.set_sample_rate(44_100);
+
+    // 1789773 / (16 * 254), about 440 Hz
+    nes.cpu.mem.write(APU_STATUS, STATUS_PULSE1);
+    nes.cpu.mem.write(0x4000, 0xBF);
+    nes.cpu.mem.write(0x4002, 0xFD);
+    nes.cpu.mem.write(0x4003, 0x08);
+
+    for _ in 0..NTSC_CPU_CLOCK / 4 {
+        nes.cpu.mem.tick();
+    }
+
+    let mut samples = vec![0.0; 44_100];
+    let count = nes.drain_samples(&mut samples);
+    assert!((11_000..=11_025).contains(&count));
+
+    // Rising edges, with some hysteresis for the ringing around zero
+    let mut high = false;
+    let mut edges = 0;
+    for &sample in &samples[..count] {
+        if !high && sample > 0.05 {
+            high = true;
+            edges += 1;
+        } else if high && sample < -0.05 {
+            high = false;
+        }
+    }
+
+    let expected = 440.4 * count as f64 / 44_100.0;
+    assert!((edges as f64 - expected).abs() <= 2.0, "{edges} edges");
+}
+
+#[test]
+fn undrained_samples_are_capped() {
+    let mut apu = Apu::new();
+    for _ in 0..NTSC_CPU_CLOCK * 2 {
+        apu.tick();
+        apu.mix(0.0);
+    }
+
+    assert!(apu.samples_available() <= DEFAULT_SAMPLE_RATE as usize);
+}
+
+#[test]
+fn apu_on_cpu_bus() {
+    let mut cpu = Cpu::default();
+    cpu.mem.write(APU_STATUS, STATUS_PULSE2);
+    cpu.mem.write(0x4007, 0x08);
+
+    assert_eq!(cpu.mem.apu().pulses[1].length.counter(), 254);
+    assert_eq!(cpu.mem.peek(APU_STATUS), STATUS_PULSE2);
+
+    // The frame interrupt reaches the CPU
+    for _ in 0..FRAME {
+        cpu.mem.tick();
+    }
+    assert!(cpu.mem.irq_asserted());
+    assert_ne!(cpu.mem.read(APU_STATUS) & STATUS_FRAME_IRQ, 0);
+    assert!(!cpu.mem.irq_asserted());
+}
+
@@ -0,0 +1,86 @@
+use {
+    super::*,
+};
+
+pub const TRIANGLE_SEQUENCE: [Byte; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Triangle {
+    // Doubles as the length counter halt flag
+    control: bool,
+
+    linear_reload: bool,
+    linear_period: Byte,
+    linear_counter: Byte,
+
+    step: usize,
+
+    period: Word,
+    timer: Word,
+
+    pub length: LengthCounter,
+}
+
+savestate!(Triangle {
+    control, linear_reload, linear_period, linear_counter, step, period, timer, length,
+});
+
+impl Triangle {
+    pub fn write(&mut self, register: Word, data: Byte) {
+        match register {
+            0 => {
+                self.control = (data & 0x80) != 0;
+                self.linear_period = data & 0x7F;
+                self.length.set_halted(self.control);
+            }
+
+            2 => self.period = (self.period & 0x0700) | data as Word,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((data as Word & 0x07) << 8);
+                self.linear_reload = true;
+                self.length.load(data >> 3);
+            }
+
+            _ => {}
+        }
+    }
+
+    // Clocked every CPU cycle, the sequencer only moves while both
+    // counters are non-zero so a silenced triangle holds its level
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    #[inline(always)]
+    pub fn linear_counter(&self) -> Byte {
+        self.linear_counter
+    }
+
+    #[inline(always)]
+    pub fn output(&self) -> Byte {
+        TRIANGLE_SEQUENCE[self.step]
+    }
+}
@@ -0,0 +1,115 @@
+use {
+    crate::{
+        mem::*,
+        state::*,
+    },
+};
+
+pub const LENGTH_TABLE: [Byte; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Volume unit of the pulse and noise channels, either a constant volume
+// or a sawtooth decaying from 15 once every (volume + 1) quarter frames
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Envelope {
+    start: bool,
+    looped: bool,
+    constant: bool,
+
+    volume: Byte,
+    divider: Byte,
+    decay: Byte,
+}
+
+savestate!(Envelope { start, looped, constant, volume, divider, decay });
+
+// Silences its channel once it counts down to zero on half frames
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+
+    counter: Byte,
+}
+
+savestate!(LengthCounter { enabled, halted, counter });
+
+impl Envelope {
+    // The low six bits of $4000, $4004 and $400C
+    pub fn write(&mut self, data: Byte) {
+        self.looped = (data & 0x20) != 0;
+        self.constant = (data & 0x10) != 0;
+        self.volume = data & 0x0F;
+    }
+
+    #[inline(always)]
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looped {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    #[inline(always)]
+    pub fn output(&self) -> Byte {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+impl LengthCounter {
+    // Loads are ignored while the channel is disabled through $4015
+    pub fn load(&mut self, index: Byte) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    #[inline(always)]
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    pub fn clock(&mut self) {
+        if self.counter > 0 && !self.halted {
+            self.counter -= 1;
+        }
+    }
+
+    #[inline(always)]
+    pub fn counter(&self) -> Byte {
+        self.counter
+    }
+
+    #[inline(always)]
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
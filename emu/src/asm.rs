@@ -0,0 +1,502 @@
+use {
+    crate::{
+        mem::*,
+        opcode::*,
+        error::*,
+        consts::*,
+    },
+
+    std::{
+        collections::HashMap,
+        sync::LazyLock,
+    },
+};
+
+// Opcodes by mnemonic and mode, the reverse of `lookup_instruction`
+static CODES: LazyLock<HashMap<&str, HashMap<AddrMode, Byte>>> = LazyLock::new(|| {
+    let mut codes: HashMap<_, HashMap<_, _>> = HashMap::new();
+
+    for code in 0..=0xFF {
+        if let Some((mnemonic, mode)) = lookup_instruction(code) {
+            codes.entry(mnemonic).or_default().insert(mode, code);
+        }
+    }
+
+    codes
+});
+
+// Assembled code, `bytes` belong at `origin` on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub origin: Word,
+    pub bytes: Vec<Byte>,
+    pub labels: HashMap<String, Word>,
+}
+
+// `source` as lines of
+//
+//     label: mnemonic operand  ; comment
+//     name = expression
+//     .org / .byte / .word expression, ...
+//
+// Mnemonics and directives are case insensitive, labels are not. Code
+// starts at `ROM_ENTRYPOINT` unless an `.org` comes before it, which is
+// where `Cpu::interpret` expects it. Numbers are `$hex`, `%binary` or
+// decimal, with `+ - * /`, parentheses and `*` for the current address.
+// A leading `<` or `>` takes the low or high byte of the whole rest of
+// the expression. Operands known to fit are zero page when the
+// instruction has the mode, forward references are always absolute
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut assembler = Assembler { symbols: HashMap::new(),
+                                    labels: HashMap::new(),
+                                    statements: Vec::new(),
+                                    origin: None,
+                                    pc: ROM_ENTRYPOINT as u32 };
+
+    for (index, line) in source.lines().enumerate() {
+        assembler.layout(index + 1, line)?;
+    }
+
+    assembler.emit()
+}
+
+// Assembles a test program, one line per argument, and panics with the
+// line on an error
+#[allow(unused_macros)]
+macro_rules! asm {
+    ($($line:expr),* $(,)?) => {{
+        let source = [$($line),*].join("\n");
+        match $crate::asm::assemble(&source) {
+            Ok(program) => program.bytes,
+            Err(error) => panic!("{error:?} in:\n{source}"),
+        }
+    }};
+}
+
+#[allow(unused_imports)]
+pub(crate) use asm;
+
+#[derive(Debug, Clone, Copy)]
+enum Data {
+    Instruction(Byte, AddrMode),
+    Bytes,
+    Words,
+}
+
+// A line that produces bytes, placed by the first pass and filled in by
+// the second once every label is known
+#[derive(Debug, Clone)]
+struct Statement {
+    line: usize,
+    addr: u32,
+    data: Data,
+    operands: Vec<String>,
+}
+
+struct Assembler<'a> {
+    // Labels and constants
+    symbols: HashMap<&'a str, i32>,
+    labels: HashMap<String, Word>,
+
+    statements: Vec<Statement>,
+    origin: Option<Word>,
+    pc: u32,
+}
+
+impl<'a> Assembler<'a> {
+    // First pass, defines labels and works out how big everything is
+    fn layout(&mut self, line: usize, text: &'a str) -> Result<(), AsmError> {
+        let mut text = text.split(';').next().unwrap().trim();
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                break;
+            }
+
+            self.define(line, label, self.pc as i32)?;
+            self.labels.insert(label.to_string(), self.pc as Word);
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        if let Some((name, value)) = text.split_once('=') {
+            let name = name.trim();
+            if !is_identifier(name) {
+                return Err(AsmError::Syntax(line));
+            }
+
+            let value = self.eval(line, value)?.ok_or(AsmError::UndefinedSymbol(line))?;
+            return self.define(line, name, value);
+        }
+
+        let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+
+        let (data, operands) = match word.to_ascii_lowercase().as_str() {
+            ".org" => return self.org(line, rest),
+            ".byte" => (Data::Bytes, split_operands(line, rest)?),
+            ".word" => (Data::Words, split_operands(line, rest)?),
+
+            _ if word.starts_with('.') => return Err(AsmError::Syntax(line)),
+            _ => {
+                let (code, mode, operand) = self.instruction(line, word, rest)?;
+                (Data::Instruction(code, mode), operand.into_iter().collect())
+            }
+        };
+
+        let size = match data {
+            Data::Instruction(_, mode) => 1 + mode.operand_size() as u32,
+            Data::Bytes => operands.len() as u32,
+            Data::Words => 2 * operands.len() as u32,
+        };
+
+        self.origin.get_or_insert(self.pc as Word);
+        self.statements.push(Statement { line, addr: self.pc, data, operands });
+
+        self.pc += size;
+        if self.pc > 0x10000 {
+            return Err(AsmError::OutOfRange(line));
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, line: usize, name: &'a str, value: i32) -> Result<(), AsmError> {
+        match self.symbols.insert(name, value) {
+            Some(_) => Err(AsmError::DuplicateSymbol(line)),
+            None => Ok(()),
+        }
+    }
+
+    fn org(&mut self, line: usize, operand: &str) -> Result<(), AsmError> {
+        let addr = self.eval(line, operand)?.ok_or(AsmError::UndefinedSymbol(line))?;
+
+        if !(0..=0xFFFF).contains(&addr) {
+            return Err(AsmError::OutOfRange(line));
+        }
+        if self.origin.is_some() && (addr as u32) < self.pc {
+            return Err(AsmError::Org(line));
+        }
+
+        self.pc = addr as u32;
+        Ok(())
+    }
+
+    // Picks the opcode from the operand's syntax, returning the
+    // expression left once the mode's decoration is stripped
+    fn instruction(&self, line: usize, mnemonic: &str, operand: &str)
+        -> Result<(Byte, AddrMode, Option<String>), AsmError>
+    {
+        let codes = CODES.get(mnemonic.to_ascii_uppercase().as_str())
+            .ok_or(AsmError::UnknownInstruction(line))?;
+
+        let has = |mode| codes.contains_key(&mode);
+
+        let operand: String = operand.chars().filter(|char| !char.is_whitespace()).collect();
+        let upper = operand.to_ascii_uppercase();
+
+        let (modes, expr): (&[AddrMode], _) = if operand.is_empty() {
+            match has(AddrMode::Implied) {
+                true => (&[AddrMode::Implied], None),
+                false => (&[AddrMode::Accumulator], None),
+            }
+        } else if upper == "A" && has(AddrMode::Accumulator) {
+            (&[AddrMode::Accumulator], None)
+        } else if let Some(expr) = operand.strip_prefix('#') {
+            (&[AddrMode::Immediate], Some(expr))
+        } else if let Some(inner) = upper.strip_suffix(",Y").and_then(parenthesized) {
+            (&[AddrMode::IndirectY], Some(&operand[1..=inner.len()]))
+        } else if let Some(inner) = parenthesized(&upper).filter(|_| has(AddrMode::Indirect) || upper.ends_with(",X)")) {
+            match inner.strip_suffix(",X") {
+                Some(expr) => (&[AddrMode::IndirectX], Some(&operand[1..=expr.len()])),
+                None => (&[AddrMode::Indirect], Some(&operand[1..=inner.len()])),
+            }
+        } else if let Some(expr) = upper.strip_suffix(",X") {
+            (&[AddrMode::ZeroPageX, AddrMode::AbsoluteX], Some(&operand[..expr.len()]))
+        } else if let Some(expr) = upper.strip_suffix(",Y") {
+            (&[AddrMode::ZeroPageY, AddrMode::AbsoluteY], Some(&operand[..expr.len()]))
+        } else if has(AddrMode::Relative) {
+            (&[AddrMode::Relative], Some(operand.as_str()))
+        } else {
+            (&[AddrMode::ZeroPage, AddrMode::Absolute], Some(operand.as_str()))
+        };
+
+        let mode = match (modes, expr) {
+            // Zero page only when it's known to fit now, the second pass
+            // has to come out the same size
+            (&[short, long], Some(expr)) => {
+                let fits = self.eval(line, expr)?.is_some_and(|value| (0..0x100).contains(&value));
+
+                match (fits && has(short)) || !has(long) {
+                    true => short,
+                    false => long,
+                }
+            }
+
+            _ => modes[0],
+        };
+
+        match codes.get(&mode) {
+            Some(&code) => Ok((code, mode, expr.map(str::to_string))),
+            None => Err(AsmError::InvalidMode(line)),
+        }
+    }
+
+    // Second pass, every symbol has to be known by now
+    fn emit(mut self) -> Result<Program, AsmError> {
+        let origin = self.origin.unwrap_or(ROM_ENTRYPOINT);
+        let mut bytes = vec![0; (self.pc - origin as u32) as usize];
+
+        for statement in std::mem::take(&mut self.statements) {
+            let line = statement.line;
+            self.pc = statement.addr;
+
+            let mut out = Vec::new();
+            let mut values = Vec::new();
+            for operand in &statement.operands {
+                values.push(self.eval(line, operand)?.ok_or(AsmError::UndefinedSymbol(line))?);
+            }
+
+            match statement.data {
+                Data::Instruction(code, mode) => {
+                    out.push(code);
+
+                    match (mode, values.first()) {
+                        (AddrMode::Relative, Some(&target)) => {
+                            let offset = target - (statement.addr as i32 + 2);
+                            if !(-0x80..0x80).contains(&offset) {
+                                return Err(AsmError::OutOfRange(line));
+                            }
+
+                            out.push(offset as Byte);
+                        }
+                        (_, Some(&value)) if mode.operand_size() == 1 => out.push(to_byte(line, value)?),
+                        (_, Some(&value)) => out.extend(to_word(line, value)?.to_le_bytes()),
+
+                        (_, None) => (),
+                    }
+                }
+
+                Data::Bytes => for value in values {
+                    out.push(to_byte(line, value)?);
+                },
+                Data::Words => for value in values {
+                    out.extend(to_word(line, value)?.to_le_bytes());
+                },
+            }
+
+            let start = (statement.addr - origin as u32) as usize;
+            bytes[start..start + out.len()].copy_from_slice(&out);
+        }
+
+        Ok(Program { origin, bytes, labels: self.labels })
+    }
+
+    // `None` for a symbol that isn't defined yet
+    fn eval(&self, line: usize, expr: &str) -> Result<Option<i32>, AsmError> {
+        let mut parser = Parser { text: expr.trim().as_bytes(),
+                                  pos: 0,
+                                  line,
+                                  symbols: &self.symbols,
+                                  pc: self.pc as i32 };
+
+        let value = parser.expr()?;
+        parser.skip_spaces();
+
+        if parser.pos != parser.text.len() {
+            return Err(AsmError::Syntax(line));
+        }
+
+        Ok(value)
+    }
+}
+
+struct Parser<'a, 'b> {
+    text: &'b [u8],
+    pos: usize,
+    line: usize,
+
+    symbols: &'b HashMap<&'a str, i32>,
+    pc: i32,
+}
+
+impl Parser<'_, '_> {
+    fn skip_spaces(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_spaces();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Result<Option<i32>, AsmError> {
+        match self.peek() {
+            Some(b'<') => {
+                self.pos += 1;
+                Ok(self.expr()?.map(|value| value & 0xFF))
+            }
+            Some(b'>') => {
+                self.pos += 1;
+                Ok(self.expr()?.map(|value| (value >> 8) & 0xFF))
+            }
+
+            _ => self.sum(),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Option<i32>, AsmError> {
+        let mut value = self.product()?;
+
+        while let Some(op @ (b'+' | b'-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.product()?;
+
+            value = value.zip(rhs).map(|(lhs, rhs)| match op {
+                b'+' => lhs.wrapping_add(rhs),
+                _ => lhs.wrapping_sub(rhs),
+            });
+        }
+
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<Option<i32>, AsmError> {
+        let mut value = self.unary()?;
+
+        while let Some(op @ (b'*' | b'/')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+
+            if op == b'/' && rhs == Some(0) {
+                return Err(AsmError::OutOfRange(self.line));
+            }
+
+            value = value.zip(rhs).map(|(lhs, rhs)| match op {
+                b'*' => lhs.wrapping_mul(rhs),
+                _ => lhs / rhs,
+            });
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Option<i32>, AsmError> {
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+            return Ok(self.unary()?.map(i32::wrapping_neg));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Option<i32>, AsmError> {
+        let syntax = AsmError::Syntax(self.line);
+
+        match self.peek().ok_or(syntax)? {
+            b'(' => {
+                self.pos += 1;
+                let value = self.expr()?;
+
+                if self.peek() != Some(b')') {
+                    return Err(syntax);
+                }
+                self.pos += 1;
+
+                Ok(value)
+            }
+            b'*' => {
+                self.pos += 1;
+                Ok(Some(self.pc))
+            }
+            b'$' => {
+                self.pos += 1;
+                self.number(16).map(Some)
+            }
+            b'%' => {
+                self.pos += 1;
+                self.number(2).map(Some)
+            }
+            b'0'..=b'9' => self.number(10).map(Some),
+
+            _ => {
+                let start = self.pos;
+                while self.text.get(self.pos).is_some_and(|&byte| byte.is_ascii_alphanumeric() || byte == b'_') {
+                    self.pos += 1;
+                }
+
+                let name = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+                if !is_identifier(name) {
+                    return Err(syntax);
+                }
+
+                Ok(self.symbols.get(name).copied())
+            }
+        }
+    }
+
+    fn number(&mut self, radix: u32) -> Result<i32, AsmError> {
+        let start = self.pos;
+        while self.text.get(self.pos).is_some_and(|&byte| (byte as char).is_digit(radix)) {
+            self.pos += 1;
+        }
+
+        let digits = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        i32::from_str_radix(digits, radix).map_err(|_| AsmError::Syntax(self.line))
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_') &&
+        chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+// `(...)` where the parentheses enclose all of it, not `(1+2)*3`
+fn parenthesized(text: &str) -> Option<&str> {
+    let inner = text.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+
+    for char in inner.chars() {
+        match char {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+
+            _ => (),
+        }
+    }
+
+    Some(inner)
+}
+
+fn split_operands(line: usize, text: &str) -> Result<Vec<String>, AsmError> {
+    let operands: Vec<String> = text.split(',').map(|operand| operand.trim().to_string()).collect();
+
+    match operands.iter().any(|operand| operand.is_empty()) {
+        true => Err(AsmError::Syntax(line)),
+        false => Ok(operands),
+    }
+}
+
+// Negative values down to -128 are taken as two's complement
+fn to_byte(line: usize, value: i32) -> Result<Byte, AsmError> {
+    match value {
+        -0x80..=0xFF => Ok(value as Byte),
+        _ => Err(AsmError::OutOfRange(line)),
+    }
+}
+
+fn to_word(line: usize, value: i32) -> Result<Word, AsmError> {
+    match value {
+        -0x8000..=0xFFFF => Ok(value as Word),
+        _ => Err(AsmError::OutOfRange(line)),
+    }
+}
@@ -0,0 +1,518 @@
+use {
+    crate::{
+        mem::*,
+        error::*,
+        mapper::{
+            self,
+            Mapper,
+        },
+        state::*,
+    },
+
+    std::{
+        fs,
+        io,
+        path::{
+            Path,
+            PathBuf,
+        },
+    }
+};
+
+pub const INES_MAGIC: [u8; 4]   = *b"NES\x1A";
+pub const INES_HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize     = 512;
+
+pub const PRG_ROM_UNIT: usize = 0x4000;
+pub const CHR_ROM_UNIT: usize = 0x2000;
+
+pub const SAVE_EXTENSION: &str = "sav";
+
+pub const NAMETABLE_PAGE_SIZE: usize   = 0x0400;
+pub const FOUR_SCREEN_VRAM_SIZE: usize = 0x0800;
+
+// Which 1K page backs each of the four nametable slots. Pages 0 and 1 are
+// the console's CIRAM, pages 2 and 3 the extra VRAM of four-screen boards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+
+    SingleScreenLower,
+    SingleScreenUpper,
+
+    FourScreen,
+
+    // Set by the mapper for each slot, $2000, $2400, $2800 and $2C00
+    Mapped([Byte; 4]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomFormat {
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RomHeader {
+    pub format: RomFormat,
+
+    pub mapper: u16,
+    pub submapper: u8,
+
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+
+    pub mirroring: Mirroring,
+
+    pub battery: bool,
+    pub trainer: bool,
+}
+
+#[derive(Clone)]
+pub struct Rom {
+    pub header: RomHeader,
+    pub trainer: Option<Vec<Byte>>,
+
+    pub prg: Vec<Byte>,
+    pub chr: Vec<Byte>,
+}
+
+pub struct Cartridge {
+    pub header: RomHeader,
+    // Identifies the ROM in save states
+    pub rom_hash: u64,
+    mapper: Box<dyn Mapper>,
+
+    // What the mapper was built from, for `power`
+    rom: Rom,
+
+    // Four-screen boards bring their own 2K for nametables 2 and 3
+    vram: Box<[Byte]>,
+
+    // Battery RAM as last read from or written to `save_path`
+    save_path: Option<PathBuf>,
+    saved: Vec<Byte>,
+}
+
+impl Savestate for Mirroring {
+    fn save(&self, state: &mut StateWriter) {
+        let (tag, pages) = match *self {
+            Mirroring::Horizontal => (0, [0; 4]),
+            Mirroring::Vertical => (1, [0; 4]),
+            Mirroring::SingleScreenLower => (2, [0; 4]),
+            Mirroring::SingleScreenUpper => (3, [0; 4]),
+            Mirroring::FourScreen => (4, [0; 4]),
+            Mirroring::Mapped(pages) => (5, pages),
+        };
+
+        (tag as Byte).save(state);
+        pages.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let [tag] = state.read_array()?;
+        let pages = state.read_array()?;
+
+        *self = match tag {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::SingleScreenLower,
+            3 => Mirroring::SingleScreenUpper,
+            4 => Mirroring::FourScreen,
+            5 => Mirroring::Mapped(pages),
+
+            _ => return Err(StateError::Corrupted),
+        };
+
+        Ok(())
+    }
+}
+
+impl Mirroring {
+    #[inline(always)]
+    pub fn page(self, slot: usize) -> usize {
+        match self {
+            Mirroring::Horizontal => (slot >> 1) & 0x01,
+            Mirroring::Vertical => slot & 0x01,
+
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+
+            Mirroring::FourScreen => slot & 0x03,
+            Mirroring::Mapped(pages) => (pages[slot & 0x03] & 0x03) as usize,
+        }
+    }
+}
+
+impl RomHeader {
+    pub fn parse(raw: &[u8]) -> Result<Self, RomError> {
+        if raw.len() < INES_HEADER_SIZE {
+            return Err(RomError::Truncated);
+        }
+
+        if raw[0..4] != INES_MAGIC {
+            return Err(RomError::InvalidMagic);
+        }
+
+        let flags6 = raw[6];
+        let flags7 = raw[7];
+
+        let format = if (flags7 & 0x0C) == 0x08 {
+            RomFormat::Nes2
+        } else {
+            RomFormat::INes
+        };
+
+        let mirroring = if (flags6 & 0x08) != 0 {
+            Mirroring::FourScreen
+        } else if (flags6 & 0x01) != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let battery = (flags6 & 0x02) != 0;
+        let trainer = (flags6 & 0x04) != 0;
+
+        let header = match format {
+            RomFormat::Nes2 => {
+                let mapper = (flags6 >> 4) as u16
+                           | (flags7 & 0xF0) as u16
+                           | ((raw[8] & 0x0F) as u16) << 8;
+
+                Self {
+                    format,
+                    mapper,
+                    submapper: raw[8] >> 4,
+
+                    prg_rom_size: nes2_rom_size(raw[4], raw[9] & 0x0F, PRG_ROM_UNIT)?,
+                    chr_rom_size: nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_UNIT)?,
+
+                    prg_ram_size: nes2_ram_size(raw[10] & 0x0F),
+                    prg_nvram_size: nes2_ram_size(raw[10] >> 4),
+
+                    chr_ram_size: nes2_ram_size(raw[11] & 0x0F),
+                    chr_nvram_size: nes2_ram_size(raw[11] >> 4),
+
+                    mirroring,
+                    battery,
+                    trainer,
+                }
+            }
+
+            RomFormat::INes => {
+                // Old dumpers used to scribble their name over bytes 7..15,
+                // in that case the upper mapper nibble is garbage as well
+                let dirty = raw[12..16].iter().any(|&byte| byte != 0);
+                let mapper = if dirty {
+                    (flags6 >> 4) as u16
+                } else {
+                    (flags6 >> 4) as u16 | (flags7 & 0xF0) as u16
+                };
+
+                let prg_ram_size = (raw[8].max(1) as usize) * 0x2000;
+                let (prg_ram_size, prg_nvram_size) = if battery {
+                    (0, prg_ram_size)
+                } else {
+                    (prg_ram_size, 0)
+                };
+
+                Self {
+                    format,
+                    mapper,
+                    submapper: 0,
+
+                    prg_rom_size: raw[4] as usize * PRG_ROM_UNIT,
+                    chr_rom_size: raw[5] as usize * CHR_ROM_UNIT,
+
+                    prg_ram_size,
+                    prg_nvram_size,
+
+                    chr_ram_size: if raw[5] == 0 { CHR_ROM_UNIT } else { 0 },
+                    chr_nvram_size: 0,
+
+                    mirroring,
+                    battery,
+                    trainer,
+                }
+            }
+        };
+
+        // Every mapper reads whole 16K banks, from the last one at least
+        if header.prg_rom_size == 0 || header.prg_rom_size % PRG_ROM_UNIT != 0 {
+            return Err(RomError::UnsupportedPrgSize(header.prg_rom_size));
+        }
+
+        Ok(header)
+    }
+}
+
+impl Rom {
+    pub fn parse(raw: &[u8]) -> Result<Self, RomError> {
+        let header = RomHeader::parse(raw)?;
+        let mut cursor = INES_HEADER_SIZE;
+
+        let trainer = if header.trainer {
+            let trainer = take(raw, &mut cursor, TRAINER_SIZE)?;
+            Some(trainer.to_vec())
+        } else {
+            None
+        };
+
+        let prg = take(raw, &mut cursor, header.prg_rom_size)?.to_vec();
+        let chr = take(raw, &mut cursor, header.chr_rom_size)?.to_vec();
+
+        Ok(Self { header, trainer,
+                  prg, chr })
+    }
+}
+
+impl Savestate for Cartridge {
+    fn save(&self, state: &mut StateWriter) {
+        self.mapper.save(state);
+        self.vram.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.mapper.load(state)?;
+        self.vram.load(state)
+    }
+}
+
+impl Cartridge {
+    pub fn from_rom(rom: Rom) -> Result<Self, RomError> {
+        let header = rom.header;
+        let rom_hash = fnv1a(fnv1a(FNV_OFFSET, &rom.prg), &rom.chr);
+        let mapper = mapper::create(rom.clone())?;
+
+        let vram = if header.mirroring == Mirroring::FourScreen {
+            vec![0; FOUR_SCREEN_VRAM_SIZE].into_boxed_slice()
+        } else {
+            Box::default()
+        };
+
+        Ok(Self { header, rom_hash,
+                  mapper, rom, vram,
+                  save_path: None,
+                  saved: Vec::new() })
+    }
+
+    // Power cycle. The board comes back as it was built, registers,
+    // CHR-RAM, PRG-RAM and extra VRAM cleared, only what the battery
+    // keeps survives
+    pub fn power(&mut self) {
+        let battery = self.header.battery.then(|| self.battery_ram());
+
+        self.mapper = mapper::create(self.rom.clone()).expect("mapper was built from this ROM before");
+        self.vram.fill(0);
+
+        if let Some(data) = battery {
+            self.set_battery_ram(&data);
+        }
+    }
+
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, RomError> {
+        Self::from_rom(Rom::parse(raw)?)
+    }
+
+    // Battery-backed carts pick up `<rom>.sav` from next to the ROM
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
+        let path = path.as_ref();
+        let raw = fs::read(path)?;
+
+        let mut cartridge = Self::from_bytes(&raw)?;
+        if cartridge.header.battery {
+            cartridge.set_save_path(path.with_extension(SAVE_EXTENSION))?;
+        }
+
+        Ok(cartridge)
+    }
+
+    // Points persistence at `path` and loads whatever is already saved there,
+    // a missing file just means the game was never saved
+    pub fn set_save_path<P: Into<PathBuf>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.into();
+
+        match fs::read(&path) {
+            Ok(saved) => self.set_battery_ram(&saved),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        self.save_path = Some(path);
+        self.saved = self.battery_ram();
+        Ok(())
+    }
+
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    // Non-volatile CHR-RAM follows PRG-RAM in the saved image
+    pub fn battery_ram(&self) -> Vec<Byte> {
+        let mut data = self.mapper.prg_ram().as_slice().to_vec();
+        if self.header.chr_nvram_size > 0 {
+            data.extend_from_slice(self.mapper.chr().ram());
+        }
+
+        data
+    }
+
+    // Copies as much as fits, a short image leaves the rest untouched
+    pub fn set_battery_ram(&mut self, data: &[Byte]) {
+        let prg_ram = self.mapper.prg_ram_mut().as_mut_slice();
+        let split = prg_ram.len().min(data.len());
+        prg_ram[..split].copy_from_slice(&data[..split]);
+
+        if self.header.chr_nvram_size > 0 {
+            let rest = &data[split..];
+            let chr_ram = self.mapper.chr_mut().ram_mut();
+            let length = chr_ram.len().min(rest.len());
+            chr_ram[..length].copy_from_slice(&rest[..length]);
+        }
+    }
+
+    // Nothing is saved on its own, the frontend calls this when it's done
+    // with the cartridge. Skips the write when the RAM hasn't changed
+    pub fn flush(&mut self) -> io::Result<()> {
+        let Some(path) = &self.save_path else {
+            return Ok(());
+        };
+
+        let ram = self.battery_ram();
+        if !self.header.battery || ram == self.saved {
+            return Ok(());
+        }
+
+        fs::write(path, &ram)?;
+        self.saved = ram;
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn cpu_read(&mut self, addr: Word) -> Byte {
+        self.mapper.cpu_read(addr)
+    }
+
+    #[inline(always)]
+    pub fn cpu_peek(&self, addr: Word) -> Byte {
+        self.mapper.cpu_peek(addr)
+    }
+
+    #[inline(always)]
+    pub fn cpu_write(&mut self, addr: Word, data: Byte) {
+        self.mapper.cpu_write(addr, data);
+    }
+
+    #[inline(always)]
+    pub fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.mapper.ppu_read(addr)
+    }
+
+    #[inline(always)]
+    pub fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.mapper.ppu_write(addr, data);
+    }
+
+    #[inline(always)]
+    pub fn snoop_write(&mut self, addr: Word, data: Byte) {
+        self.mapper.snoop_write(addr, data);
+    }
+
+    // `None` leaves the access to CIRAM in the PPU
+    pub fn nametable_read(&mut self, addr: Word) -> Option<Byte> {
+        self.mapper.nametable_read(addr)
+            .or_else(|| self.vram_offset(addr).map(|offset| self.vram[offset]))
+    }
+
+    pub fn nametable_write(&mut self, addr: Word, data: Byte) -> bool {
+        if self.mapper.nametable_write(addr, data) {
+            return true;
+        }
+
+        match self.vram_offset(addr) {
+            Some(offset) => {
+                self.vram[offset] = data;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Pages 2 and 3 fall back to CIRAM on boards without extra VRAM
+    fn vram_offset(&self, addr: Word) -> Option<usize> {
+        let slot = ((addr >> 10) & 0x03) as usize;
+        let page = self.mirroring().page(slot).checked_sub(2)?;
+
+        if self.vram.is_empty() {
+            return None;
+        }
+
+        Some(page * NAMETABLE_PAGE_SIZE + (addr as usize & (NAMETABLE_PAGE_SIZE - 1)))
+    }
+
+    #[inline(always)]
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    #[inline(always)]
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    #[inline(always)]
+    pub fn cpu_tick(&mut self) {
+        self.mapper.cpu_tick();
+    }
+
+    #[inline(always)]
+    pub fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+}
+
+fn take<'a>(
+    raw: &'a [u8],
+    cursor: &mut usize,
+    length: usize,
+) -> Result<&'a [u8], RomError> {
+    let begin = *cursor;
+    let end = begin.checked_add(length)
+                   .filter(|&end| end <= raw.len())
+                   .ok_or(RomError::Truncated)?;
+
+    *cursor = end;
+    Ok(&raw[begin..end])
+}
+
+// A size too big to even count can't be in the file either
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> Result<usize, RomError> {
+    let size = if msb == 0x0F {
+        // Exponent-multiplier notation: 2^E * (MM * 2 + 1)
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+
+        1usize.checked_shl(exponent).and_then(|size| size.checked_mul(multiplier))
+    } else {
+        (((msb as usize) << 8) | lsb as usize).checked_mul(unit)
+    };
+
+    size.ok_or(RomError::Truncated)
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
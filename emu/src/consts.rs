@@ -1,4 +1,13 @@
 use crate::mem::*;
 
 pub const ROM_ENTRYPOINT: Word = 0x8000;
+pub const NMI_VECTOR: Word     = 0xFFFA;
+pub const RESET_VECTOR: Word   = 0xFFFC;
+pub const IRQ_VECTOR: Word     = 0xFFFE;
+
+pub const STACK_BASE: Word     = 0x0100;
+pub const STACK_RESET: Byte    = 0xFD;
+
 pub const CARRY_MASK: Word     = 1 << 8;
+
+pub const INTERRUPT_CYCLES: u8 = 7;
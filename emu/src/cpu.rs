@@ -5,6 +5,8 @@ use {
         status::*,
         opcode::*,
         error::*,
+        cartridge::*,
+        state::*,
     },
 
     std::{
@@ -15,6 +17,7 @@ use {
 
 pub struct Cpu {
     pub pc: Word,
+    pub sp: Byte,
 
     pub x: Byte,
     pub y: Byte,
@@ -22,9 +25,14 @@ pub struct Cpu {
     pub acc: Byte,
 
     pub mem: Memory,
-    pub status: CpuStatus
+    pub status: CpuStatus,
+
+    // Page crossing and branch penalties of the current instruction
+    extra_cycles: u8,
 }
 
+savestate!(Cpu { pc, sp, x, y, acc, status, extra_cycles, mem });
+
 impl Cpu {
     pub fn inx(
         &mut self,
@@ -33,6 +41,13 @@ impl Cpu {
         self.chk_zero_neg_b(self.x);
     }
 
+    pub fn rti(
+        &mut self,
+    ) {
+        self.status = CpuStatus::from_stack(self.pop());
+        self.pc = self.pop_word();
+    }
+
     pub fn tax(
         &mut self,
     ) {
@@ -156,7 +171,7 @@ impl Cpu {
         relative_addr: Signed,
     ) {
         if self.status.fetch(CpuStatus::CARRY) {
-            self.add_pc_signed(relative_addr as SignedWord);
+            self.branch(relative_addr);
         }
     }
 
@@ -165,7 +180,7 @@ impl Cpu {
         relative_addr: Signed
     ) {
         if !self.status.fetch(CpuStatus::CARRY) {
-            self.add_pc_signed(relative_addr as SignedWord);
+            self.branch(relative_addr);
         }
     }
 
@@ -174,12 +189,36 @@ impl Cpu {
         relative_addr: Signed
     ) {
         if self.status.fetch(CpuStatus::ZERO) {
-            self.add_pc_signed(relative_addr as SignedWord);
+            self.branch(relative_addr);
         }
     }
 }
 
 impl Cpu {
+    // Taken branches cost a cycle, two when they land on another page
+    #[inline(always)]
+    pub fn branch(&mut self, relative_addr: Signed) {
+        let from = self.pc;
+        self.add_pc_signed(relative_addr as SignedWord);
+
+        self.extra_cycles += 1 + ((from & 0xFF00) != (self.pc & 0xFF00)) as u8;
+    }
+
+    // Indexed reads take a cycle longer when the index crosses a page
+    #[inline(always)]
+    pub fn page_penalty(&mut self, mode: AddrMode) {
+        let (base, index) = match mode {
+            AddrMode::AbsoluteX => (self.mem.read_word(self.pc), self.x),
+            AddrMode::AbsoluteY => (self.mem.read_word(self.pc), self.y),
+            AddrMode::IndirectY => (self.mem.read_word(self.mem.peek(self.pc) as Word), self.y),
+
+            _ => return,
+        };
+
+        let target = base.wrapping_add(index as Word);
+        self.extra_cycles += ((base & 0xFF00) != (target & 0xFF00)) as u8;
+    }
+
     #[inline]
     pub fn exec(
         &mut self,
@@ -205,17 +244,23 @@ impl Cpu {
             Opcode::Tax => self.tax(),
             Opcode::Tay => self.tay(),
 
+            Opcode::Cli => self.status.set_off(CpuStatus::INTERRUPT),
+            Opcode::Sei => self.status.set_on(CpuStatus::INTERRUPT),
+            Opcode::Rti => self.rti(),
+
             Opcode::Asl(mode, length) => {
                 self.asl(mode);
                 self.add_pc(length);
             }
 
             Opcode::And(mode, length) => {
+                self.page_penalty(mode);
                 self.and(mode);
                 self.add_pc(length);
             }
 
             Opcode::Adc(mode, length) => {
+                self.page_penalty(mode);
                 self.adc(mode);
                 self.add_pc(length);
             }
@@ -239,6 +284,7 @@ impl Cpu {
             }
 
             Opcode::Lda(mode, length) => {
+                self.page_penalty(mode);
                 self.lda(mode);
                 self.add_pc(length);
             }
@@ -267,8 +313,25 @@ impl Cpu {
 
     #[inline(always)]
     pub fn exec_next(&mut self) -> Result<ExecStatus, ExecError> {
+        self.poll_interrupts();
+
+        let code = self.mem.peek(self.pc);
         let opcode = self.next();
-        self.exec(opcode)
+
+        self.extra_cycles = 0;
+        let status = self.exec(opcode);
+
+        self.clock(lookup_cycles(code) + self.extra_cycles);
+        self.run_dma();
+
+        status
+    }
+
+    #[inline(always)]
+    pub fn clock(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.mem.tick();
+        }
     }
 
     #[inline(always)]
@@ -312,11 +375,22 @@ impl Cpu {
         self.pc = ROM_ENTRYPOINT;
     }
 
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+        self.mem.insert_cartridge(cartridge);
+        self.reset();
+
+        self.pc = self.mem.read_word(RESET_VECTOR);
+    }
+
+    // Like the reset sequence, IRQs stay masked until the program
+    // clears I itself
     pub fn reset(&mut self) {
         self.pc = 0;
+        self.sp = STACK_RESET;
         self.x = 0;
         self.y = 0;
         self.acc = 0;
+        self.status.set_on(CpuStatus::INTERRUPT);
     }
 
     pub fn new(
@@ -332,7 +406,72 @@ impl Cpu {
 
         Self { x, y,
                mem, status,
-               pc, acc, }
+               pc, acc,
+               sp: STACK_RESET,
+               extra_cycles: 0 }
+    }
+}
+
+impl Cpu {
+    pub fn nmi(&mut self) {
+        self.interrupt(Interrupt::Nmi);
+    }
+
+    pub fn irq(&mut self) -> bool {
+        if self.status.fetch(CpuStatus::INTERRUPT) {
+            return false;
+        }
+
+        self.interrupt(Interrupt::Irq);
+        true
+    }
+
+    pub fn interrupt(&mut self, kind: Interrupt) {
+        self.push_word(self.pc);
+        self.push(self.status.to_stack(false));
+        self.status.set_on(CpuStatus::INTERRUPT);
+
+        self.pc = self.mem.read_word(match kind {
+            Interrupt::Nmi => NMI_VECTOR,
+            Interrupt::Irq => IRQ_VECTOR,
+        });
+
+        self.clock(INTERRUPT_CYCLES);
+    }
+
+    #[inline(always)]
+    pub fn poll_interrupts(&mut self) {
+        if self.mem.take_nmi() {
+            self.nmi();
+        } else if self.mem.irq_asserted() {
+            self.irq();
+        }
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, data: Byte) {
+        self.mem.write(STACK_BASE | self.sp as Word, data);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    #[inline(always)]
+    pub fn push_word(&mut self, data: Word) {
+        self.push((data >> 8) as Byte);
+        self.push((data & 0xff) as Byte);
+    }
+
+    #[inline(always)]
+    pub fn pop(&mut self) -> Byte {
+        self.sp = self.sp.wrapping_add(1);
+        self.mem.read(STACK_BASE | self.sp as Word)
+    }
+
+    #[inline(always)]
+    pub fn pop_word(&mut self) -> Word {
+        let low = self.pop() as Word;
+        let high = self.pop() as Word;
+
+        (high << 8) | low
     }
 }
 
@@ -460,13 +599,13 @@ impl Cpu {
         mode: AddrMode
     ) -> Word {
         match mode {
-            AddrMode::Accumulator => unreachable!(),
-            AddrMode::Relative => self.mem.read(self.pc) as Word,
+            AddrMode::Accumulator | AddrMode::Implied => unreachable!(),
+            AddrMode::Relative => self.mem.peek(self.pc) as Word,
             AddrMode::Immediate => self.pc,
-            AddrMode::ZeroPage  => self.mem.read(self.pc) as Word,
+            AddrMode::ZeroPage  => self.mem.peek(self.pc) as Word,
 
-            AddrMode::ZeroPageX => (self.mem.read(self.pc) as Word).wrapping_add(self.x as Word),
-            AddrMode::ZeroPageY => (self.mem.read(self.pc) as Word).wrapping_add(self.y as Word),
+            AddrMode::ZeroPageX => (self.mem.peek(self.pc) as Word).wrapping_add(self.x as Word),
+            AddrMode::ZeroPageY => (self.mem.peek(self.pc) as Word).wrapping_add(self.y as Word),
 
             AddrMode::Absolute  => self.mem.read_word(self.pc),
 
@@ -475,8 +614,8 @@ impl Cpu {
 
             AddrMode::Indirect  => self.mem.read_word(self.mem.read_word(self.pc)),
             
-            AddrMode::IndirectX => self.mem.read_word((self.mem.read(self.pc) as Word).wrapping_add(self.x as Word)),
-            AddrMode::IndirectY => self.mem.read_word(self.mem.read(self.pc) as Word).wrapping_add(self.y as Word),
+            AddrMode::IndirectX => self.mem.read_word((self.mem.peek(self.pc) as Word).wrapping_add(self.x as Word)),
+            AddrMode::IndirectY => self.mem.read_word(self.mem.peek(self.pc) as Word).wrapping_add(self.y as Word),
         }
     }
 }
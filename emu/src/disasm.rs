@@ -0,0 +1,148 @@
+use {
+    crate::{
+        mem::*,
+        opcode::*,
+    },
+
+    std::{
+        fmt,
+        ops::RangeInclusive,
+    },
+};
+
+// A decoded instruction. Unofficial opcodes, and instructions cut off at
+// the end of the input, come out as a single data byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: Word,
+    pub code: Byte,
+    pub mnemonic: Option<&'static str>,
+    pub mode: AddrMode,
+    pub operand: Word,
+}
+
+impl Instruction {
+    pub fn decode<F: Fn(Word) -> Byte>(addr: Word, read: F) -> Self {
+        let code = read(addr);
+
+        let Some((mnemonic, mode)) = lookup_instruction(code) else {
+            return Self::data(addr, code);
+        };
+
+        let low = read(addr.wrapping_add(1)) as Word;
+        let high = read(addr.wrapping_add(2)) as Word;
+
+        let operand = match mode.operand_size() {
+            0 => 0,
+            1 => low,
+            _ => high << 8 | low,
+        };
+
+        Self { addr, code, mnemonic: Some(mnemonic), mode, operand }
+    }
+
+    fn data(addr: Word, code: Byte) -> Self {
+        Self { addr, code, mnemonic: None, mode: AddrMode::Implied, operand: 0 }
+    }
+
+    pub fn size(&self) -> Word {
+        1 + self.mode.operand_size()
+    }
+
+    #[inline(always)]
+    pub fn next(&self) -> Word {
+        self.addr.wrapping_add(self.size())
+    }
+
+    // Where a branch goes when taken
+    pub fn target(&self) -> Option<Word> {
+        (self.mode == AddrMode::Relative).then(|| {
+            self.next().wrapping_add(self.operand as Byte as Signed as Word)
+        })
+    }
+
+    pub fn bytes(&self) -> Vec<Byte> {
+        let operand = self.operand.to_le_bytes();
+        let size = self.mode.operand_size() as usize;
+
+        [&[self.code], &operand[..size]].concat()
+    }
+
+    // Address, raw bytes and the instruction, one line of a listing
+    pub fn listing(&self) -> String {
+        let bytes: Vec<String> = self.bytes().iter().map(|byte| format!("{byte:02X}")).collect();
+        format!("{:04X}  {:<8}  {self}", self.addr, bytes.join(" "))
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(mnemonic) = self.mnemonic else {
+            return write!(f, ".byte ${:02X}", self.code);
+        };
+
+        let operand = self.operand;
+
+        match self.mode {
+            AddrMode::Implied => write!(f, "{mnemonic}"),
+            AddrMode::Accumulator => write!(f, "{mnemonic} A"),
+            AddrMode::Immediate => write!(f, "{mnemonic} #${operand:02X}"),
+            AddrMode::Relative => write!(f, "{mnemonic} ${:04X}", self.target().unwrap()),
+
+            AddrMode::ZeroPage => write!(f, "{mnemonic} ${operand:02X}"),
+            AddrMode::ZeroPageX => write!(f, "{mnemonic} ${operand:02X},X"),
+            AddrMode::ZeroPageY => write!(f, "{mnemonic} ${operand:02X},Y"),
+
+            AddrMode::Absolute => write!(f, "{mnemonic} ${operand:04X}"),
+            AddrMode::AbsoluteX => write!(f, "{mnemonic} ${operand:04X},X"),
+            AddrMode::AbsoluteY => write!(f, "{mnemonic} ${operand:04X},Y"),
+
+            AddrMode::Indirect => write!(f, "{mnemonic} (${operand:04X})"),
+            AddrMode::IndirectX => write!(f, "{mnemonic} (${operand:02X},X)"),
+            AddrMode::IndirectY => write!(f, "{mnemonic} (${operand:02X}),Y"),
+        }
+    }
+}
+
+// Goes through `Memory::peek`, so registers with read side effects are
+// left as they were
+pub fn disassemble(mem: &Memory, addr: Word) -> Instruction {
+    Instruction::decode(addr, |addr| mem.peek(addr))
+}
+
+// Every instruction starting in `range`, the last may run past its end
+pub fn disassemble_range(mem: &Memory, range: RangeInclusive<Word>) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut addr = *range.start() as u32;
+
+    while addr <= *range.end() as u32 {
+        let instruction = disassemble(mem, addr as Word);
+        addr += instruction.size() as u32;
+
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+// Code that isn't on a bus, as if loaded at `origin`
+pub fn disassemble_bytes(bytes: &[Byte], origin: Word) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let addr = origin.wrapping_add(offset as Word);
+        let mut instruction = Instruction::decode(addr, |at| {
+            bytes.get(at.wrapping_sub(origin) as usize).copied().unwrap_or(0)
+        });
+
+        if offset + instruction.size() as usize > bytes.len() {
+            instruction = Instruction::data(addr, bytes[offset]);
+        }
+
+        offset += instruction.size() as usize;
+        instructions.push(instruction);
+    }
+
+    instructions
+}
@@ -0,0 +1,104 @@
+use {
+    crate::{
+        cpu::*,
+        mem::*,
+        ppu::*,
+        state::*,
+    },
+};
+
+pub const OAM_DMA: Word = 0x4014;
+
+// Pending transfers, the CPU services them between instructions.
+// The DMC requests a sample byte and picks it up once it has been read
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Dma {
+    oam_page: Option<Byte>,
+
+    dmc_addr: Option<Word>,
+    dmc_sample: Option<Byte>,
+}
+
+savestate!(Dma { oam_page, dmc_addr, dmc_sample });
+
+impl Dma {
+    #[inline(always)]
+    pub fn request_oam(&mut self, page: Byte) {
+        self.oam_page = Some(page);
+    }
+
+    #[inline(always)]
+    pub fn request_dmc(&mut self, addr: Word) {
+        self.dmc_addr = Some(addr);
+    }
+
+    #[inline(always)]
+    pub fn take_dmc_sample(&mut self) -> Option<Byte> {
+        self.dmc_sample.take()
+    }
+
+    #[inline(always)]
+    pub fn pending(&self) -> bool {
+        self.oam_page.is_some() || self.dmc_addr.is_some()
+    }
+}
+
+impl Cpu {
+    // Both units share one DMA engine that only reads on even ("get")
+    // cycles and only writes on odd ("put") ones. OAM DMA is a halt cycle,
+    // an optional alignment cycle and 256 get/put pairs, 513 or 514
+    // cycles in total. A DMC fetch needs a halt and a dummy cycle of its
+    // own before its get, but during OAM DMA it just steals a get slot,
+    // which then costs an extra alignment cycle for the sprite copy
+    pub fn run_dma(&mut self) {
+        if !self.mem.dma.pending() {
+            return;
+        }
+
+        let oam_page = self.mem.dma.oam_page.take();
+        let mut oam_index: usize = 0;
+        let mut oam_latch: Byte = 0;
+
+        // Cycles left before a DMC request may take a get slot, a request
+        // that is already waiting shares the halt cycle
+        let mut dmc_delay: u8 = if self.mem.dma.dmc_addr.is_some() { 1 } else { 2 };
+        self.mem.tick();
+
+        loop {
+            let get = (self.mem.cycles() & 1) == 0;
+            let oam_active = oam_page.is_some() && oam_index < OAM_SIZE * 2;
+
+            if !oam_active && self.mem.dma.dmc_addr.is_none() {
+                break;
+            }
+
+            let dmc_ready = self.mem.dma.dmc_addr.is_some() && dmc_delay == 0;
+
+            match (get, dmc_ready, oam_active, oam_index & 1) {
+                (true, true, _, _) => {
+                    let addr = self.mem.dma.dmc_addr.take().unwrap_or_default();
+                    self.mem.dma.dmc_sample = Some(self.mem.read(addr));
+                    dmc_delay = 2;
+                }
+                (true, false, true, 0) => {
+                    let page = oam_page.unwrap_or_default() as Word;
+                    oam_latch = self.mem.read(page << 8 | (oam_index / 2) as Word);
+                    oam_index += 1;
+                }
+                (false, _, true, 1) => {
+                    self.mem.write(OAMDATA, oam_latch);
+                    oam_index += 1;
+                }
+
+                // Halt, dummy and alignment cycles
+                _ => {}
+            }
+
+            if self.mem.dma.dmc_addr.is_some() {
+                dmc_delay = dmc_delay.saturating_sub(1);
+            }
+
+            self.mem.tick();
+        }
+    }
+}
@@ -1,4 +1,120 @@
+use crate::input::Device;
+
 #[derive(Debug, Clone, Copy)]
 pub enum ExecError {
     InvalidInstruction,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomError {
+    InvalidMagic,
+    Truncated,
+    UnsupportedMapper(u16),
+    // Empty or not a whole number of 16K banks
+    UnsupportedPrgSize(usize),
+
+    Io(std::io::ErrorKind),
+}
+
+impl From<std::io::Error> for RomError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteError {
+    InvalidSize(usize),
+
+    Io(std::io::ErrorKind),
+}
+
+impl From<std::io::Error> for PaletteError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RecordError {
+    Exec(ExecError),
+
+    Io(std::io::ErrorKind),
+}
+
+impl From<ExecError> for RecordError {
+    fn from(error: ExecError) -> Self {
+        Self::Exec(error)
+    }
+}
+
+impl From<std::io::Error> for RecordError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    // 1-based line of the movie file that couldn't be read
+    Parse(usize),
+    UnsupportedDevice(Device),
+    Rom(RomError),
+
+    Io(std::io::ErrorKind),
+}
+
+impl From<RomError> for MovieError {
+    fn from(error: RomError) -> Self {
+        Self::Rom(error)
+    }
+}
+
+impl From<std::io::Error> for MovieError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error.kind())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    ChecksumMismatch,
+    Truncated,
+    Corrupted,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RewindError {
+    Exec(ExecError),
+    State(StateError),
+}
+
+impl From<ExecError> for RewindError {
+    fn from(error: ExecError) -> Self {
+        Self::Exec(error)
+    }
+}
+
+impl From<StateError> for RewindError {
+    fn from(error: StateError) -> Self {
+        Self::State(error)
+    }
+}
+
+// Each carries the 1-based source line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsmError {
+    Syntax(usize),
+    UnknownInstruction(usize),
+    // The instruction has no such addressing mode
+    InvalidMode(usize),
+    UndefinedSymbol(usize),
+    DuplicateSymbol(usize),
+    // A value too big for its operand, or a branch too far
+    OutOfRange(usize),
+    // `.org` going back over code already placed
+    Org(usize),
+}
@@ -0,0 +1,80 @@
+use {
+    super::*,
+};
+
+// Signatures shifted out after both pads, MSB first, so games can tell
+// the adapter from two plain controllers
+pub const FOUR_SCORE_SIGNATURES: [Byte; PORT_COUNT] = [0x10, 0x20];
+
+// One side of the Four Score multitap. Port 1 chains players 1 and 3,
+// port 2 players 2 and 4, each followed by its signature byte
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FourScore {
+    pub pads: [Joypad; 2],
+    signature: Byte,
+
+    strobe: bool,
+    reads: u8,
+}
+
+savestate!(FourScore { pads, strobe, reads });
+
+impl FourScore {
+    pub fn new(port: usize) -> Self {
+        Self { signature: FOUR_SCORE_SIGNATURES[port],
+               ..Self::default() }
+    }
+
+    fn output(&self) -> Byte {
+        match self.reads {
+            0..=7 => self.pads[0].peek(),
+            8..=15 => self.pads[1].peek(),
+            16..=23 => (self.signature >> (23 - self.reads)) & 0x01,
+
+            _ => 1,
+        }
+    }
+}
+
+impl ControllerPort for FourScore {
+    fn device(&self) -> Device {
+        Device::FourScore
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.reads = 0;
+        }
+
+        for pad in &mut self.pads {
+            pad.write_strobe(strobe);
+        }
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> Byte {
+        let data = self.output();
+        if self.strobe {
+            return data;
+        }
+
+        match self.reads {
+            0..=7 => { self.pads[0].read(); }
+            8..=15 => { self.pads[1].read(); }
+            _ => {}
+        }
+        self.reads = self.reads.saturating_add(1);
+
+        data
+    }
+
+    fn peek(&self, _ppu: &Ppu) -> Byte {
+        self.output()
+    }
+
+    fn set_pad(&mut self, pad: usize, buttons: Byte) {
+        if let Some(pad) = self.pads.get_mut(pad) {
+            pad.set_buttons(buttons);
+        }
+    }
+}
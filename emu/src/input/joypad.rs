@@ -0,0 +1,92 @@
+use {
+    super::*,
+};
+
+// Standard controller. A 4021 shift register that keeps reloading from
+// the buttons while strobe is high, once it drops every read shifts the
+// next button out
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Joypad {
+    buttons: Byte,
+
+    strobe: bool,
+    shift: Byte,
+    reads: u8,
+}
+
+savestate!(Joypad { buttons, strobe, shift, reads });
+
+impl Joypad {
+    pub fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.reload();
+        }
+    }
+
+    // Bit 0 of the next serial read, official pads read 1 once the
+    // eight buttons have been shifted out
+    pub fn read(&mut self) -> Byte {
+        let data = self.peek();
+
+        if self.strobe {
+            self.reload();
+        } else if self.reads < 8 {
+            self.shift >>= 1;
+            self.reads += 1;
+        }
+
+        data
+    }
+
+    pub fn peek(&self) -> Byte {
+        match (self.strobe, self.reads) {
+            (true, _) => self.buttons & BUTTON_A,
+            (false, 8..) => 1,
+            _ => self.shift & 0x01,
+        }
+    }
+
+    #[inline(always)]
+    pub fn buttons(&self) -> Byte {
+        self.buttons
+    }
+
+    // Taken as is, opposite directions included, see
+    // `Input::cancel_opposites`
+    pub fn set_buttons(&mut self, buttons: Byte) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        self.shift = self.buttons;
+        self.reads = 0;
+    }
+}
+
+impl ControllerPort for Joypad {
+    fn device(&self) -> Device {
+        Device::Joypad
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        Joypad::write_strobe(self, strobe);
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> Byte {
+        Joypad::read(self)
+    }
+
+    fn peek(&self, _ppu: &Ppu) -> Byte {
+        Joypad::peek(self)
+    }
+
+    fn set_pad(&mut self, pad: usize, buttons: Byte) {
+        if pad == 0 {
+            self.set_buttons(buttons);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use {
+    crate::{
+        mem::*,
+        ppu::*,
+        state::*,
+        error::*,
+    },
+
+    std::{
+        any::Any,
+        fmt::Debug,
+    },
+};
+
+pub mod joypad;
+pub mod four_score;
+pub mod zapper;
+pub mod paddle;
+
+#[cfg(test)]
+mod tests;
+
+pub use {
+    joypad::*,
+    four_score::*,
+    zapper::*,
+    paddle::*,
+};
+
+pub const JOYPAD1: Word = 0x4016;
+pub const JOYPAD2: Word = 0x4017;
+
+pub const PORT_COUNT: usize   = 2;
+pub const PLAYER_COUNT: usize = 4;
+
+// Buttons in the order the pad shifts them out
+pub const BUTTON_A: Byte      = 1 << 0;
+pub const BUTTON_B: Byte      = 1 << 1;
+pub const BUTTON_SELECT: Byte = 1 << 2;
+pub const BUTTON_START: Byte  = 1 << 3;
+pub const BUTTON_UP: Byte     = 1 << 4;
+pub const BUTTON_DOWN: Byte   = 1 << 5;
+pub const BUTTON_LEFT: Byte   = 1 << 6;
+pub const BUTTON_RIGHT: Byte  = 1 << 7;
+
+// Only the low bits are driven on $4016/$4017, the rest float at what
+// was last on the bus, the $40 of the address for a plain `lda $4016`
+pub const INPUT_OPEN_BUS: Byte  = 0x40;
+pub const INPUT_DATA_MASK: Byte = 0x1F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Unplugged,
+    Joypad,
+    FourScore,
+    Zapper,
+    Paddle,
+}
+
+// Whatever is plugged into a controller port. Reads return the driven
+// data lines (D0-D4), the bus fills in the open bus bits above them
+pub trait ControllerPort: Any + Debug + Savestate {
+    fn device(&self) -> Device;
+
+    // $4016 bit 0, seen by both ports
+    fn write_strobe(&mut self, strobe: bool);
+
+    fn read(&mut self, ppu: &Ppu) -> Byte;
+    fn peek(&self, ppu: &Ppu) -> Byte;
+
+    // Held buttons of a pad on this port, only the Four Score has more
+    // than one
+    fn set_pad(&mut self, _pad: usize, _buttons: Byte) {}
+}
+
+impl dyn ControllerPort {
+    pub fn downcast_ref<T: ControllerPort>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: ControllerPort>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Unplugged;
+
+savestate!(Unplugged {});
+
+impl ControllerPort for Unplugged {
+    fn device(&self) -> Device {
+        Device::Unplugged
+    }
+
+    fn write_strobe(&mut self, _strobe: bool) {}
+
+    fn read(&mut self, _ppu: &Ppu) -> Byte {
+        0
+    }
+
+    fn peek(&self, _ppu: &Ppu) -> Byte {
+        0
+    }
+}
+
+#[derive(Debug)]
+pub struct Input {
+    ports: [Box<dyn ControllerPort>; PORT_COUNT],
+
+    // Frontend setting, drops Up+Down and Left+Right in `set_buttons`
+    // since some games misbehave when given them. Movies set the ports
+    // directly and always get the raw buttons
+    pub cancel_opposites: bool,
+}
+
+// Each port is saved along with its device, a state only restores ports
+// that still have the same kind of device plugged in
+impl Savestate for Input {
+    fn save(&self, state: &mut StateWriter) {
+        for port in &self.ports {
+            let mut device = StateWriter::new();
+            port.save(&mut device);
+
+            (port.device() as Byte).save(state);
+            device.into_inner().save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for port in &mut self.ports {
+            let (mut device, mut data) = (0 as Byte, Vec::<Byte>::new());
+            device.load(state)?;
+            data.load(state)?;
+
+            if device == port.device() as Byte {
+                port.load(&mut StateReader::new(&data))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self { ports: [Box::new(Joypad::default()), Box::new(Joypad::default())],
+               cancel_opposites: false }
+    }
+}
+
+impl Input {
+    // $4016 bit 0 is the strobe line of both ports
+    pub fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr == JOYPAD1 {
+            for port in &mut self.ports {
+                port.write_strobe((data & 0x01) != 0);
+            }
+        }
+    }
+
+    pub fn cpu_read(&mut self, addr: Word, ppu: &Ppu) -> Byte {
+        let data = self.ports[port_index(addr)].read(ppu);
+        (INPUT_OPEN_BUS & !INPUT_DATA_MASK) | (data & INPUT_DATA_MASK)
+    }
+
+    pub fn cpu_peek(&self, addr: Word, ppu: &Ppu) -> Byte {
+        let data = self.ports[port_index(addr)].peek(ppu);
+        (INPUT_OPEN_BUS & !INPUT_DATA_MASK) | (data & INPUT_DATA_MASK)
+    }
+
+    pub fn connect(&mut self, port: usize, device: Box<dyn ControllerPort>) {
+        self.ports[port] = device;
+    }
+
+    // The multitap takes up both ports
+    pub fn connect_four_score(&mut self) {
+        self.ports = [Box::new(FourScore::new(0)), Box::new(FourScore::new(1))];
+    }
+
+    pub fn port(&self, port: usize) -> &dyn ControllerPort {
+        self.ports[port].as_ref()
+    }
+
+    pub fn port_mut(&mut self, port: usize) -> &mut dyn ControllerPort {
+        self.ports[port].as_mut()
+    }
+
+    // The device on a port when it's of type `T`, e.g. to aim a `Zapper`
+    pub fn device_mut<T: ControllerPort>(&mut self, port: usize) -> Option<&mut T> {
+        self.ports[port].downcast_mut()
+    }
+
+    pub fn set_buttons(&mut self, port: usize, buttons: Byte) {
+        let buttons = self.filter_buttons(buttons);
+        self.ports[port].set_pad(0, buttons);
+    }
+
+    // Players alternate between the ports, 3 and 4 only exist behind a
+    // Four Score
+    pub fn set_player_buttons(&mut self, player: usize, buttons: Byte) {
+        let buttons = self.filter_buttons(buttons);
+        self.ports[player % PORT_COUNT].set_pad(player / PORT_COUNT, buttons);
+    }
+
+    fn filter_buttons(&self, mut buttons: Byte) -> Byte {
+        if self.cancel_opposites {
+            for pair in [BUTTON_UP | BUTTON_DOWN, BUTTON_LEFT | BUTTON_RIGHT] {
+                if (buttons & pair) == pair {
+                    buttons &= !pair;
+                }
+            }
+        }
+
+        buttons
+    }
+}
+
+#[inline(always)]
+pub fn port_index(addr: Word) -> usize {
+    (addr == JOYPAD2) as usize
+}
@@ -0,0 +1,77 @@
+use {
+    super::*,
+};
+
+pub const PADDLE_BUTTON: Byte = 1 << 3;
+pub const PADDLE_DATA: Byte   = 1 << 4;
+
+// Knob range of the original controller, Arkanoid expects roughly this
+pub const PADDLE_MIN: Byte = 0x62;
+pub const PADDLE_MAX: Byte = 0xF2;
+
+// Arkanoid "Vaus" controller. The strobe latches the knob position,
+// reads then shift it out inverted, MSB first on D4, with the fire
+// button on D3
+#[derive(Debug, Clone, Copy)]
+pub struct Paddle {
+    pub position: Byte,
+    pub button: bool,
+
+    strobe: bool,
+    shift: Byte,
+}
+
+savestate!(Paddle { position, button, strobe, shift });
+
+impl Default for Paddle {
+    fn default() -> Self {
+        Self { position: PADDLE_MIN,
+               button: false,
+               strobe: false,
+               shift: 0 }
+    }
+}
+
+impl Paddle {
+    fn latch(&mut self) {
+        self.shift = !self.position;
+    }
+}
+
+impl ControllerPort for Paddle {
+    fn device(&self) -> Device {
+        Device::Paddle
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, ppu: &Ppu) -> Byte {
+        let data = self.peek(ppu);
+
+        if self.strobe {
+            self.latch();
+        } else {
+            self.shift <<= 1;
+        }
+
+        data
+    }
+
+    fn peek(&self, _ppu: &Ppu) -> Byte {
+        let mut data = 0;
+
+        if (self.shift & 0x80) != 0 {
+            data |= PADDLE_DATA;
+        }
+        if self.button {
+            data |= PADDLE_BUTTON;
+        }
+
+        data
+    }
+}
@@ -0,0 +1,348 @@
+use {
+    super::*,
+    crate::{
+        status::*,
+        nes::*,
+    },
+};
+
+// Strobes the ports and reads `count` bits of `port` directly
+fn read_port(port: &mut dyn ControllerPort, ppu: &Ppu, count: usize) -> Vec<Byte> {
+    port.write_strobe(true);
+    port.write_strobe(false);
+
+    (0..count).map(|_| port.read(ppu)).collect()
+}
+
+// Runs the PPU up to the given position
+fn run_to(ppu: &mut Ppu, scanline: u16, dot: u16) {
+    while (ppu.scanline, ppu.dot) != (scanline, dot) {
+        ppu.tick(None);
+    }
+}
+
+// Strobes the pads and reads `count` bits from a port through the bus
+fn read_serial(mem: &mut Memory, addr: Word, count: usize) -> Vec<Byte> {
+    mem.write(JOYPAD1, 1);
+    mem.write(JOYPAD1, 0);
+
+    (0..count).map(|_| mem.read(addr) & 0x01).collect()
+}
+
+// JOYPAD
+
+#[test]
+fn joypad_serial_order() {
+    let mut pad = Joypad::default();
+    pad.set_buttons(BUTTON_A | BUTTON_START | BUTTON_LEFT);
+
+    pad.write_strobe(true);
+    pad.write_strobe(false);
+
+    let bits: Vec<Byte> = (0..8).map(|_| pad.read()).collect();
+    assert_eq!(bits, [1, 0, 0, 1, 0, 0, 1, 0]);
+}
+
+#[test]
+fn joypad_reads_one_after_eight() {
+    let mut pad = Joypad::default();
+    pad.write_strobe(true);
+    pad.write_strobe(false);
+
+    for _ in 0..8 {
+        assert_eq!(pad.read(), 0);
+    }
+    for _ in 0..4 {
+        assert_eq!(pad.read(), 1);
+    }
+}
+
+#[test]
+fn joypad_strobe_high_repeats_a() {
+    let mut pad = Joypad::default();
+    pad.set_buttons(BUTTON_A | BUTTON_B);
+    pad.write_strobe(true);
+
+    for _ in 0..10 {
+        assert_eq!(pad.read(), 1);
+    }
+
+    pad.set_buttons(BUTTON_B);
+    assert_eq!(pad.read(), 0);
+}
+
+#[test]
+fn joypad_latches_on_strobe() {
+    let mut pad = Joypad::default();
+    pad.set_buttons(BUTTON_A);
+    pad.write_strobe(true);
+    pad.write_strobe(false);
+
+    // Changes after the latch show up on the next strobe only
+    pad.set_buttons(BUTTON_B);
+    assert_eq!(pad.read(), 1);
+    assert_eq!(pad.read(), 0);
+
+    pad.write_strobe(true);
+    pad.write_strobe(false);
+    assert_eq!(pad.read(), 0);
+    assert_eq!(pad.read(), 1);
+}
+
+#[test]
+fn joypad_opposite_directions() {
+    let mut pad = Joypad::default();
+
+    pad.set_buttons(BUTTON_UP | BUTTON_DOWN | BUTTON_A);
+    assert_eq!(pad.buttons(), BUTTON_UP | BUTTON_DOWN | BUTTON_A);
+
+    // Only dropped when the frontend asks for it
+    let mut input = Input::default();
+    input.set_buttons(0, BUTTON_LEFT | BUTTON_RIGHT);
+    assert_eq!(input.device_mut::<Joypad>(0).unwrap().buttons(), BUTTON_LEFT | BUTTON_RIGHT);
+
+    input.cancel_opposites = true;
+    input.set_buttons(0, BUTTON_LEFT | BUTTON_RIGHT | BUTTON_UP);
+    assert_eq!(input.device_mut::<Joypad>(0).unwrap().buttons(), BUTTON_UP);
+
+    input.set_player_buttons(1, BUTTON_UP | BUTTON_DOWN | BUTTON_B);
+    assert_eq!(input.device_mut::<Joypad>(1).unwrap().buttons(), BUTTON_B);
+}
+
+#[test]
+fn joypad_peek_does_not_shift() {
+    let mut pad = Joypad::default();
+    pad.set_buttons(BUTTON_A);
+    pad.write_strobe(true);
+    pad.write_strobe(false);
+
+    assert_eq!(pad.peek(), 1);
+    assert_eq!(pad.peek(), 1);
+    assert_eq!(pad.read(), 1);
+    assert_eq!(pad.peek(), 0);
+}
+
+// FOUR SCORE
+
+#[test]
+fn four_score_serial_order() {
+    let ppu = Ppu::new();
+    let mut port = FourScore::new(0);
+    port.set_pad(0, BUTTON_A);
+    port.set_pad(1, BUTTON_B | BUTTON_RIGHT);
+
+    let bits = read_port(&mut port, &ppu, 26);
+    assert_eq!(bits[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(bits[8..16], [0, 1, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(bits[16..24], [0, 0, 0, 1, 0, 0, 0, 0]);
+    assert_eq!(bits[24..], [1, 1]);
+}
+
+#[test]
+fn four_score_signatures() {
+    let ppu = Ppu::new();
+
+    // The 20th read on $4016 and the 19th on $4017 are the only ones set
+    let expected = [[0, 0, 0, 1, 0, 0, 0, 0], [0, 0, 1, 0, 0, 0, 0, 0]];
+
+    for (index, expected) in expected.into_iter().enumerate() {
+        let mut port = FourScore::new(index);
+        let bits = read_port(&mut port, &ppu, 24);
+
+        assert_eq!(bits[16..], expected);
+    }
+}
+
+#[test]
+fn four_score_players() {
+    let mut mem = Memory::zeroed();
+    mem.input.connect_four_score();
+
+    for player in 0..PLAYER_COUNT {
+        mem.input.set_player_buttons(player, 1 << player);
+    }
+
+    let port1 = read_serial(&mut mem, JOYPAD1, 16);
+    let port2 = read_serial(&mut mem, JOYPAD2, 16);
+
+    // Players 1 and 3 on $4016, 2 and 4 on $4017
+    assert_eq!(port1, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0]);
+    assert_eq!(port2, [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+}
+
+// ZAPPER
+
+#[test]
+fn zapper_trigger() {
+    let ppu = Ppu::new();
+    let mut zapper = Zapper::default();
+
+    assert_eq!(zapper.read(&ppu), ZAPPER_LIGHT);
+
+    zapper.trigger = true;
+    assert_eq!(zapper.read(&ppu), ZAPPER_LIGHT | ZAPPER_TRIGGER);
+}
+
+#[test]
+fn zapper_senses_beam() {
+    let mut ppu = Ppu::new();
+    ppu.palette[0] = 0x30;
+
+    let mut zapper = Zapper { aim: Some((100, 100)),
+                              trigger: false };
+
+    // Not drawn yet this frame
+    run_to(&mut ppu, 90, 0);
+    assert_eq!(zapper.read(&ppu) & ZAPPER_LIGHT, ZAPPER_LIGHT);
+
+    run_to(&mut ppu, 105, 0);
+    assert_eq!(zapper.read(&ppu) & ZAPPER_LIGHT, 0);
+
+    // The photodiode only stays lit for a while after the beam passed
+    run_to(&mut ppu, 100 + ZAPPER_SENSE_LINES as u16 + ZAPPER_RADIUS as u16 + 1, 0);
+    assert_eq!(zapper.read(&ppu) & ZAPPER_LIGHT, ZAPPER_LIGHT);
+
+    zapper.aim = None;
+    run_to(&mut ppu, 105, 0);
+    assert_eq!(zapper.read(&ppu) & ZAPPER_LIGHT, ZAPPER_LIGHT);
+}
+
+#[test]
+fn zapper_ignores_dark_pixels() {
+    let mut ppu = Ppu::new();
+    ppu.palette[0] = 0x0F;
+
+    let zapper = Zapper { aim: Some((100, 100)),
+                          trigger: false };
+
+    run_to(&mut ppu, 105, 0);
+    assert_eq!(zapper.peek(&ppu) & ZAPPER_LIGHT, ZAPPER_LIGHT);
+}
+
+#[test]
+fn zapper_on_bus() {
+    let mut mem = Memory::zeroed();
+    mem.input.connect(1, Box::new(Zapper::default()));
+
+    mem.input.device_mut::<Zapper>(1).unwrap().trigger = true;
+    assert!(mem.input.device_mut::<Joypad>(1).is_none());
+
+    assert_eq!(mem.read(JOYPAD2), INPUT_OPEN_BUS | ZAPPER_LIGHT | ZAPPER_TRIGGER);
+    assert_eq!(mem.input.port(1).device(), Device::Zapper);
+}
+
+// PADDLE
+
+#[test]
+fn paddle_position() {
+    let ppu = Ppu::new();
+    let mut paddle = Paddle::default();
+    paddle.position = 0xA5;
+
+    // Inverted and MSB first on D4
+    let bits: Vec<Byte> = read_port(&mut paddle, &ppu, 8).iter().map(|data| data >> 4).collect();
+    assert_eq!(bits, [0, 1, 0, 1, 1, 0, 1, 0]);
+}
+
+#[test]
+fn paddle_button() {
+    let ppu = Ppu::new();
+    let mut paddle = Paddle::default();
+    paddle.position = 0xFF;
+    paddle.button = true;
+
+    assert_eq!(read_port(&mut paddle, &ppu, 2), [PADDLE_BUTTON, PADDLE_BUTTON]);
+}
+
+#[test]
+fn paddle_latches_on_strobe() {
+    let ppu = Ppu::new();
+    let mut paddle = Paddle::default();
+    paddle.position = 0x00;
+
+    paddle.write_strobe(true);
+    paddle.write_strobe(false);
+    paddle.position = 0xFF;
+
+    assert_eq!(paddle.read(&ppu), PADDLE_DATA);
+}
+
+#[test]
+fn unplugged_port() {
+    let mut mem = Memory::zeroed();
+    mem.input.connect(0, Box::new(Unplugged));
+
+    assert_eq!(read_serial(&mut mem, JOYPAD1, 4), [0, 0, 0, 0]);
+    assert_eq!(mem.peek(JOYPAD1), INPUT_OPEN_BUS);
+}
+
+// BUS
+
+#[test]
+fn input_ports_on_bus() {
+    let mut mem = Memory::zeroed();
+    mem.input.set_buttons(0, BUTTON_A | BUTTON_RIGHT);
+    mem.input.set_buttons(1, BUTTON_B);
+
+    assert_eq!(read_serial(&mut mem, JOYPAD1, 8), [1, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(read_serial(&mut mem, JOYPAD2, 8), [0, 1, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn input_open_bus() {
+    let mut mem = Memory::zeroed();
+    mem.input.set_buttons(0, BUTTON_A);
+
+    mem.write(JOYPAD1, 1);
+    mem.write(JOYPAD1, 0);
+
+    assert_eq!(mem.read(JOYPAD1), 0x41);
+    assert_eq!(mem.read(JOYPAD1), 0x40);
+    assert_eq!(mem.read(JOYPAD2), 0x40);
+}
+
+#[test]
+fn input_peek_on_bus() {
+    let mut mem = Memory::zeroed();
+    mem.input.set_buttons(0, BUTTON_A);
+
+    mem.write(JOYPAD1, 1);
+    mem.write(JOYPAD1, 0);
+
+    assert_eq!(mem.peek(JOYPAD1), 0x41);
+    assert_eq!(mem.read(JOYPAD1), 0x41);
+    assert_eq!(mem.peek(JOYPAD1), 0x40);
+}
+
+#[test]
+fn input_joypad2_write_goes_to_apu() {
+    let mut mem = Memory::zeroed();
+    mem.input.set_buttons(1, BUTTON_A);
+
+    // $4017 writes are the frame counter, they don't strobe the pads
+    mem.write(JOYPAD2, 1);
+    assert_eq!(mem.read(JOYPAD2), 0x40);
+    assert!(!mem.apu().frame_counter.five_step());
+}
+
+#[test]
+fn input_read_by_program() {
+    let mut nes = Nes::default();
+    nes.set_buttons(0, BUTTON_START);
+
+    // lda #1; sta $4016; lda #0; sta $4016, then read four bits into $10-$13
+    nes.cpu.reset_load_rom(&[
+        0xA9, 0x01, 0x8D, 0x16, 0x40,
+        0xA9, 0x00, 0x8D, 0x16, 0x40,
+        0xAD, 0x16, 0x40, 0x85, 0x10,
+        0xAD, 0x16, 0x40, 0x85, 0x11,
+        0xAD, 0x16, 0x40, 0x85, 0x12,
+        0xAD, 0x16, 0x40, 0x85, 0x13,
+        0x00,
+    ]);
+
+    while nes.step().unwrap() != ExecStatus::Exit {}
+
+    let bits: Vec<Byte> = (0x10..0x14).map(|addr| nes.cpu.mem.peek(addr)).collect();
+    assert_eq!(bits, [0x40, 0x40, 0x40, 0x41]);
+}
@@ -0,0 +1,87 @@
+use {
+    super::*,
+};
+
+pub const ZAPPER_LIGHT: Byte   = 1 << 3;
+pub const ZAPPER_TRIGGER: Byte = 1 << 4;
+
+// How far around the aimed pixel the sensor sees, and for how many lines
+// after the beam passed it the photodiode stays lit
+pub const ZAPPER_RADIUS: i32      = 2;
+pub const ZAPPER_SENSE_LINES: i32 = 20;
+
+// Minimum luma of a pixel that counts as light, the target flashes are
+// white on a black screen so anything near the top of the range will do
+pub const ZAPPER_BRIGHTNESS: u32 = 0x80;
+
+// Light gun. Doesn't use the strobe, reads report the trigger on D4 and
+// D3 low while the sensor sees light from the beam near where it aims
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Zapper {
+    // Screen pixel aimed at, `None` when pointed away from the screen
+    pub aim: Option<(u8, u8)>,
+    pub trigger: bool,
+}
+
+savestate!(Zapper { aim, trigger });
+
+impl Zapper {
+    // Looks for bright pixels around the aim that the beam drew within
+    // the last few lines, anything not yet drawn this frame is dark
+    pub fn senses_light(&self, framebuffer: &[Word], scanline: u16, dot: u16) -> bool {
+        let Some((x, y)) = self.aim else {
+            return false;
+        };
+
+        let (x, y) = (x as i32, y as i32);
+        let (line, drawn) = (scanline as i32, dot as i32 - 1);
+
+        for py in (y - ZAPPER_RADIUS)..=(y + ZAPPER_RADIUS) {
+            if !(0..SCREEN_HEIGHT as i32).contains(&py) || !(0..=ZAPPER_SENSE_LINES).contains(&(line - py)) {
+                continue;
+            }
+
+            for px in (x - ZAPPER_RADIUS)..=(x + ZAPPER_RADIUS) {
+                if !(0..SCREEN_WIDTH as i32).contains(&px) || (py == line && px >= drawn) {
+                    continue;
+                }
+
+                if is_bright(framebuffer[py as usize * SCREEN_WIDTH + px as usize]) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+impl ControllerPort for Zapper {
+    fn device(&self) -> Device {
+        Device::Zapper
+    }
+
+    fn write_strobe(&mut self, _strobe: bool) {}
+
+    fn read(&mut self, ppu: &Ppu) -> Byte {
+        self.peek(ppu)
+    }
+
+    fn peek(&self, ppu: &Ppu) -> Byte {
+        let mut data = 0;
+
+        if !self.senses_light(ppu.framebuffer(), ppu.scanline, ppu.dot) {
+            data |= ZAPPER_LIGHT;
+        }
+        if self.trigger {
+            data |= ZAPPER_TRIGGER;
+        }
+
+        data
+    }
+}
+
+fn is_bright(entry: Word) -> bool {
+    let [r, g, b] = NTSC_PALETTE[(entry & 0x3F) as usize].map(|c| c as u32);
+    (r * 299 + g * 587 + b * 114) / 1000 >= ZAPPER_BRIGHTNESS
+}
@@ -3,6 +3,22 @@ pub mod mem;
 
 pub mod status;
 pub mod opcode;
+pub mod disasm;
+pub mod asm;
+
+pub mod cartridge;
+pub mod mapper;
+pub mod ppu;
+pub mod apu;
+pub mod input;
+pub mod dma;
+
+pub mod region;
+pub mod nes;
+pub mod wav;
+pub mod movie;
+pub mod state;
+pub mod rewind;
 
 pub mod error;
 pub mod consts;
@@ -0,0 +1,95 @@
+use {
+    super::*,
+};
+
+pub struct Axrom {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    bank: usize,
+
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+}
+
+savestate!(Axrom { chr, prg_ram, bank, mirroring });
+
+impl Mapper for Axrom {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x8000..=0xFFFF => self.prg[self.bank + (addr as usize - 0x8000)],
+            0x6000..=0x7FFF => self.prg_ram.read(addr as usize - 0x6000),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr < 0x8000 {
+            if (0x6000..0x8000).contains(&addr) {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            return;
+        }
+
+        let data = if self.bus_conflicts {
+            data & self.cpu_peek(addr)
+        } else {
+            data
+        };
+
+        self.bank = bank_offset((data & 0x07) as usize, PRG_BANK_32K, self.prg.len());
+        self.mirroring = if (data & 0x10) != 0 {
+            Mirroring::SingleScreenUpper
+        } else {
+            Mirroring::SingleScreenLower
+        };
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+}
+
+impl Axrom {
+    pub fn new(rom: Rom) -> Self {
+        // Boards power up with an unknown bank selected, but they are
+        // wired so that the reset vector lives in every bank anyway
+        let mut prg = rom.prg;
+        if prg.len() < PRG_BANK_32K {
+            prg = prg.repeat(PRG_BANK_32K / prg.len().max(1));
+        }
+
+        Self { chr: ChrMemory::from_rom(rom.chr, &rom.header),
+               prg_ram: PrgRam::optional(&rom.header),
+               mirroring: Mirroring::SingleScreenLower,
+               bus_conflicts: has_bus_conflicts(&rom.header),
+               prg,
+               bank: 0 }
+    }
+}
@@ -0,0 +1,83 @@
+use {
+    super::*,
+};
+
+pub struct Cnrom {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    bank: usize,
+
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+}
+
+savestate!(Cnrom { chr, prg_ram, bank });
+
+impl Mapper for Cnrom {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x8000..=0xFFFF => self.prg[(addr as usize - 0x8000) % self.prg.len()],
+            0x6000..=0x7FFF => self.prg_ram.read(addr as usize - 0x6000),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr < 0x8000 {
+            if (0x6000..0x8000).contains(&addr) {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            return;
+        }
+
+        let data = if self.bus_conflicts {
+            data & self.cpu_peek(addr)
+        } else {
+            data
+        };
+
+        self.bank = bank_offset(data as usize, CHR_BANK_8K, self.chr.len());
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(self.bank + (addr as usize & (CHR_BANK_8K - 1)))
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(self.bank + (addr as usize & (CHR_BANK_8K - 1)), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+}
+
+impl Cnrom {
+    pub fn new(rom: Rom) -> Self {
+        Self { chr: ChrMemory::from_rom(rom.chr, &rom.header),
+               prg_ram: PrgRam::optional(&rom.header),
+               mirroring: rom.header.mirroring,
+               bus_conflicts: has_bus_conflicts(&rom.header),
+               prg: rom.prg,
+               bank: 0 }
+    }
+}
@@ -0,0 +1,259 @@
+use {
+    super::*,
+};
+
+// A12 has to stay low for this many M2 cycles before a rising edge is
+// counted, otherwise the 8 sprite fetches would clock the counter 8 times
+pub const A12_FILTER_CYCLES: u8 = 3;
+
+pub struct Mmc3 {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    select: Byte,
+    registers: [Byte; 8],
+
+    prg_banks: [usize; 4],
+    chr_banks: [usize; 8],
+
+    mirroring: Mirroring,
+    four_screen: bool,
+
+    ram_enabled: bool,
+    ram_protected: bool,
+
+    irq_latch: Byte,
+    irq_counter: Byte,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    a12: bool,
+    a12_low_cycles: u8,
+}
+
+savestate!(Mmc3 {
+    chr, prg_ram, select, registers, prg_banks, chr_banks, mirroring, ram_enabled,
+    ram_protected, irq_latch, irq_counter, irq_reload, irq_enabled, irq_pending, a12,
+    a12_low_cycles,
+});
+
+impl Mapper for Mmc3 {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x6000..=0x7FFF if self.ram_enabled && !self.prg_ram.is_empty() => {
+                self.prg_ram.read(addr as usize - 0x6000)
+            }
+
+            0x8000..=0xFFFF => {
+                let slot = (addr as usize - 0x8000) / PRG_BANK_8K;
+                self.prg[self.prg_banks[slot] + (addr as usize & (PRG_BANK_8K - 1))]
+            }
+
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        let even = (addr & 1) == 0;
+
+        match addr {
+            0x6000..=0x7FFF if self.ram_writable() => {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            0x8000..=0x9FFF if even => {
+                self.select = data;
+                self.update_banks();
+            }
+            0x8000..=0x9FFF => {
+                self.registers[(self.select & 0x07) as usize] = data;
+                self.update_banks();
+            }
+
+            0xA000..=0xBFFF if even && !self.four_screen => {
+                self.mirroring = if (data & 1) == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0xA000..=0xBFFF if !even => {
+                self.ram_enabled = (data & 0x80) != 0;
+                self.ram_protected = (data & 0x40) != 0;
+            }
+
+            0xC000..=0xDFFF if even => self.irq_latch = data,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.watch_a12(addr);
+
+        match addr {
+            0x0000..=0x1FFF => self.chr.read(self.chr_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.watch_a12(addr);
+
+        if addr < 0x2000 {
+            self.chr.write(self.chr_offset(addr), data);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+
+    fn cpu_tick(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+impl Mmc3 {
+    #[inline(always)]
+    fn ram_writable(&self) -> bool {
+        self.ram_enabled && !self.ram_protected && !self.prg_ram.is_empty()
+    }
+
+    fn watch_a12(&mut self, addr: Word) {
+        let a12 = (addr & 0x1000) != 0;
+
+        if a12 && !self.a12 && self.a12_low_cycles >= A12_FILTER_CYCLES {
+            self.clock_irq();
+        }
+
+        if !a12 && self.a12 {
+            self.a12_low_cycles = 0;
+        }
+
+        self.a12 = a12;
+    }
+
+    pub fn clock_irq(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    #[inline(always)]
+    fn chr_offset(&self, addr: Word) -> usize {
+        let slot = addr as usize / CHR_BANK_1K;
+        self.chr_banks[slot] + (addr as usize & (CHR_BANK_1K - 1))
+    }
+
+    fn update_banks(&mut self) {
+        let prg_len = self.prg.len();
+        let chr_len = self.chr.len();
+
+        let prg_bank = |bank: usize| bank_offset(bank, PRG_BANK_8K, prg_len);
+        let chr_bank = |bank: Byte| bank_offset(bank as usize, CHR_BANK_1K, chr_len);
+
+        let second_last = prg_bank(last_bank(PRG_BANK_8K, prg_len).saturating_sub(1));
+        let last = prg_bank(last_bank(PRG_BANK_8K, prg_len));
+
+        let r6 = prg_bank(self.registers[6] as usize & 0x3F);
+        let r7 = prg_bank(self.registers[7] as usize & 0x3F);
+
+        self.prg_banks = if (self.select & 0x40) == 0 {
+            [r6, r7, second_last, last]
+        } else {
+            [second_last, r7, r6, last]
+        };
+
+        let r = self.registers;
+        let two_kb = [
+            chr_bank(r[0] & 0xFE), chr_bank(r[0] | 0x01),
+            chr_bank(r[1] & 0xFE), chr_bank(r[1] | 0x01),
+        ];
+        let one_kb = [chr_bank(r[2]), chr_bank(r[3]), chr_bank(r[4]), chr_bank(r[5])];
+
+        if (self.select & 0x80) == 0 {
+            self.chr_banks[..4].copy_from_slice(&two_kb);
+            self.chr_banks[4..].copy_from_slice(&one_kb);
+        } else {
+            self.chr_banks[..4].copy_from_slice(&one_kb);
+            self.chr_banks[4..].copy_from_slice(&two_kb);
+        }
+    }
+
+    pub fn new(rom: Rom) -> Self {
+        let header = rom.header;
+        let ram_size = (header.prg_ram_size + header.prg_nvram_size).max(PRG_BANK_8K);
+
+        let mut mapper = Self {
+            chr: ChrMemory::from_rom(rom.chr, &header),
+            prg: rom.prg,
+            prg_ram: PrgRam::new(ram_size),
+
+            select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+
+            prg_banks: [0; 4],
+            chr_banks: [0; 8],
+
+            mirroring: header.mirroring,
+            four_screen: header.mirroring == Mirroring::FourScreen,
+
+            ram_enabled: true,
+            ram_protected: false,
+
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+
+            a12: false,
+            a12_low_cycles: A12_FILTER_CYCLES,
+        };
+
+        mapper.update_banks();
+        mapper
+    }
+}
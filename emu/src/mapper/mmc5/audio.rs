@@ -0,0 +1,189 @@
+use {
+    crate::{
+        mem::*,
+        state::*,
+        apu::{
+            Envelope,
+            LengthCounter,
+            DUTY_TABLE,
+            pulse_level,
+        },
+    },
+};
+
+// The MMC5 has no frame counter of its own, envelopes and length
+// counters are clocked at a fixed ~240 Hz instead
+pub const FRAME_PERIOD: u16 = 7457;
+
+// A 2A03 pulse without the sweep unit
+#[derive(Default)]
+struct Pulse {
+    duty: usize,
+    step: usize,
+
+    period: Word,
+    timer: Word,
+
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+savestate!(Pulse { duty, step, period, timer, length, envelope });
+
+pub struct Mmc5Audio {
+    pulses: [Pulse; 2],
+
+    pcm: Byte,
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+
+    divider: u16,
+    odd_cycle: bool,
+}
+
+savestate!(Mmc5Audio { pulses, pcm, pcm_read_mode, pcm_irq_enabled, pcm_irq, divider, odd_cycle });
+
+impl Pulse {
+    fn write(&mut self, register: Word, data: Byte) {
+        match register {
+            0 => {
+                self.duty = (data >> 6) as usize;
+                self.envelope.write(data);
+
+                // Envelope loop doubles as the length counter halt flag
+                self.length.set_halted((data & 0x20) != 0);
+            }
+
+            2 => self.period = (self.period & 0x0700) | data as Word,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((data as Word & 0x07) << 8);
+                self.step = 0;
+
+                self.envelope.restart();
+                self.length.load(data >> 3);
+            }
+
+            _ => {}
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_frame(&mut self) {
+        self.envelope.clock();
+        self.length.clock();
+    }
+
+    // Unlike the 2A03 pulses, low periods are not silenced
+    fn output(&self) -> Byte {
+        if !self.length.active() || DUTY_TABLE[self.duty][self.step] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Mmc5Audio {
+    pub fn write(&mut self, addr: Word, data: Byte) {
+        match addr {
+            0x5000..=0x5003 => self.pulses[0].write(addr - 0x5000, data),
+            0x5004..=0x5007 => self.pulses[1].write(addr - 0x5004, data),
+
+            0x5010 => {
+                self.pcm_read_mode = (data & 0x01) != 0;
+                self.pcm_irq_enabled = (data & 0x80) != 0;
+            }
+
+            // Zero is ignored in write mode
+            0x5011 if !self.pcm_read_mode && data != 0 => self.pcm = data,
+
+            0x5015 => {
+                self.pulses[0].length.set_enabled((data & 0x01) != 0);
+                self.pulses[1].length.set_enabled((data & 0x02) != 0);
+            }
+
+            _ => {}
+        }
+    }
+
+    pub fn status(&self) -> Byte {
+        self.pulses[0].length.active() as Byte
+            | (self.pulses[1].length.active() as Byte) << 1
+    }
+
+    // $5010, bit 7 is set once a zero sample was read
+    pub fn pcm_status(&self) -> Byte {
+        (self.pcm_irq as Byte) << 7
+    }
+
+    #[inline(always)]
+    pub fn acknowledge_pcm(&mut self) {
+        self.pcm_irq = false;
+    }
+
+    // In read mode every CPU read from $8000-$BFFF is latched as the next
+    // sample, a zero ends the sample with an IRQ instead
+    pub fn snoop_read(&mut self, data: Byte) {
+        if !self.pcm_read_mode {
+            return;
+        }
+
+        match data {
+            0 => self.pcm_irq = true,
+            _ => self.pcm = data,
+        }
+    }
+
+    #[inline(always)]
+    pub fn irq(&self) -> bool {
+        self.pcm_irq && self.pcm_irq_enabled
+    }
+
+    pub fn tick(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            for pulse in &mut self.pulses {
+                pulse.clock_timer();
+            }
+        }
+
+        self.divider += 1;
+        if self.divider >= FRAME_PERIOD {
+            self.divider = 0;
+
+            for pulse in &mut self.pulses {
+                pulse.clock_frame();
+            }
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let pulse_out = pulse_level(self.pulses[0].output() + self.pulses[1].output());
+        pulse_out + (self.pcm as f32 / 255.0) * 0.25
+    }
+
+    pub fn new() -> Self {
+        Self { pulses: Default::default(),
+               pcm: 0,
+               pcm_read_mode: false,
+               pcm_irq_enabled: false,
+               pcm_irq: false,
+               divider: 0,
+               odd_cycle: false }
+    }
+}
+
+impl Default for Mmc5Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,582 @@
+use {
+    super::*,
+};
+
+pub mod audio;
+
+use audio::Mmc5Audio;
+
+pub const EXRAM_SIZE: usize = 0x400;
+
+// Background fetches of a scanline (32 tiles * 4 reads) come first,
+// followed by 8 sprites * 4 reads and the two prefetched tiles of the
+// next line
+pub const SPRITE_FETCH_BEGIN: u16   = 128;
+pub const PREFETCH_BEGIN: u16       = 160;
+pub const PREFETCH_END: u16         = 168;
+
+pub const IDLE_CYCLES_OUT_OF_FRAME: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChrSet {
+    Sprites,
+    Background,
+}
+
+impl Savestate for ChrSet {
+    fn save(&self, state: &mut StateWriter) {
+        (*self == ChrSet::Background).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut background = false;
+        background.load(state)?;
+
+        *self = if background { ChrSet::Background } else { ChrSet::Sprites };
+        Ok(())
+    }
+}
+
+pub struct Mmc5 {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+    exram: [Byte; EXRAM_SIZE],
+
+    prg_mode: Byte,
+    prg_regs: [Byte; 5],
+
+    chr_mode: Byte,
+    chr_upper: Word,
+    chr_sprite_regs: [Word; 8],
+    chr_bg_regs: [Word; 4],
+    chr_last_set: ChrSet,
+
+    chr_sprite_banks: [usize; 8],
+    chr_bg_banks: [usize; 8],
+
+    ram_protect: [Byte; 2],
+
+    exram_mode: Byte,
+    nt_mapping: Byte,
+    fill_tile: Byte,
+    fill_attribute: Byte,
+
+    split_control: Byte,
+    split_scroll: Byte,
+    split_page: Byte,
+
+    multiplicand: Byte,
+    multiplier: Byte,
+
+    irq_compare: Byte,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    large_sprites: bool,
+    rendering: bool,
+
+    in_frame: bool,
+    scanline: Byte,
+    idle_cycles: u8,
+
+    last_addr: Option<Word>,
+    repeats: u8,
+    fetch_index: u16,
+
+    tile_split: bool,
+    tile_attribute: Byte,
+
+    audio: Mmc5Audio,
+}
+
+savestate!(Mmc5 {
+    chr, prg_ram, exram, prg_mode, prg_regs, chr_mode, chr_upper, chr_sprite_regs,
+    chr_bg_regs, chr_last_set, chr_sprite_banks, chr_bg_banks, ram_protect, exram_mode,
+    nt_mapping, fill_tile, fill_attribute, split_control, split_scroll, split_page,
+    multiplicand, multiplier, irq_compare, irq_enabled, irq_pending, large_sprites,
+    rendering, in_frame, scanline, idle_cycles, last_addr, repeats, fetch_index,
+    tile_split, tile_attribute, audio,
+});
+
+impl Mapper for Mmc5 {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x5010 => self.audio.pcm_status(),
+            0x5015 => self.audio.status(),
+
+            0x5204 => (self.irq_pending as Byte) << 7 | (self.in_frame as Byte) << 6,
+            0x5205 => self.product() as Byte,
+            0x5206 => (self.product() >> 8) as Byte,
+
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[addr as usize - 0x5C00],
+
+            0x6000..=0x7FFF => self.ram_read(self.prg_regs[0] as usize, addr),
+            0x8000..=0xFFFF => {
+                let slot = (addr as usize - 0x8000) / PRG_BANK_8K;
+                let (rom, bank) = self.prg_slot(slot);
+
+                if rom {
+                    let offset = bank_offset(bank, PRG_BANK_8K, self.prg.len());
+                    self.prg[offset + (addr as usize & (PRG_BANK_8K - 1))]
+                } else {
+                    self.ram_read(bank, addr)
+                }
+            }
+
+            _ => 0,
+        }
+    }
+
+    fn cpu_read(&mut self, addr: Word) -> Byte {
+        let data = self.cpu_peek(addr);
+
+        match addr {
+            0x5010 => self.audio.acknowledge_pcm(),
+            0x5204 => self.irq_pending = false,
+            0x8000..=0xBFFF => self.audio.snoop_read(data),
+
+            // Fetching the NMI vector means the PPU has entered vblank
+            0xFFFA | 0xFFFB => self.leave_frame(),
+
+            _ => {}
+        }
+
+        data
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        match addr {
+            0x5000..=0x5015 => self.audio.write(addr, data),
+
+            0x5100 => {
+                self.prg_mode = data & 0x03;
+            }
+            0x5101 => {
+                self.chr_mode = data & 0x03;
+                self.update_chr_banks();
+            }
+
+            0x5102 => self.ram_protect[0] = data & 0x03,
+            0x5103 => self.ram_protect[1] = data & 0x03,
+
+            0x5104 => self.exram_mode = data & 0x03,
+            0x5105 => self.nt_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = (data & 0x03) * 0x55,
+
+            0x5113..=0x5117 => self.prg_regs[addr as usize - 0x5113] = data,
+
+            0x5120..=0x5127 => {
+                self.chr_sprite_regs[addr as usize - 0x5120] = self.chr_upper | data as Word;
+                self.chr_last_set = ChrSet::Sprites;
+                self.update_chr_banks();
+            }
+            0x5128..=0x512B => {
+                self.chr_bg_regs[addr as usize - 0x5128] = self.chr_upper | data as Word;
+                self.chr_last_set = ChrSet::Background;
+                self.update_chr_banks();
+            }
+            0x5130 => self.chr_upper = ((data & 0x03) as Word) << 8,
+
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_page = data,
+
+            0x5203 => self.irq_compare = data,
+            0x5204 => self.irq_enabled = (data & 0x80) != 0,
+
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+
+            0x5C00..=0x5FFF => {
+                let offset = addr as usize - 0x5C00;
+
+                match self.exram_mode {
+                    0 | 1 => self.exram[offset] = if self.in_frame { data } else { 0 },
+                    2 => self.exram[offset] = data,
+                    _ => {}
+                }
+            }
+
+            0x6000..=0x7FFF => self.ram_write(self.prg_regs[0] as usize, addr, data),
+            0x8000..=0xDFFF => {
+                let (rom, bank) = self.prg_slot((addr as usize - 0x8000) / PRG_BANK_8K);
+                if !rom {
+                    self.ram_write(bank, addr, data);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn snoop_write(&mut self, addr: Word, data: Byte) {
+        match addr & 0xE007 {
+            0x2000 => self.large_sprites = (data & 0x20) != 0,
+            0x2001 => {
+                self.rendering = (data & 0x18) != 0;
+                if !self.rendering {
+                    self.leave_frame();
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        let index = self.observe_fetch(addr);
+        let background = self.background_fetch(index);
+
+        let offset = if background && self.tile_split {
+            let fine_y = self.split_y(index) as usize & 0x07;
+            let page = bank_offset(self.split_page as usize, 0x1000, self.chr.len());
+
+            page + (addr as usize & 0x0FF8) + fine_y
+        } else if background && self.exram_mode == 1 {
+            let bank = (self.chr_upper as usize >> 2) | (self.tile_attribute & 0x3F) as usize;
+            bank_offset(bank, 0x1000, self.chr.len()) + (addr as usize & 0x0FFF)
+        } else {
+            let set = if self.large_sprites && self.in_frame {
+                if (SPRITE_FETCH_BEGIN..PREFETCH_BEGIN).contains(&index) {
+                    ChrSet::Sprites
+                } else {
+                    ChrSet::Background
+                }
+            } else {
+                self.chr_last_set
+            };
+
+            let banks = match set {
+                ChrSet::Sprites => &self.chr_sprite_banks,
+                ChrSet::Background => &self.chr_bg_banks,
+            };
+
+            let slot = (addr as usize & 0x1FFF) / CHR_BANK_1K;
+            banks[slot] + (addr as usize & (CHR_BANK_1K - 1))
+        };
+
+        self.chr.read(offset)
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        let slot = (addr as usize & 0x1FFF) / CHR_BANK_1K;
+        self.chr.write(self.chr_sprite_banks[slot] + (addr as usize & (CHR_BANK_1K - 1)), data);
+    }
+
+    fn nametable_read(&mut self, addr: Word) -> Option<Byte> {
+        let index = self.observe_nametable(addr);
+        let offset = (addr & 0x03FF) as usize;
+        let attribute = offset >= 0x3C0;
+
+        if self.background_fetch(index) {
+            let tile = self.fetch_tile(index);
+
+            if !attribute {
+                self.tile_split = self.in_split(tile);
+                self.tile_attribute = self.exram[offset];
+
+                if self.tile_split {
+                    let row = self.split_y(index) as usize / 8;
+                    return Some(self.exram[row * 32 + tile as usize]);
+                }
+            } else if self.tile_split {
+                let row = self.split_y(index) as usize / 8;
+                let attr = self.exram[0x3C0 + (row / 4) * 8 + tile as usize / 4];
+                let shift = ((row & 0x02) << 1) | (tile as usize & 0x02);
+
+                return Some(((attr >> shift) & 0x03) * 0x55);
+            } else if self.exram_mode == 1 {
+                return Some((self.tile_attribute >> 6) * 0x55);
+            }
+        }
+
+        match self.nt_source(addr) {
+            2 if self.exram_mode <= 1 => Some(self.exram[offset]),
+            2 => Some(0),
+
+            3 if attribute => Some(self.fill_attribute),
+            3 => Some(self.fill_tile),
+
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, addr: Word, data: Byte) -> bool {
+        match self.nt_source(addr) {
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[(addr & 0x03FF) as usize] = data;
+                }
+                true
+            }
+
+            3 => true,
+            _ => false,
+        }
+    }
+
+    // ExRAM and fill-mode slots are served through `nametable_read`
+    fn mirroring(&self) -> Mirroring {
+        let page = |slot: Byte| (self.nt_mapping >> (slot * 2)) & 0x01;
+        Mirroring::Mapped([page(0), page(1), page(2), page(3)])
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+
+    fn cpu_tick(&mut self) {
+        self.audio.tick();
+
+        if self.in_frame {
+            self.idle_cycles += 1;
+            if self.idle_cycles >= IDLE_CYCLES_OUT_OF_FRAME {
+                self.leave_frame();
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_pending && self.irq_enabled) || self.audio.irq()
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Mmc5 {
+    #[inline(always)]
+    fn product(&self) -> Word {
+        self.multiplicand as Word * self.multiplier as Word
+    }
+
+    #[inline(always)]
+    fn nt_source(&self, addr: Word) -> Byte {
+        let slot = (addr >> 10) & 0x03;
+        (self.nt_mapping >> (slot * 2)) & 0x03
+    }
+
+    fn leave_frame(&mut self) {
+        self.in_frame = false;
+        self.last_addr = None;
+        self.repeats = 0;
+        self.irq_pending = false;
+    }
+
+    fn observe_fetch(&mut self, addr: Word) -> u16 {
+        let index = self.fetch_index;
+
+        self.idle_cycles = 0;
+        self.fetch_index = index.saturating_add(1);
+
+        if self.last_addr == Some(addr) {
+            self.repeats = self.repeats.saturating_add(1);
+        } else {
+            self.repeats = 0;
+        }
+        self.last_addr = Some(addr);
+
+        index
+    }
+
+    // Three reads of the same nametable address in a row only happen
+    // at the boundary of two rendered scanlines, the PPU's dummy fetches
+    // at dots 337 and 339 and the first tile fetch at dot 1. The third
+    // read is index 0, the first tile of the new line
+    fn observe_nametable(&mut self, addr: Word) -> u16 {
+        let index = self.observe_fetch(addr);
+
+        if self.repeats < 2 {
+            return index;
+        }
+
+        self.repeats = 0;
+        self.fetch_index = 1;
+
+        if !self.in_frame {
+            self.in_frame = true;
+            self.scanline = 0;
+        } else {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_compare {
+                self.irq_pending = true;
+            }
+        }
+
+        0
+    }
+
+    #[inline(always)]
+    fn background_fetch(&self, index: u16) -> bool {
+        self.in_frame
+            && (index < SPRITE_FETCH_BEGIN || (PREFETCH_BEGIN..PREFETCH_END).contains(&index))
+    }
+
+    #[inline(always)]
+    fn fetch_tile(&self, index: u16) -> u16 {
+        if index < SPRITE_FETCH_BEGIN {
+            (index / 4 + 2) & 0x1F
+        } else {
+            (index - PREFETCH_BEGIN) / 4
+        }
+    }
+
+    fn in_split(&self, tile: u16) -> bool {
+        if (self.split_control & 0x80) == 0 || self.exram_mode > 1 {
+            return false;
+        }
+
+        let threshold = (self.split_control & 0x1F) as u16;
+        if (self.split_control & 0x40) != 0 {
+            tile >= threshold
+        } else {
+            tile < threshold
+        }
+    }
+
+    fn split_y(&self, index: u16) -> u16 {
+        // Prefetched tiles belong to the next scanline
+        let scanline = if index >= PREFETCH_BEGIN {
+            self.scanline as u16 + 1
+        } else {
+            self.scanline as u16
+        };
+
+        (scanline + self.split_scroll as u16) % 240
+    }
+
+    fn prg_slot(&self, slot: usize) -> (bool, usize) {
+        let (register, units) = match (self.prg_mode, slot) {
+            (0, _) => (4, 4),
+
+            (1, 0 | 1) => (2, 2),
+            (1, _) => (4, 2),
+
+            (2, 0 | 1) => (2, 2),
+            (2, 2) => (3, 1),
+            (2, _) => (4, 1),
+
+            (_, slot) => (slot + 1, 1),
+        };
+
+        let value = self.prg_regs[register];
+        let rom = register == 4 || (value & 0x80) != 0;
+        let bank = ((value & 0x7F) as usize & !(units - 1)) + slot % units;
+
+        (rom, bank)
+    }
+
+    fn ram_read(&self, bank: usize, addr: Word) -> Byte {
+        if self.prg_ram.is_empty() {
+            return 0;
+        }
+
+        let offset = bank_offset(bank & 0x07, PRG_BANK_8K, self.prg_ram.len());
+        self.prg_ram.read(offset + (addr as usize & (PRG_BANK_8K - 1)))
+    }
+
+    fn ram_write(&mut self, bank: usize, addr: Word, data: Byte) {
+        if self.prg_ram.is_empty() || self.ram_protect != [0x02, 0x01] {
+            return;
+        }
+
+        let offset = bank_offset(bank & 0x07, PRG_BANK_8K, self.prg_ram.len());
+        self.prg_ram.write(offset + (addr as usize & (PRG_BANK_8K - 1)), data);
+    }
+
+    fn update_chr_banks(&mut self) {
+        let per_bank = 8 >> self.chr_mode;
+        let size = CHR_BANK_1K * per_bank;
+        let len = self.chr.len();
+
+        for slot in 0..8 {
+            let within = (slot % per_bank) * CHR_BANK_1K;
+
+            let sprite_reg = self.chr_sprite_regs[(slot / per_bank) * per_bank + per_bank - 1];
+            self.chr_sprite_banks[slot] = bank_offset(sprite_reg as usize, size, len) + within;
+
+            let bg_slot = slot & 0x03;
+            let bg_reg = self.chr_bg_regs[((bg_slot / per_bank) * per_bank + per_bank - 1).min(3)];
+            self.chr_bg_banks[slot] = bank_offset(bg_reg as usize, size, len) + within;
+        }
+    }
+
+    pub fn new(rom: Rom) -> Self {
+        let header = rom.header;
+        let ram_size = match header.format {
+            RomFormat::INes => 0x10000,
+            RomFormat::Nes2 => header.prg_ram_size + header.prg_nvram_size,
+        };
+
+        let mut mapper = Self {
+            chr: ChrMemory::from_rom(rom.chr, &header),
+            prg: rom.prg,
+            prg_ram: PrgRam::new(ram_size),
+            exram: [0; EXRAM_SIZE],
+
+            prg_mode: 3,
+            prg_regs: [0, 0, 0, 0, 0xFF],
+
+            chr_mode: 3,
+            chr_upper: 0,
+            chr_sprite_regs: [0, 1, 2, 3, 4, 5, 6, 7],
+            chr_bg_regs: [0, 1, 2, 3],
+            chr_last_set: ChrSet::Sprites,
+
+            chr_sprite_banks: [0; 8],
+            chr_bg_banks: [0; 8],
+
+            ram_protect: [0; 2],
+
+            exram_mode: 0,
+            nt_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+
+            split_control: 0,
+            split_scroll: 0,
+            split_page: 0,
+
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+
+            large_sprites: false,
+            rendering: false,
+
+            in_frame: false,
+            scanline: 0,
+            idle_cycles: 0,
+
+            last_addr: None,
+            repeats: 0,
+            fetch_index: 0,
+
+            tile_split: false,
+            tile_attribute: 0,
+
+            audio: Mmc5Audio::new(),
+        };
+
+        mapper.update_chr_banks();
+        mapper
+    }
+}
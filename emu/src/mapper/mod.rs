@@ -0,0 +1,240 @@
+use {
+    crate::{
+        mem::*,
+        error::*,
+        cartridge::*,
+        state::*,
+    },
+};
+
+pub mod nrom;
+pub mod uxrom;
+pub mod cnrom;
+pub mod axrom;
+pub mod mmc3;
+pub mod mmc5;
+pub mod vrc;
+
+#[cfg(test)]
+mod tests;
+
+pub const PRG_BANK_8K: usize  = 0x2000;
+pub const PRG_BANK_16K: usize = 0x4000;
+pub const PRG_BANK_32K: usize = 0x8000;
+
+pub const CHR_BANK_1K: usize  = 0x0400;
+pub const CHR_BANK_8K: usize  = 0x2000;
+
+// Registers and RAM are saved through `Savestate`, ROM is left out
+pub trait Mapper: Savestate {
+    fn cpu_peek(&self, addr: Word) -> Byte;
+    fn cpu_write(&mut self, addr: Word, data: Byte);
+
+    fn ppu_read(&mut self, addr: Word) -> Byte;
+    fn ppu_write(&mut self, addr: Word, data: Byte);
+
+    fn mirroring(&self) -> Mirroring;
+
+    fn prg_ram(&self) -> &PrgRam;
+    fn prg_ram_mut(&mut self) -> &mut PrgRam;
+
+    fn chr(&self) -> &ChrMemory;
+    fn chr_mut(&mut self) -> &mut ChrMemory;
+
+    fn cpu_read(&mut self, addr: Word) -> Byte {
+        self.cpu_peek(addr)
+    }
+
+    // Sees CPU writes below the cartridge space, e.g. PPU registers
+    fn snoop_write(&mut self, _addr: Word, _data: Byte) {}
+
+    // Lets the board substitute nametable bytes instead of CIRAM
+    fn nametable_read(&mut self, _addr: Word) -> Option<Byte> {
+        None
+    }
+
+    fn nametable_write(&mut self, _addr: Word, _data: Byte) -> bool {
+        false
+    }
+
+    // Called on every CPU (M2) cycle
+    fn cpu_tick(&mut self) {}
+
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Expansion audio, in the same scale as the APU mixer output
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+}
+
+pub struct PrgRam {
+    data: Box<[Byte]>,
+}
+
+savestate!(PrgRam { data });
+
+pub struct ChrMemory {
+    data: Box<[Byte]>,
+    writable: bool,
+}
+
+// Only CHR-RAM is state, ROM comes back with the cartridge
+impl Savestate for ChrMemory {
+    fn save(&self, state: &mut StateWriter) {
+        if self.writable {
+            self.data.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        if self.writable {
+            self.data.load(state)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PrgRam {
+    #[inline(always)]
+    pub fn read(&self, offset: usize) -> Byte {
+        if self.data.is_empty() {
+            0
+        } else {
+            self.data[offset % self.data.len()]
+        }
+    }
+
+    #[inline(always)]
+    pub fn write(&mut self, offset: usize, data: Byte) {
+        if !self.data.is_empty() {
+            let len = self.data.len();
+            self.data[offset % len] = data;
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[Byte] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Byte] {
+        &mut self.data
+    }
+
+    pub fn from_header(header: &RomHeader) -> Self {
+        Self::new(header.prg_ram_size + header.prg_nvram_size)
+    }
+
+    // Discrete boards only carry a RAM chip when the header really
+    // asks for one, iNES defaults to 8K for everything otherwise
+    pub fn optional(header: &RomHeader) -> Self {
+        match header.format {
+            RomFormat::Nes2 => Self::from_header(header),
+            RomFormat::INes if header.battery => Self::new(header.prg_nvram_size),
+            RomFormat::INes => Self::new(0),
+        }
+    }
+
+    pub fn new(size: usize) -> Self {
+        Self { data: vec![0; size].into_boxed_slice() }
+    }
+}
+
+impl ChrMemory {
+    #[inline(always)]
+    pub fn read(&self, offset: usize) -> Byte {
+        self.data[offset % self.data.len()]
+    }
+
+    #[inline(always)]
+    pub fn write(&mut self, offset: usize, data: Byte) {
+        if self.writable {
+            let len = self.data.len();
+            self.data[offset % len] = data;
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // Empty for CHR-ROM
+    pub fn ram(&self) -> &[Byte] {
+        if self.writable { &self.data } else { &[] }
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [Byte] {
+        if self.writable { &mut self.data } else { &mut [] }
+    }
+
+    pub fn from_rom(chr: Vec<Byte>, header: &RomHeader) -> Self {
+        if chr.is_empty() {
+            let size = (header.chr_ram_size + header.chr_nvram_size)
+                .max(CHR_BANK_8K);
+
+            Self { data: vec![0; size].into_boxed_slice(), writable: true }
+        } else {
+            Self { data: chr.into_boxed_slice(), writable: false }
+        }
+    }
+}
+
+#[inline(always)]
+pub fn bank_offset(
+    bank: usize,
+    size: usize,
+    len: usize,
+) -> usize {
+    let count = (len / size).max(1);
+    (bank % count) * size
+}
+
+// NES 2.0 submapper 2 marks the discrete boards where the ROM drives
+// the data bus together with the CPU on register writes
+#[inline(always)]
+pub fn has_bus_conflicts(header: &RomHeader) -> bool {
+    header.format == RomFormat::Nes2 && header.submapper == 2
+}
+
+#[inline(always)]
+pub fn last_bank(size: usize, len: usize) -> usize {
+    (len / size).max(1) - 1
+}
+
+pub fn create(rom: Rom) -> Result<Box<dyn Mapper>, RomError> {
+    let mapper: Box<dyn Mapper> = match rom.header.mapper {
+        0 => Box::new(nrom::Nrom::new(rom)),
+        2 => Box::new(uxrom::Uxrom::new(rom)),
+        3 => Box::new(cnrom::Cnrom::new(rom)),
+        4 => Box::new(mmc3::Mmc3::new(rom)),
+        5 => Box::new(mmc5::Mmc5::new(rom)),
+        7 => Box::new(axrom::Axrom::new(rom)),
+
+        21 | 22 | 23 | 25 => Box::new(vrc::vrc24::Vrc24::new(rom)),
+        24 | 26 => Box::new(vrc::vrc6::Vrc6::new(rom)),
+        85 => Box::new(vrc::vrc7::Vrc7::new(rom)),
+
+        unknown => return Err(RomError::UnsupportedMapper(unknown)),
+    };
+
+    Ok(mapper)
+}
@@ -0,0 +1,66 @@
+use {
+    super::*,
+};
+
+pub struct Nrom {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    mirroring: Mirroring,
+}
+
+savestate!(Nrom { chr, prg_ram });
+
+impl Mapper for Nrom {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x8000..=0xFFFF => self.prg[(addr as usize - 0x8000) % self.prg.len()],
+            0x6000..=0x7FFF => self.prg_ram.read(addr as usize - 0x6000),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if (0x6000..0x8000).contains(&addr) {
+            self.prg_ram.write(addr as usize - 0x6000, data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        Self { chr: ChrMemory::from_rom(rom.chr, &rom.header),
+               prg_ram: PrgRam::optional(&rom.header),
+               mirroring: rom.header.mirroring,
+               prg: rom.prg }
+    }
+}
@@ -0,0 +1,907 @@
+use {
+    super::{
+        *,
+        mmc5::IDLE_CYCLES_OUT_OF_FRAME,
+    },
+    crate::{
+        cpu::*,
+        ppu::*,
+    },
+};
+
+// Every 16K PRG bank and every 8K CHR bank is filled with its own index
+fn ines(
+    mapper: u8,
+    prg_banks: u8,
+    chr_banks: u8,
+    flags6: u8,
+) -> Vec<u8> {
+    let mut raw = vec![
+        b'N', b'E', b'S', 0x1A,
+        prg_banks, chr_banks,
+        flags6 | (mapper << 4), mapper & 0xF0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    for bank in 0..prg_banks {
+        raw.extend(std::iter::repeat_n(bank, PRG_BANK_16K));
+    }
+
+    for bank in 0..chr_banks {
+        raw.extend(std::iter::repeat_n(bank, CHR_BANK_8K));
+    }
+
+    raw
+}
+
+fn nes2(
+    mapper: u8,
+    submapper: u8,
+    prg_banks: u8,
+    chr_banks: u8,
+) -> Vec<u8> {
+    let mut raw = ines(mapper, prg_banks, chr_banks, 0);
+    raw[7] |= 0x08;
+    raw[8] = submapper << 4;
+
+    raw
+}
+
+// CARTRIDGE
+
+#[test]
+fn ines_header() {
+    let header = RomHeader::parse(&ines(2, 8, 0, 0x03)).unwrap();
+
+    assert_eq!(header.format, RomFormat::INes);
+    assert_eq!(header.mapper, 2);
+    assert_eq!(header.prg_rom_size, 8 * PRG_BANK_16K);
+    assert_eq!(header.chr_rom_size, 0);
+    assert_eq!(header.chr_ram_size, CHR_BANK_8K);
+    assert_eq!(header.mirroring, Mirroring::Vertical);
+    assert!(header.battery);
+}
+
+#[test]
+fn nes2_header() {
+    let mut raw = nes2(7, 2, 2, 0);
+    raw[10] = 0x70;  // 8K PRG-NVRAM
+    raw[11] = 0x07;  // 8K CHR-RAM
+
+    let header = RomHeader::parse(&raw).unwrap();
+
+    assert_eq!(header.format, RomFormat::Nes2);
+    assert_eq!(header.mapper, 7);
+    assert_eq!(header.submapper, 2);
+    assert_eq!(header.prg_nvram_size, 0x2000);
+    assert_eq!(header.chr_ram_size, 0x2000);
+}
+
+#[test]
+fn invalid_roms() {
+    let mut raw = ines(0, 2, 1, 0);
+    assert_eq!(Rom::parse(&raw[..raw.len() - 1]).err(), Some(RomError::Truncated));
+
+    raw[0] = b'X';
+    assert_eq!(Rom::parse(&raw).err(), Some(RomError::InvalidMagic));
+
+    let raw = ines(0xF0, 2, 1, 0);
+    assert_eq!(
+        Cartridge::from_bytes(&raw).err(),
+        Some(RomError::UnsupportedMapper(0xF0))
+    );
+
+    // No PRG at all would panic on the first read
+    for mapper in [0, 2, 7] {
+        assert_eq!(Rom::parse(&ines(mapper, 0, 1, 0)).err(), Some(RomError::UnsupportedPrgSize(0)));
+    }
+
+    // NES 2.0 exponent sizes that don't fit in a usize
+    let mut raw = nes2(0, 0, 2, 1);
+    raw[4] = 0xFF;
+    raw[9] = 0x0F;
+    assert_eq!(Rom::parse(&raw).err(), Some(RomError::Truncated));
+
+    raw[4] = 0x01;
+    assert_eq!(Rom::parse(&raw).err(), Some(RomError::UnsupportedPrgSize(3)));
+
+    raw[4] = 0x02;
+    raw[5] = 0xFF;
+    raw[9] = 0xF2;
+    assert_eq!(Rom::parse(&raw).err(), Some(RomError::Truncated));
+}
+
+#[test]
+fn battery_ram() {
+    // Without the battery bit a plain NROM board has no RAM at all
+    let mut cartridge = Cartridge::from_bytes(&ines(0, 2, 1, 0)).unwrap();
+    cartridge.cpu_write(0x6000, 0x42);
+    assert_eq!(cartridge.cpu_read(0x6000), 0);
+    assert!(cartridge.battery_ram().is_empty());
+
+    let mut cartridge = Cartridge::from_bytes(&ines(0, 2, 1, 0x02)).unwrap();
+    cartridge.cpu_write(0x6123, 0x42);
+    assert_eq!(cartridge.cpu_read(0x6123), 0x42);
+
+    let saved = cartridge.battery_ram();
+    assert_eq!(saved.len(), PRG_BANK_8K);
+    assert_eq!(saved[0x123], 0x42);
+
+    cartridge.set_battery_ram(&[0x11, 0x22]);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x11);
+    assert_eq!(cartridge.cpu_read(0x6001), 0x22);
+    assert_eq!(cartridge.cpu_read(0x6123), 0x42);
+}
+
+#[test]
+fn cartridge_power() {
+    let mut cartridge = Cartridge::from_bytes(&ines(2, 4, 0, 0x02)).unwrap();
+    cartridge.cpu_write(0xC000, 2);
+    cartridge.cpu_write(0x6000, 0x42);
+    cartridge.ppu_write(0x0010, 0x99);
+    assert_eq!(cartridge.cpu_read(0x8000), 2);
+
+    // Only the battery RAM makes it through
+    cartridge.power();
+    assert_eq!(cartridge.cpu_read(0x8000), 0);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+    assert_eq!(cartridge.ppu_read(0x0010), 0);
+}
+
+#[test]
+fn battery_chr_nvram() {
+    let mut raw = nes2(2, 0, 2, 0);
+    raw[6] |= 0x02;
+    raw[10] = 0x70;  // 8K PRG-NVRAM
+    raw[11] = 0x70;  // 8K CHR-NVRAM
+
+    let mut cartridge = Cartridge::from_bytes(&raw).unwrap();
+    cartridge.cpu_write(0x7FFF, 0x01);
+    cartridge.ppu_write(0x0000, 0x02);
+
+    let saved = cartridge.battery_ram();
+    assert_eq!(saved.len(), 2 * 0x2000);
+    assert_eq!(saved[0x1FFF], 0x01);
+    assert_eq!(saved[0x2000], 0x02);
+}
+
+#[test]
+fn battery_save_file() {
+    let dir = std::env::temp_dir().join(format!("emu-battery-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let rom = dir.join("game.nes");
+    std::fs::write(&rom, ines(4, 2, 1, 0x02)).unwrap();
+
+    let mut cartridge = Cartridge::load(&rom).unwrap();
+    assert_eq!(cartridge.save_path(), Some(dir.join("game.sav").as_path()));
+
+    // Dropping a cartridge doesn't write, and neither does an unchanged flush
+    cartridge.flush().unwrap();
+    drop(Cartridge::load(&rom).unwrap());
+    assert!(!dir.join("game.sav").exists());
+
+    cartridge.cpu_write(0x6010, 0x5A);
+    drop(cartridge);
+    assert!(!dir.join("game.sav").exists());
+
+    let mut cartridge = Cartridge::load(&rom).unwrap();
+    cartridge.cpu_write(0x6010, 0x5A);
+    cartridge.flush().unwrap();
+
+    let saved = std::fs::read(dir.join("game.sav")).unwrap();
+    assert_eq!(saved[0x10], 0x5A);
+
+    let mut cartridge = Cartridge::load(&rom).unwrap();
+    assert_eq!(cartridge.cpu_read(0x6010), 0x5A);
+
+    // A file that can't be written is reported
+    cartridge.cpu_write(0x6010, 0xA5);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(cartridge.flush().is_err());
+}
+
+// UxROM
+
+#[test]
+fn uxrom_switch() {
+    let mut cartridge = Cartridge::from_bytes(&ines(2, 8, 0, 0)).unwrap();
+
+    assert_eq!(cartridge.cpu_read(0x8000), 0);
+    assert_eq!(cartridge.cpu_read(0xC000), 7);
+
+    cartridge.cpu_write(0x8000, 5);
+    assert_eq!(cartridge.cpu_read(0xBFFF), 5);
+    assert_eq!(cartridge.cpu_read(0xFFFF), 7);
+
+    // CHR-RAM is writable
+    cartridge.ppu_write(0x1234, 0xAB);
+    assert_eq!(cartridge.ppu_read(0x1234), 0xAB);
+}
+
+#[test]
+fn uxrom_bus_conflict() {
+    let mut cartridge = Cartridge::from_bytes(&nes2(2, 2, 8, 0)).unwrap();
+
+    cartridge.cpu_write(0xC000, 0x06);  // ROM holds 0x07 there
+    assert_eq!(cartridge.cpu_read(0x8000), 6);
+
+    cartridge.cpu_write(0x8000, 0x03);  // ROM holds 0x06 there
+    assert_eq!(cartridge.cpu_read(0x8000), 2);
+}
+
+// CNROM
+
+#[test]
+fn cnrom_switch() {
+    let mut cartridge = Cartridge::from_bytes(&ines(3, 1, 4, 0)).unwrap();
+
+    assert_eq!(cartridge.ppu_read(0x0000), 0);
+    assert_eq!(cartridge.cpu_read(0xC000), cartridge.cpu_read(0x8000));
+
+    cartridge.cpu_write(0x8000, 3);
+    assert_eq!(cartridge.ppu_read(0x1FFF), 3);
+
+    // CHR-ROM is read only
+    cartridge.ppu_write(0x0000, 0xFF);
+    assert_eq!(cartridge.ppu_read(0x0000), 3);
+}
+
+// AxROM
+
+#[test]
+fn axrom_switch() {
+    let mut cartridge = Cartridge::from_bytes(&ines(7, 8, 0, 0)).unwrap();
+
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenLower);
+
+    cartridge.cpu_write(0x8000, 0x12);
+    assert_eq!(cartridge.cpu_read(0x8000), 4);
+    assert_eq!(cartridge.cpu_read(0xFFFF), 5);
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn cpu_switches_bank() {
+    let mut raw = ines(2, 4, 0, 0);
+    let last = raw.len() - PRG_BANK_16K;
+
+    raw[last..last + 8].copy_from_slice(&[
+        0xA9, 0x02,        // lda 0x02 (imm)
+        0x8D, 0x00, 0x80,  // sta 0x8000 (abs)
+        0xAD, 0x00, 0x80,  // lda 0x8000 (abs)
+    ]);
+    raw[last + 8] = 0x00;  // brk
+
+    // Reset vector -> 0xC000
+    raw[last + 0x3FFC] = 0x00;
+    raw[last + 0x3FFD] = 0xC0;
+
+    let mut cpu = Cpu::default();
+    cpu.load_cartridge(Cartridge::from_bytes(&raw).unwrap());
+
+    assert_eq!(cpu.pc, 0xC000);
+    assert!(cpu.exec_until_brk().is_none());
+    assert_eq!(cpu.acc, 0x02);
+}
+
+// MMC3
+
+fn mmc3_scanline(cartridge: &mut Cartridge) {
+    cartridge.ppu_read(0x0000);
+    for _ in 0..4 {
+        cartridge.cpu_tick();
+    }
+    cartridge.ppu_read(0x1000);
+
+    // Sprite fetches toggle A12 too quickly to be counted
+    cartridge.ppu_read(0x0000);
+    cartridge.ppu_read(0x1000);
+}
+
+#[test]
+fn mmc3_prg_modes() {
+    // 16 8K banks, 8K bank N holds N / 2
+    let mut cartridge = Cartridge::from_bytes(&ines(4, 8, 2, 0)).unwrap();
+
+    cartridge.cpu_write(0x8000, 0x06);
+    cartridge.cpu_write(0x8001, 0x04);
+    cartridge.cpu_write(0x8000, 0x07);
+    cartridge.cpu_write(0x8001, 0x08);
+
+    assert_eq!(cartridge.cpu_read(0x8000), 2);
+    assert_eq!(cartridge.cpu_read(0xA000), 4);
+    assert_eq!(cartridge.cpu_read(0xC000), 7);
+    assert_eq!(cartridge.cpu_read(0xE000), 7);
+
+    cartridge.cpu_write(0x8000, 0x46);
+    assert_eq!(cartridge.cpu_read(0x8000), 7);
+    assert_eq!(cartridge.cpu_read(0xC000), 2);
+}
+
+#[test]
+fn mmc3_chr_inversion() {
+    // 16 1K banks, 1K bank N holds N / 8
+    let mut cartridge = Cartridge::from_bytes(&ines(4, 2, 2, 0)).unwrap();
+
+    cartridge.cpu_write(0x8000, 0x00);
+    cartridge.cpu_write(0x8001, 0x09);  // R0 = 2K bank at 8
+    cartridge.cpu_write(0x8000, 0x02);
+    cartridge.cpu_write(0x8001, 0x0F);  // R2 = 1K bank 15
+
+    assert_eq!(cartridge.ppu_read(0x0000), 1);
+    assert_eq!(cartridge.ppu_read(0x1000), 1);
+    assert_eq!(cartridge.ppu_read(0x0800), 0);
+
+    cartridge.cpu_write(0x8000, 0x80);
+    assert_eq!(cartridge.ppu_read(0x1000), 1);
+    assert_eq!(cartridge.ppu_read(0x0000), 1);
+    assert_eq!(cartridge.ppu_read(0x1800), 0);
+}
+
+#[test]
+fn mmc3_mirroring_and_ram() {
+    let mut cartridge = Cartridge::from_bytes(&ines(4, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0xA000, 0x01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    cartridge.cpu_write(0xA000, 0x00);
+    assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+
+    cartridge.cpu_write(0x6000, 0x42);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+
+    cartridge.cpu_write(0xA001, 0xC0);  // write protect
+    cartridge.cpu_write(0x6000, 0x24);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+
+    cartridge.cpu_write(0xA001, 0x00);  // chip disabled
+    assert_eq!(cartridge.cpu_read(0x6000), 0x00);
+}
+
+#[test]
+fn mmc3_save_state() {
+    let raw = ines(4, 8, 2, 0);
+    let mut cartridge = Cartridge::from_bytes(&raw).unwrap();
+
+    cartridge.cpu_write(0x8000, 0x46);
+    cartridge.cpu_write(0x8001, 0x04);
+    cartridge.cpu_write(0xA000, 0x01);
+    cartridge.cpu_write(0x6010, 0x42);
+    cartridge.cpu_write(0xC000, 5);
+
+    let mut state = StateWriter::new();
+    cartridge.save(&mut state);
+    let state = state.into_inner();
+
+    let mut restored = Cartridge::from_bytes(&raw).unwrap();
+    restored.load(&mut StateReader::new(&state)).unwrap();
+
+    assert_eq!(restored.cpu_read(0xC000), 2);
+    assert_eq!(restored.cpu_read(0x8000), 7);
+    assert_eq!(restored.cpu_read(0x6010), 0x42);
+    assert_eq!(restored.mirroring(), Mirroring::Horizontal);
+
+    // Nothing left over, nothing missing
+    assert_eq!(restored.load(&mut StateReader::new(&state[..state.len() - 1])), Err(StateError::Truncated));
+}
+
+#[test]
+fn chr_ram_save_state() {
+    let raw = ines(2, 2, 0, 0);
+    let mut cartridge = Cartridge::from_bytes(&raw).unwrap();
+    cartridge.ppu_write(0x0123, 0x99);
+
+    let mut state = StateWriter::new();
+    cartridge.save(&mut state);
+
+    let mut restored = Cartridge::from_bytes(&raw).unwrap();
+    restored.load(&mut StateReader::new(&state.into_inner())).unwrap();
+    assert_eq!(restored.ppu_read(0x0123), 0x99);
+}
+
+#[test]
+fn mmc3_irq_counter() {
+    let mut cartridge = Cartridge::from_bytes(&ines(4, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0xC000, 2);  // latch
+    cartridge.cpu_write(0xC001, 0);  // reload
+    cartridge.cpu_write(0xE001, 0);  // enable
+
+    mmc3_scanline(&mut cartridge);  // reload -> 2
+    mmc3_scanline(&mut cartridge);  // 1
+    assert!(!cartridge.irq());
+
+    mmc3_scanline(&mut cartridge);  // 0
+    assert!(cartridge.irq());
+
+    cartridge.cpu_write(0xE000, 0);
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn mmc3_raises_cpu_irq() {
+    let mut raw = ines(4, 2, 1, 0);
+    let last = raw.len() - CHR_BANK_8K - PRG_BANK_16K;
+
+    raw[last..last + 4].copy_from_slice(&[
+        0x58,  // cli
+        0xE8,  // inx
+        0xE8,  // inx
+        0x00,  // brk
+    ]);
+    raw[last + 0x10..last + 0x12].copy_from_slice(&[
+        0xC8,  // iny
+        0x00,  // brk
+    ]);
+
+    raw[last + 0x3FFC] = 0x00;  // reset -> 0xC000
+    raw[last + 0x3FFD] = 0xC0;
+    raw[last + 0x3FFE] = 0x10;  // irq -> 0xC010
+    raw[last + 0x3FFF] = 0xC0;
+
+    let mut cpu = Cpu::default();
+    cpu.load_cartridge(Cartridge::from_bytes(&raw).unwrap());
+
+    let cartridge = cpu.mem.cartridge_mut().unwrap();
+    cartridge.cpu_write(0xC000, 0);
+    cartridge.cpu_write(0xC001, 0);
+    cartridge.cpu_write(0xE001, 0);
+    mmc3_scanline(cartridge);
+
+    assert!(cpu.exec_until_brk().is_none());
+    assert_eq!(cpu.x, 0);
+    assert_eq!(cpu.y, 1);
+    assert_eq!(cpu.mem.read_word(0x01FC), 0xC001);
+}
+
+// MMC5
+
+// Replays the PPU fetch pattern of one rendered scanline, nametable
+// fetches go through `nametable_read` exactly like the PPU does
+fn mmc5_scanline(cartridge: &mut Cartridge) -> Vec<u8> {
+    let mut fetched = vec![];
+
+    for tile in 2..34 {
+        fetched.push(cartridge.nametable_read(0x2000 + (tile & 0x1F)).unwrap_or(0));
+        fetched.push(cartridge.nametable_read(0x23C0).unwrap_or(0));
+        fetched.push(cartridge.ppu_read(0x0000));
+        cartridge.ppu_read(0x0008);
+    }
+
+    for _ in 0..8 {
+        cartridge.nametable_read(0x2000);
+        cartridge.nametable_read(0x2000);
+        cartridge.ppu_read(0x1000);
+        cartridge.ppu_read(0x1008);
+    }
+
+    for tile in 0..2 {
+        cartridge.nametable_read(0x2000 + tile);
+        cartridge.nametable_read(0x23C0);
+        cartridge.ppu_read(0x0000);
+        cartridge.ppu_read(0x0008);
+    }
+
+    cartridge.nametable_read(0x2002);
+    cartridge.nametable_read(0x2002);
+
+    fetched
+}
+
+fn mmc5_frame_start(cartridge: &mut Cartridge) {
+    cartridge.nametable_read(0x2002);
+    cartridge.nametable_read(0x2002);
+}
+
+#[test]
+fn mmc5_prg_modes() {
+    // 16 8K banks, 8K bank N holds N / 2
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 8, 1, 0)).unwrap();
+
+    assert_eq!(cartridge.cpu_read(0xE000), 7);
+
+    cartridge.cpu_write(0x5100, 0);
+    cartridge.cpu_write(0x5117, 0x87);
+    assert_eq!(cartridge.cpu_read(0x8000), 2);
+    assert_eq!(cartridge.cpu_read(0xE000), 3);
+
+    cartridge.cpu_write(0x5100, 1);
+    cartridge.cpu_write(0x5115, 0x82);
+    assert_eq!(cartridge.cpu_read(0x8000), 1);
+    assert_eq!(cartridge.cpu_read(0xA000), 1);
+    assert_eq!(cartridge.cpu_read(0xC000), 3);
+}
+
+#[test]
+fn mmc5_prg_ram() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0x5113, 1);
+    cartridge.cpu_write(0x6000, 0x55);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x00);  // protected
+
+    cartridge.cpu_write(0x5102, 0x02);
+    cartridge.cpu_write(0x5103, 0x01);
+    cartridge.cpu_write(0x6000, 0x55);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x55);
+
+    cartridge.cpu_write(0x5113, 0);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x00);
+
+    // RAM bank 1 mapped into $8000 in mode 3
+    cartridge.cpu_write(0x5114, 0x01);
+    assert_eq!(cartridge.cpu_read(0x8000), 0x55);
+}
+
+#[test]
+fn mmc5_chr_sets() {
+    // 64 1K banks, 1K bank N holds N / 8
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 8, 0)).unwrap();
+
+    cartridge.cpu_write(0x5120, 8);
+    cartridge.cpu_write(0x5128, 16);
+    assert_eq!(cartridge.ppu_read(0x0000), 2);
+    assert_eq!(cartridge.ppu_read(0x1000), 2);
+
+    cartridge.cpu_write(0x5120, 8);
+    assert_eq!(cartridge.ppu_read(0x0000), 1);
+
+    // 4K banks
+    cartridge.cpu_write(0x5101, 1);
+    cartridge.cpu_write(0x5127, 9);
+    assert_eq!(cartridge.ppu_read(0x1FFF), 4);
+}
+
+#[test]
+fn mmc5_multiplier() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0x5205, 200);
+    cartridge.cpu_write(0x5206, 3);
+
+    assert_eq!(cartridge.cpu_read(0x5205), 0x58);
+    assert_eq!(cartridge.cpu_read(0x5206), 0x02);
+}
+
+#[test]
+fn mmc5_exram_and_fill() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0x5104, 2);
+    cartridge.cpu_write(0x5C10, 0x42);
+    assert_eq!(cartridge.cpu_read(0x5C10), 0x42);
+
+    cartridge.cpu_write(0x5104, 3);
+    cartridge.cpu_write(0x5C10, 0x24);
+    assert_eq!(cartridge.cpu_read(0x5C10), 0x42);
+
+    // Slot 0 from CIRAM, slot 1 from ExRAM (mode 0 only), slot 2 filled
+    cartridge.cpu_write(0x5104, 0);
+    cartridge.cpu_write(0x5105, 0b00_11_10_00);
+    cartridge.cpu_write(0x5106, 0x77);
+    cartridge.cpu_write(0x5107, 0x02);
+
+    assert_eq!(cartridge.nametable_read(0x2010), None);
+    assert_eq!(cartridge.nametable_read(0x2410), Some(0x42));
+    assert_eq!(cartridge.nametable_read(0x2810), Some(0x77));
+    assert_eq!(cartridge.nametable_read(0x2BC0), Some(0xAA));
+}
+
+#[test]
+fn mmc5_nametable_mapping() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0x5105, 0b01_00_00_01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Mapped([1, 0, 0, 1]));
+    assert_eq!(cartridge.nametable_read(0x2C00), None);
+}
+
+#[test]
+fn mmc5_scanline_irq() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0x5203, 2);
+    cartridge.cpu_write(0x5204, 0x80);
+
+    mmc5_frame_start(&mut cartridge);
+    mmc5_scanline(&mut cartridge);
+    assert_eq!(cartridge.cpu_read(0x5204), 0x40);
+
+    mmc5_scanline(&mut cartridge);
+    assert!(!cartridge.irq());
+
+    mmc5_scanline(&mut cartridge);
+    assert!(cartridge.irq());
+    assert_eq!(cartridge.cpu_read(0x5204), 0xC0);
+    assert!(!cartridge.irq());
+
+    // PPU stops fetching in vblank
+    for _ in 0..IDLE_CYCLES_OUT_OF_FRAME {
+        cartridge.cpu_tick();
+    }
+    assert_eq!(cartridge.cpu_read(0x5204), 0x00);
+}
+
+#[test]
+fn mmc5_scanlines_from_ppu() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+    cartridge.cpu_write(0x5203, 10);
+    cartridge.cpu_write(0x5204, 0x80);
+
+    let mut ppu = Ppu::new();
+    ppu.cpu_write(PPUMASK, MASK_BACKGROUND, None);
+
+    // The first frame starts at line 0 without the pre-render line, the
+    // NMI vector fetch in vblank ends it
+    while ppu.scanline != 241 {
+        ppu.tick(Some(&mut cartridge));
+    }
+    cartridge.cpu_read(0xFFFA);
+
+    while !cartridge.irq() {
+        ppu.tick(Some(&mut cartridge));
+    }
+
+    // Dummy fetches at 337 and 339 and the first one at dot 1 of the
+    // next line read the same nametable byte
+    assert_eq!((ppu.scanline, ppu.dot), (10, 2));
+}
+
+#[test]
+fn mmc5_extended_attributes() {
+    // 4K bank N holds N / 2
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 4, 0)).unwrap();
+
+    cartridge.cpu_write(0x5104, 2);
+    cartridge.cpu_write(0x5C02, 0xC5);  // palette 3, 4K bank 5
+    cartridge.cpu_write(0x5104, 1);
+
+    mmc5_frame_start(&mut cartridge);
+    let fetched = mmc5_scanline(&mut cartridge);
+
+    assert_eq!(&fetched[..3], &[0x00, 0xFF, 0x02]);
+    assert_eq!(&fetched[3..6], &[0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn mmc5_vertical_split() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 4, 0)).unwrap();
+
+    cartridge.cpu_write(0x5104, 2);
+    cartridge.cpu_write(0x5C00 + 8 * 32 + 2, 0x33);  // row 8, tile 2
+    cartridge.cpu_write(0x5104, 0);
+
+    cartridge.cpu_write(0x5200, 0x80 | 4);  // left split, tiles 0..3
+    cartridge.cpu_write(0x5201, 64);
+    cartridge.cpu_write(0x5202, 3);
+
+    mmc5_frame_start(&mut cartridge);
+    let fetched = mmc5_scanline(&mut cartridge);
+
+    assert_eq!(fetched[0], 0x33);
+    assert_eq!(fetched[2], 0x01);
+
+    // Tile 4 is past the split and comes from CIRAM
+    assert_eq!(fetched[6], 0x00);
+}
+
+#[test]
+fn mmc5_audio() {
+    let mut cartridge = Cartridge::from_bytes(&ines(5, 2, 1, 0)).unwrap();
+
+    cartridge.cpu_write(0x5015, 0x01);
+    cartridge.cpu_write(0x5000, 0xBF);
+    cartridge.cpu_write(0x5003, 0x08);
+    assert_eq!(cartridge.cpu_read(0x5015), 0x01);
+
+    let mut loud = false;
+    for _ in 0..64 {
+        cartridge.cpu_tick();
+        loud |= cartridge.audio_output() > 0.0;
+    }
+    assert!(loud);
+
+    cartridge.cpu_write(0x5015, 0x00);
+    assert_eq!(cartridge.cpu_read(0x5015), 0x00);
+
+    cartridge.cpu_write(0x5011, 0xFF);
+    assert!(cartridge.audio_output() > 0.2);
+}
+
+#[test]
+fn mmc5_pcm_read_mode() {
+    // PRG bank 0 is all zeros except the first byte
+    let mut raw = ines(5, 2, 1, 0);
+    raw[16] = 0x80;
+    let mut cartridge = Cartridge::from_bytes(&raw).unwrap();
+    cartridge.cpu_write(0x5100, 0);
+    cartridge.cpu_write(0x5117, 0x80);
+
+    // Writes to $5011 only count in write mode
+    cartridge.cpu_write(0x5010, 0x81);
+    cartridge.cpu_write(0x5011, 0xFF);
+    assert_eq!(cartridge.audio_output(), 0.0);
+
+    cartridge.cpu_read(0x8000);
+    assert!(cartridge.audio_output() > 0.0);
+    assert!(!cartridge.irq());
+
+    // A zero sample keeps the last one and raises the IRQ
+    let level = cartridge.audio_output();
+    cartridge.cpu_read(0x8001);
+    assert_eq!(cartridge.audio_output(), level);
+    assert!(cartridge.irq());
+
+    assert_eq!(cartridge.cpu_peek(0x5010), 0x80);
+    assert_eq!(cartridge.cpu_read(0x5010), 0x80);
+    assert_eq!(cartridge.cpu_read(0x5010), 0x00);
+    assert!(!cartridge.irq());
+
+    // Masked IRQs still set the flag
+    cartridge.cpu_write(0x5010, 0x01);
+    cartridge.cpu_read(0x8001);
+    assert!(!cartridge.irq());
+    assert_eq!(cartridge.cpu_read(0x5010), 0x80);
+}
+
+// VRC2 & VRC4
+
+#[test]
+fn vrc4_wiring() {
+    // VRC4c decodes A6 and A7, 8K bank N holds N / 2, 1K CHR bank N holds N / 8
+    let mut cartridge = Cartridge::from_bytes(&nes2(21, 2, 8, 4)).unwrap();
+
+    cartridge.cpu_write(0x8000, 6);
+    assert_eq!(cartridge.cpu_read(0x8000), 3);
+    assert_eq!(cartridge.cpu_read(0xC000), 7);
+
+    cartridge.cpu_write(0xB000, 0x02);
+    cartridge.cpu_write(0xB040, 0x01);  // high bits of CHR bank 0
+    assert_eq!(cartridge.ppu_read(0x0000), 2);
+
+    cartridge.cpu_write(0xB080, 0x08);  // low bits of CHR bank 1
+    assert_eq!(cartridge.ppu_read(0x0400), 1);
+
+    // PRG swap mode
+    cartridge.cpu_write(0x9080, 0x02);
+    assert_eq!(cartridge.cpu_read(0x8000), 7);
+    assert_eq!(cartridge.cpu_read(0xC000), 3);
+
+    cartridge.cpu_write(0x9000, 0x03);
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn vrc2a_chr_shift() {
+    let mut cartridge = Cartridge::from_bytes(&ines(22, 8, 4, 0)).unwrap();
+
+    cartridge.cpu_write(0xB000, 0x0F);
+    cartridge.cpu_write(0xB002, 0x01);  // high bits, A0 and A1 are swapped
+    assert_eq!(cartridge.ppu_read(0x0000), 1);
+
+    // VRC2 only knows horizontal and vertical mirroring
+    cartridge.cpu_write(0x9000, 0x03);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn vrc_irq_cycle_mode() {
+    let mut cartridge = Cartridge::from_bytes(&ines(23, 8, 4, 0)).unwrap();
+
+    cartridge.cpu_write(0xF000, 0x0E);
+    cartridge.cpu_write(0xF004, 0x0F);  // latch = 0xFE
+    cartridge.cpu_write(0xF008, 0x07);  // enable, enable after ack, cycle mode
+
+    cartridge.cpu_tick();
+    assert!(!cartridge.irq());
+    cartridge.cpu_tick();
+    assert!(cartridge.irq());
+
+    cartridge.cpu_write(0xF00C, 0);
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn vrc_irq_scanline_mode() {
+    let mut cartridge = Cartridge::from_bytes(&nes2(25, 1, 8, 4)).unwrap();
+
+    // VRC4b feeds CPU A1 into the chip's A0 and vice versa
+    cartridge.cpu_write(0xF000, 0x0F);
+    cartridge.cpu_write(0xF002, 0x0F);  // latch = 0xFF
+    cartridge.cpu_write(0xF001, 0x02);  // enable, scanline mode
+
+    for _ in 0..113 {
+        cartridge.cpu_tick();
+    }
+    assert!(!cartridge.irq());
+
+    cartridge.cpu_tick();
+    assert!(cartridge.irq());
+}
+
+// VRC6
+
+#[test]
+fn vrc6_banking() {
+    let mut cartridge = Cartridge::from_bytes(&ines(26, 8, 4, 0)).unwrap();
+
+    cartridge.cpu_write(0x8000, 3);
+    cartridge.cpu_write(0xC000, 9);
+    assert_eq!(cartridge.cpu_read(0x8000), 3);
+    assert_eq!(cartridge.cpu_read(0xC000), 4);
+    assert_eq!(cartridge.cpu_read(0xE000), 7);
+
+    cartridge.cpu_write(0xD002, 17);  // VRC6b: A1 -> reg 1
+    assert_eq!(cartridge.ppu_read(0x0400), 2);
+
+    cartridge.cpu_write(0xB003, 0x84);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn vrc6_audio() {
+    let mut cartridge = Cartridge::from_bytes(&ines(24, 8, 4, 0)).unwrap();
+
+    cartridge.cpu_write(0x9000, 0x8F);  // ignore duty, volume 15
+    cartridge.cpu_write(0x9001, 0x10);
+    cartridge.cpu_write(0x9002, 0x80);
+    assert!((cartridge.audio_output() - 15.0 * vrc::vrc6::VRC6_LEVEL).abs() < 1e-6);
+
+    cartridge.cpu_write(0x9002, 0x00);
+    cartridge.cpu_write(0xB000, 0x3F);
+    cartridge.cpu_write(0xB002, 0x80);
+
+    let mut peak = 0.0f32;
+    for _ in 0..16 {
+        cartridge.cpu_tick();
+        peak = peak.max(cartridge.audio_output());
+    }
+    assert!(peak > 0.0);
+
+    cartridge.cpu_write(0x9003, 0x01);  // halt
+    let halted = cartridge.audio_output();
+    cartridge.cpu_tick();
+    assert_eq!(cartridge.audio_output(), halted);
+}
+
+// VRC7
+
+#[test]
+fn vrc7_banking() {
+    let mut cartridge = Cartridge::from_bytes(&nes2(85, 2, 8, 4)).unwrap();
+
+    cartridge.cpu_write(0x8000, 2);
+    cartridge.cpu_write(0x8010, 4);
+    cartridge.cpu_write(0x9000, 6);
+    assert_eq!(cartridge.cpu_read(0x8000), 1);
+    assert_eq!(cartridge.cpu_read(0xA000), 2);
+    assert_eq!(cartridge.cpu_read(0xC000), 3);
+    assert_eq!(cartridge.cpu_read(0xE000), 7);
+
+    cartridge.cpu_write(0xD010, 24);
+    assert_eq!(cartridge.ppu_read(0x1C00), 3);
+
+    cartridge.cpu_write(0xE000, 0x01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn vrc7_fm_synth() {
+    let mut cartridge = Cartridge::from_bytes(&ines(85, 8, 4, 0)).unwrap();
+
+    for (register, data) in [(0x30, 0x10), (0x10, 0xAC), (0x20, 0x1C)] {
+        cartridge.cpu_write(0x9010, register);
+        cartridge.cpu_write(0x9030, data);
+    }
+
+    let mut peak = 0.0f32;
+    for _ in 0..36 * 200 {
+        cartridge.cpu_tick();
+        peak = peak.max(cartridge.audio_output().abs());
+    }
+    assert!(peak > 0.01);
+
+    cartridge.cpu_write(0xE000, 0x40);
+    assert_eq!(cartridge.audio_output(), 0.0);
+}
@@ -0,0 +1,91 @@
+use {
+    super::*,
+};
+
+pub struct Uxrom {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    bank: usize,
+    last: usize,
+
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+}
+
+savestate!(Uxrom { chr, prg_ram, bank });
+
+impl Mapper for Uxrom {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        let offset = (addr as usize) & (PRG_BANK_16K - 1);
+
+        match addr {
+            0x8000..=0xBFFF => self.prg[self.bank + offset],
+            0xC000..=0xFFFF => self.prg[self.last + offset],
+
+            0x6000..=0x7FFF => self.prg_ram.read(addr as usize - 0x6000),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr < 0x8000 {
+            if (0x6000..0x8000).contains(&addr) {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            return;
+        }
+
+        let data = if self.bus_conflicts {
+            data & self.cpu_peek(addr)
+        } else {
+            data
+        };
+
+        self.bank = bank_offset(data as usize, PRG_BANK_16K, self.prg.len());
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+}
+
+impl Uxrom {
+    pub fn new(rom: Rom) -> Self {
+        let last = last_bank(PRG_BANK_16K, rom.prg.len()) * PRG_BANK_16K;
+
+        Self { chr: ChrMemory::from_rom(rom.chr, &rom.header),
+               prg_ram: PrgRam::optional(&rom.header),
+               mirroring: rom.header.mirroring,
+               bus_conflicts: has_bus_conflicts(&rom.header),
+               prg: rom.prg,
+               bank: 0,
+               last }
+    }
+}
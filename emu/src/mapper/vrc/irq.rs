@@ -0,0 +1,89 @@
+use {
+    crate::{
+        mem::*,
+        state::*,
+    },
+};
+
+pub const PRESCALER_PERIOD: i16 = 341;
+
+// The IRQ counter shared by VRC4, VRC6 and VRC7. In scanline mode a
+// prescaler divides CPU cycles by 113.667 to approximate scanlines
+#[derive(Debug, Default)]
+pub struct VrcIrq {
+    latch: Byte,
+    counter: Byte,
+    prescaler: i16,
+
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+
+    pending: bool,
+}
+
+savestate!(VrcIrq { latch, counter, prescaler, enabled, enable_after_ack, cycle_mode, pending });
+
+impl VrcIrq {
+    #[inline(always)]
+    pub fn write_latch(&mut self, data: Byte) {
+        self.latch = data;
+    }
+
+    #[inline(always)]
+    pub fn write_latch_low(&mut self, data: Byte) {
+        self.latch = (self.latch & 0xF0) | (data & 0x0F);
+    }
+
+    #[inline(always)]
+    pub fn write_latch_high(&mut self, data: Byte) {
+        self.latch = (self.latch & 0x0F) | (data << 4);
+    }
+
+    pub fn write_control(&mut self, data: Byte) {
+        self.enable_after_ack = (data & 0x01) != 0;
+        self.enabled = (data & 0x02) != 0;
+        self.cycle_mode = (data & 0x04) != 0;
+        self.pending = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.cycle_mode {
+            self.clock();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock();
+            }
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    #[inline(always)]
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+}
@@ -0,0 +1,33 @@
+use {
+    super::*,
+};
+
+pub mod irq;
+pub mod opll;
+
+pub mod vrc24;
+pub mod vrc6;
+pub mod vrc7;
+
+// The VRC chips only see two (VRC7: one) low address lines and every
+// board routes a different pair of CPU address bits into them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wiring {
+    pub a0: Word,
+    pub a1: Word,
+}
+
+impl Wiring {
+    // Folds the board specific address bits into $x000-$x003
+    #[inline(always)]
+    pub fn translate(&self, addr: Word) -> Word {
+        let a0 = ((addr & self.a0) != 0) as Word;
+        let a1 = ((addr & self.a1) != 0) as Word;
+
+        (addr & 0xF000) | (a1 << 1) | a0
+    }
+
+    pub const fn new(a0: Word, a1: Word) -> Self {
+        Self { a0, a1 }
+    }
+}
@@ -0,0 +1,434 @@
+use {
+    crate::{
+        mem::*,
+        state::*,
+        error::*,
+    },
+
+    std::f32::consts::PI,
+};
+
+pub const OPLL_CHANNELS: usize = 6;
+
+// The YM2413 derivative inside VRC7 produces one sample every 72
+// cycles of its 3.58 MHz clock, which is 36 CPU cycles
+pub const OPLL_DIVIDER: u8 = 36;
+pub const OPLL_RATE: f32   = 49716.0;
+
+pub const OPLL_LEVEL: f32  = 0.08;
+
+pub const VRC7_PATCHES: [[Byte; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+const MULTIPLIERS: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0,
+    8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+// Key scale attenuation in dB by the top F-number bits, at block 7
+const KSL_TABLE: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25,
+    36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25, 42.0,
+];
+
+const SILENT_DB: f32 = 48.0;
+
+// Samples needed to decay over the whole range at effective rate 0,
+// every 4 effective rate steps halve it
+const DECAY_BASE_SAMPLES: f32 = 1_966_080.0;
+
+const AM_DEPTH_DB: f32  = 4.8;
+const AM_RATE: f32      = 3.7;
+const VIB_DEPTH: f32    = 0.004;
+const VIB_RATE: f32     = 6.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+
+    #[default]
+    Off,
+}
+
+impl Savestate for EnvelopeState {
+    fn save(&self, state: &mut StateWriter) {
+        (*self as Byte).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        *self = match state.read_array::<1>()? {
+            [0] => EnvelopeState::Attack,
+            [1] => EnvelopeState::Decay,
+            [2] => EnvelopeState::Sustain,
+            [3] => EnvelopeState::Release,
+            [4] => EnvelopeState::Off,
+
+            _ => return Err(StateError::Corrupted),
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Operator {
+    phase: f32,
+
+    state: EnvelopeState,
+    attenuation: f32,
+}
+
+savestate!(Operator { phase, state, attenuation });
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    fnum: Word,
+    block: Byte,
+
+    key: bool,
+    sustain: bool,
+
+    instrument: Byte,
+    volume: Byte,
+
+    operators: [Operator; 2],
+    feedback: [f32; 2],
+}
+
+savestate!(Channel { fnum, block, key, sustain, instrument, volume, operators, feedback });
+
+// One operator's view of an instrument patch
+struct Patch {
+    am: bool,
+    vibrato: bool,
+    sustained: bool,
+    ksr: bool,
+    multiplier: f32,
+
+    ksl: Byte,
+    total_level: f32,
+    half_wave: bool,
+
+    attack: Byte,
+    decay: Byte,
+    sustain_level: f32,
+    release: Byte,
+}
+
+pub struct Opll {
+    custom: [Byte; 8],
+    select: Byte,
+
+    channels: [Channel; OPLL_CHANNELS],
+
+    am_phase: f32,
+    vibrato_phase: f32,
+
+    divider: u8,
+    output: f32,
+}
+
+savestate!(Opll { custom, select, channels, am_phase, vibrato_phase, divider, output });
+
+impl Patch {
+    fn decode(raw: &[Byte; 8], operator: usize) -> Self {
+        let flags = raw[operator];
+        let (ksl, total_level) = if operator == 0 {
+            (raw[2] >> 6, (raw[2] & 0x3F) as f32 * 0.75)
+        } else {
+            (raw[3] >> 6, 0.0)
+        };
+
+        Self {
+            am: (flags & 0x80) != 0,
+            vibrato: (flags & 0x40) != 0,
+            sustained: (flags & 0x20) != 0,
+            ksr: (flags & 0x10) != 0,
+            multiplier: MULTIPLIERS[(flags & 0x0F) as usize],
+
+            ksl,
+            total_level,
+            half_wave: (raw[3] & (0x08 << operator)) != 0,
+
+            attack: raw[4 + operator] >> 4,
+            decay: raw[4 + operator] & 0x0F,
+            sustain_level: (raw[6 + operator] >> 4) as f32 * 3.0,
+            release: raw[6 + operator] & 0x0F,
+        }
+    }
+}
+
+impl Channel {
+    #[inline(always)]
+    fn key_scale(&self, ksr: bool) -> Byte {
+        let rks = self.block * 2 + (self.fnum >> 8) as Byte;
+        if ksr { rks } else { rks >> 2 }
+    }
+
+    fn ksl_attenuation(&self, ksl: Byte) -> f32 {
+        if ksl == 0 {
+            return 0.0;
+        }
+
+        let base = KSL_TABLE[(self.fnum >> 5) as usize & 0x0F] - 6.0 * (7 - self.block) as f32;
+        base.max(0.0) / (1 << (3 - ksl)) as f32
+    }
+}
+
+impl Operator {
+    fn clock_envelope(
+        &mut self,
+        patch: &Patch,
+        key_scale: Byte,
+        sustain: bool,
+    ) {
+        match self.state {
+            EnvelopeState::Attack => {
+                let rate = effective_rate(patch.attack, key_scale);
+                if rate >= 60 {
+                    self.attenuation = 0.0;
+                } else {
+                    self.attenuation -= envelope_step(rate) * 8.0;
+                }
+
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+
+            EnvelopeState::Decay => {
+                self.attenuation += envelope_step(effective_rate(patch.decay, key_scale));
+                if self.attenuation >= patch.sustain_level {
+                    self.attenuation = patch.sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+
+            // Percussive patches keep decaying with the release rate
+            EnvelopeState::Sustain if !patch.sustained => {
+                self.attenuation += envelope_step(effective_rate(patch.release, key_scale));
+            }
+            EnvelopeState::Sustain => {}
+
+            EnvelopeState::Release => {
+                let release = if sustain { 5 } else { patch.release };
+                self.attenuation += envelope_step(effective_rate(release, key_scale));
+            }
+
+            EnvelopeState::Off => return,
+        }
+
+        if self.attenuation >= SILENT_DB {
+            self.attenuation = SILENT_DB;
+            self.state = EnvelopeState::Off;
+        }
+    }
+
+    fn output(
+        &self,
+        patch: &Patch,
+        attenuation: f32,
+        modulation: f32,
+    ) -> f32 {
+        if self.state == EnvelopeState::Off {
+            return 0.0;
+        }
+
+        let wave = (self.phase * 2.0 * PI + modulation).sin();
+        if patch.half_wave && wave < 0.0 {
+            return 0.0;
+        }
+
+        let total = self.attenuation + attenuation;
+        if total >= SILENT_DB {
+            0.0
+        } else {
+            wave * 10f32.powf(-total / 20.0)
+        }
+    }
+}
+
+impl Opll {
+    pub fn select(&mut self, register: Byte) {
+        self.select = register;
+    }
+
+    pub fn write(&mut self, data: Byte) {
+        let register = self.select;
+        let index = (register & 0x0F) as usize;
+
+        match register {
+            0x00..=0x07 => self.custom[index] = data,
+
+            0x10..=0x15 => {
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0x100) | data as Word;
+            }
+
+            0x20..=0x25 => {
+                let channel = &mut self.channels[index];
+                let key = (data & 0x10) != 0;
+
+                channel.fnum = (channel.fnum & 0x0FF) | ((data & 0x01) as Word) << 8;
+                channel.block = (data >> 1) & 0x07;
+                channel.sustain = (data & 0x20) != 0;
+
+                if key && !channel.key {
+                    for operator in &mut channel.operators {
+                        operator.phase = 0.0;
+                        operator.state = EnvelopeState::Attack;
+                    }
+                } else if !key && channel.key {
+                    for operator in &mut channel.operators {
+                        if operator.state != EnvelopeState::Off {
+                            operator.state = EnvelopeState::Release;
+                        }
+                    }
+                }
+
+                channel.key = key;
+            }
+
+            0x30..=0x35 => {
+                let channel = &mut self.channels[index];
+                channel.instrument = data >> 4;
+                channel.volume = data & 0x0F;
+            }
+
+            _ => {}
+        }
+    }
+
+    fn patch(&self, instrument: Byte) -> &[Byte; 8] {
+        match instrument {
+            0 => &self.custom,
+            n => &VRC7_PATCHES[n as usize - 1],
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        self.am_phase = (self.am_phase + AM_RATE / OPLL_RATE).fract();
+        self.vibrato_phase = (self.vibrato_phase + VIB_RATE / OPLL_RATE).fract();
+
+        let am = AM_DEPTH_DB * 0.5 * (1.0 - (self.am_phase * 2.0 * PI).cos());
+        let vibrato = 1.0 + VIB_DEPTH * (self.vibrato_phase * 2.0 * PI).sin();
+
+        let mut mixed = 0.0;
+        for index in 0..OPLL_CHANNELS {
+            let raw = *self.patch(self.channels[index].instrument);
+            let patches = [Patch::decode(&raw, 0), Patch::decode(&raw, 1)];
+            let feedback_level = raw[3] & 0x07;
+
+            let channel = &mut self.channels[index];
+            let increment = channel.fnum as f32 * (1 << channel.block) as f32 / (1 << 19) as f32;
+
+            for (operator, patch) in channel.operators.iter_mut().zip(&patches) {
+                let step = if patch.vibrato { increment * vibrato } else { increment };
+                operator.phase = (operator.phase + step * patch.multiplier).fract();
+            }
+
+            for (slot, patch) in patches.iter().enumerate() {
+                let key_scale = channel.key_scale(patch.ksr);
+                let sustain = channel.sustain;
+
+                channel.operators[slot].clock_envelope(patch, key_scale, sustain);
+            }
+
+            let attenuation = |patch: &Patch, extra: f32| {
+                patch.total_level
+                    + channel.ksl_attenuation(patch.ksl)
+                    + if patch.am { am } else { 0.0 }
+                    + extra
+            };
+
+            let feedback = if feedback_level == 0 {
+                0.0
+            } else {
+                (channel.feedback[0] + channel.feedback[1]) * 0.5
+                    * 4.0 * PI / (1 << (7 - feedback_level)) as f32
+            };
+
+            let modulator = channel.operators[0].output(
+                &patches[0],
+                attenuation(&patches[0], 0.0),
+                feedback,
+            );
+            let carrier = channel.operators[1].output(
+                &patches[1],
+                attenuation(&patches[1], channel.volume as f32 * 3.0),
+                modulator * 4.0 * PI,
+            );
+
+            channel.feedback = [channel.feedback[1], modulator];
+            mixed += carrier;
+        }
+
+        mixed * OPLL_LEVEL
+    }
+
+    pub fn tick(&mut self) {
+        self.divider += 1;
+        if self.divider >= OPLL_DIVIDER {
+            self.divider = 0;
+            self.output = self.sample();
+        }
+    }
+
+    #[inline(always)]
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    pub fn new() -> Self {
+        Self { custom: [0; 8],
+               select: 0,
+               channels: [Channel::default(); OPLL_CHANNELS],
+               am_phase: 0.0,
+               vibrato_phase: 0.0,
+               divider: 0,
+               output: 0.0 }
+    }
+}
+
+impl Default for Opll {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline(always)]
+fn effective_rate(rate: Byte, key_scale: Byte) -> Byte {
+    if rate == 0 {
+        0
+    } else {
+        (rate * 4 + key_scale).min(63)
+    }
+}
+
+#[inline(always)]
+fn envelope_step(rate: Byte) -> f32 {
+    if rate < 4 {
+        0.0
+    } else {
+        SILENT_DB / (DECAY_BASE_SAMPLES / (1u32 << (rate / 4)) as f32)
+    }
+}
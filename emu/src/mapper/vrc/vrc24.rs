@@ -0,0 +1,193 @@
+use {
+    super::{
+        *,
+        irq::VrcIrq,
+    },
+};
+
+pub struct Vrc24 {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    wiring: Wiring,
+    vrc2: bool,
+    chr_shift: u8,
+
+    prg_regs: [Byte; 2],
+    prg_swap: bool,
+    chr_regs: [Word; 8],
+
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+savestate!(Vrc24 { chr, prg_ram, prg_regs, prg_swap, chr_regs, mirroring, irq });
+
+impl Mapper for Vrc24 {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                self.prg_ram.read(addr as usize - 0x6000)
+            }
+
+            0x8000..=0xFFFF => {
+                let len = self.prg.len();
+                let second_last = last_bank(PRG_BANK_8K, len).saturating_sub(1);
+
+                let bank = match (addr, self.prg_swap) {
+                    (0x8000..=0x9FFF, false) => self.prg_regs[0] as usize,
+                    (0x8000..=0x9FFF, true)  => second_last,
+                    (0xA000..=0xBFFF, _)     => self.prg_regs[1] as usize,
+                    (0xC000..=0xDFFF, false) => second_last,
+                    (0xC000..=0xDFFF, true)  => self.prg_regs[0] as usize,
+                    _                        => last_bank(PRG_BANK_8K, len),
+                };
+
+                self.prg[bank_offset(bank, PRG_BANK_8K, len) + (addr as usize & (PRG_BANK_8K - 1))]
+            }
+
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr < 0x8000 {
+            if (0x6000..0x8000).contains(&addr) && !self.prg_ram.is_empty() {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            return;
+        }
+
+        let register = self.wiring.translate(addr);
+        match register {
+            0x8000..=0x8003 => self.prg_regs[0] = data & 0x1F,
+            0xA000..=0xA003 => self.prg_regs[1] = data & 0x1F,
+
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = if (data & 0x01) == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0x9000 | 0x9001 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002 => self.prg_swap = (data & 0x02) != 0,
+
+            0xB000..=0xEFFF => {
+                let index = (((register - 0xB000) >> 12) * 2 + ((register & 0x02) >> 1)) as usize;
+                let high_mask = if self.vrc2 { 0x0F } else { 0x1F };
+
+                let value = &mut self.chr_regs[index];
+                if (register & 0x01) == 0 {
+                    *value = (*value & 0x1F0) | (data & 0x0F) as Word;
+                } else {
+                    *value = (*value & 0x00F) | ((data & high_mask) as Word) << 4;
+                }
+            }
+
+            0xF000 if !self.vrc2 => self.irq.write_latch_low(data),
+            0xF001 if !self.vrc2 => self.irq.write_latch_high(data),
+            0xF002 if !self.vrc2 => self.irq.write_control(data),
+            0xF003 if !self.vrc2 => self.irq.acknowledge(),
+
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(self.chr_offset(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+}
+
+impl Vrc24 {
+    #[inline(always)]
+    fn chr_offset(&self, addr: Word) -> usize {
+        let slot = (addr as usize & 0x1FFF) / CHR_BANK_1K;
+        let bank = (self.chr_regs[slot] >> self.chr_shift) as usize;
+
+        bank_offset(bank, CHR_BANK_1K, self.chr.len()) + (addr as usize & (CHR_BANK_1K - 1))
+    }
+
+    // Mapper and NES 2.0 submapper select the chip and its wiring,
+    // submapper 0 ORs both known wirings of a mapper number together
+    pub fn new(rom: Rom) -> Self {
+        let header = rom.header;
+
+        let (vrc2, wiring) = match (header.mapper, header.submapper) {
+            (21, 1) => (false, Wiring::new(0x02, 0x04)),
+            (21, 2) => (false, Wiring::new(0x40, 0x80)),
+            (21, _) => (false, Wiring::new(0x42, 0x84)),
+
+            (22, _) => (true, Wiring::new(0x02, 0x01)),
+
+            (23, 1) => (false, Wiring::new(0x01, 0x02)),
+            (23, 2) => (false, Wiring::new(0x04, 0x08)),
+            (23, 3) => (true, Wiring::new(0x01, 0x02)),
+            (23, _) => (false, Wiring::new(0x05, 0x0A)),
+
+            (25, 1) => (false, Wiring::new(0x02, 0x01)),
+            (25, 2) => (false, Wiring::new(0x08, 0x04)),
+            (25, 3) => (true, Wiring::new(0x02, 0x01)),
+            (_, _)  => (false, Wiring::new(0x0A, 0x05)),
+        };
+
+        // VRC2a ignores the lowest CHR bank bit
+        let chr_shift = (header.mapper == 22) as u8;
+
+        Self { chr: ChrMemory::from_rom(rom.chr, &header),
+               prg: rom.prg,
+               prg_ram: PrgRam::from_header(&header),
+
+               wiring,
+               vrc2,
+               chr_shift,
+
+               prg_regs: [0, 1],
+               prg_swap: false,
+               chr_regs: [0; 8],
+
+               mirroring: header.mirroring,
+               irq: VrcIrq::default() }
+    }
+}
@@ -0,0 +1,328 @@
+use {
+    super::{
+        *,
+        irq::VrcIrq,
+    },
+};
+
+// A VRC6 pulse at full volume is about as loud as a 2A03 pulse
+pub const VRC6_LEVEL: f32 = 0.15 / 15.0;
+
+#[derive(Default)]
+struct Vrc6Pulse {
+    enabled: bool,
+    ignore_duty: bool,
+
+    duty: Byte,
+    volume: Byte,
+
+    period: Word,
+    timer: Word,
+    step: Byte,
+}
+
+savestate!(Vrc6Pulse { enabled, ignore_duty, duty, volume, period, timer, step });
+
+#[derive(Default)]
+struct Vrc6Saw {
+    enabled: bool,
+
+    rate: Byte,
+    accumulator: Byte,
+
+    period: Word,
+    timer: Word,
+    step: Byte,
+}
+
+savestate!(Vrc6Saw { enabled, rate, accumulator, period, timer, step });
+
+#[derive(Default)]
+pub struct Vrc6Audio {
+    pulses: [Vrc6Pulse; 2],
+    saw: Vrc6Saw,
+
+    halted: bool,
+}
+
+savestate!(Vrc6Audio { pulses, saw, halted });
+
+pub struct Vrc6 {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    wiring: Wiring,
+
+    prg_16k: Byte,
+    prg_8k: Byte,
+    chr_regs: [Byte; 8],
+
+    ram_enabled: bool,
+    mirroring: Mirroring,
+
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+savestate!(Vrc6 { chr, prg_ram, prg_16k, prg_8k, chr_regs, ram_enabled, mirroring, irq, audio });
+
+impl Vrc6Pulse {
+    fn write(&mut self, register: Word, data: Byte) {
+        match register {
+            0 => {
+                self.ignore_duty = (data & 0x80) != 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | data as Word,
+            2 => {
+                self.period = (self.period & 0x00FF) | ((data & 0x0F) as Word) << 8;
+                self.enabled = (data & 0x80) != 0;
+
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> Byte {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, register: Word, data: Byte) {
+        match register {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | data as Word,
+            2 => {
+                self.period = (self.period & 0x00FF) | ((data & 0x0F) as Word) << 8;
+                self.enabled = (data & 0x80) != 0;
+
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    // The accumulator grows on every other clock and resets on the 14th
+    fn clock(&mut self) {
+        if self.timer != 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period;
+        self.step += 1;
+
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if (self.step & 1) == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> Byte {
+        if self.enabled {
+            self.accumulator >> 3
+        } else {
+            0
+        }
+    }
+}
+
+impl Vrc6Audio {
+    pub fn write(&mut self, register: Word, data: Byte) {
+        match register {
+            0x9000..=0x9002 => self.pulses[0].write(register - 0x9000, data),
+            0x9003 => self.halted = (data & 0x01) != 0,
+
+            0xA000..=0xA002 => self.pulses[1].write(register - 0xA000, data),
+            0xB000..=0xB002 => self.saw.write(register - 0xB000, data),
+
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if self.halted {
+            return;
+        }
+
+        self.pulses[0].clock();
+        self.pulses[1].clock();
+        self.saw.clock();
+    }
+
+    pub fn output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.saw.output();
+        sum as f32 * VRC6_LEVEL
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x6000..=0x7FFF if self.ram_enabled && !self.prg_ram.is_empty() => {
+                self.prg_ram.read(addr as usize - 0x6000)
+            }
+
+            0x8000..=0xFFFF => {
+                let len = self.prg.len();
+                let offset = match addr {
+                    0x8000..=0xBFFF => {
+                        bank_offset(self.prg_16k as usize, PRG_BANK_16K, len)
+                            + (addr as usize & (PRG_BANK_16K - 1))
+                    }
+                    0xC000..=0xDFFF => {
+                        bank_offset(self.prg_8k as usize, PRG_BANK_8K, len)
+                            + (addr as usize & (PRG_BANK_8K - 1))
+                    }
+                    _ => {
+                        bank_offset(last_bank(PRG_BANK_8K, len), PRG_BANK_8K, len)
+                            + (addr as usize & (PRG_BANK_8K - 1))
+                    }
+                };
+
+                self.prg[offset]
+            }
+
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr < 0x8000 {
+            if (0x6000..0x8000).contains(&addr) && self.ram_enabled && !self.prg_ram.is_empty() {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            return;
+        }
+
+        let register = self.wiring.translate(addr);
+        match register {
+            0x8000..=0x8003 => self.prg_16k = data & 0x0F,
+            0xC000..=0xC003 => self.prg_8k = data & 0x1F,
+
+            0x9000..=0xB002 => self.audio.write(register, data),
+            0xB003 => {
+                self.ram_enabled = (data & 0x80) != 0;
+                self.mirroring = match (data >> 2) & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+
+            0xD000..=0xD003 => self.chr_regs[(register & 0x03) as usize] = data,
+            0xE000..=0xE003 => self.chr_regs[4 + (register & 0x03) as usize] = data,
+
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(self.chr_offset(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+        self.audio.tick();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Vrc6 {
+    #[inline(always)]
+    fn chr_offset(&self, addr: Word) -> usize {
+        let slot = (addr as usize & 0x1FFF) / CHR_BANK_1K;
+        let bank = self.chr_regs[slot] as usize;
+
+        bank_offset(bank, CHR_BANK_1K, self.chr.len()) + (addr as usize & (CHR_BANK_1K - 1))
+    }
+
+    // VRC6b (mapper 26) swaps A0 and A1
+    pub fn new(rom: Rom) -> Self {
+        let header = rom.header;
+        let wiring = if header.mapper == 26 {
+            Wiring::new(0x02, 0x01)
+        } else {
+            Wiring::new(0x01, 0x02)
+        };
+
+        Self { chr: ChrMemory::from_rom(rom.chr, &header),
+               prg: rom.prg,
+               prg_ram: PrgRam::from_header(&header),
+
+               wiring,
+
+               prg_16k: 0,
+               prg_8k: 0,
+               chr_regs: [0, 1, 2, 3, 4, 5, 6, 7],
+
+               ram_enabled: true,
+               mirroring: header.mirroring,
+
+               irq: VrcIrq::default(),
+               audio: Vrc6Audio::default() }
+    }
+}
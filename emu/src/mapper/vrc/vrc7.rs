@@ -0,0 +1,178 @@
+use {
+    super::{
+        *,
+        irq::VrcIrq,
+        opll::Opll,
+    },
+};
+
+pub struct Vrc7 {
+    prg: Vec<Byte>,
+    chr: ChrMemory,
+    prg_ram: PrgRam,
+
+    line: Word,
+
+    prg_regs: [Byte; 3],
+    chr_regs: [Byte; 8],
+
+    ram_enabled: bool,
+    silenced: bool,
+    mirroring: Mirroring,
+
+    irq: VrcIrq,
+    opll: Opll,
+}
+
+savestate!(Vrc7 { chr, prg_ram, prg_regs, chr_regs, ram_enabled, silenced, mirroring, irq, opll });
+
+impl Mapper for Vrc7 {
+    fn cpu_peek(&self, addr: Word) -> Byte {
+        match addr {
+            0x6000..=0x7FFF if self.ram_enabled && !self.prg_ram.is_empty() => {
+                self.prg_ram.read(addr as usize - 0x6000)
+            }
+
+            0x8000..=0xFFFF => {
+                let len = self.prg.len();
+                let bank = match addr {
+                    0x8000..=0x9FFF => self.prg_regs[0] as usize,
+                    0xA000..=0xBFFF => self.prg_regs[1] as usize,
+                    0xC000..=0xDFFF => self.prg_regs[2] as usize,
+                    _               => last_bank(PRG_BANK_8K, len),
+                };
+
+                self.prg[bank_offset(bank, PRG_BANK_8K, len) + (addr as usize & (PRG_BANK_8K - 1))]
+            }
+
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: Word, data: Byte) {
+        if addr < 0x8000 {
+            if (0x6000..0x8000).contains(&addr) && self.ram_enabled && !self.prg_ram.is_empty() {
+                self.prg_ram.write(addr as usize - 0x6000, data);
+            }
+
+            return;
+        }
+
+        // The sound chip ports always decode A4 and A5
+        match addr & 0xF030 {
+            0x9010 => return self.opll.select(data),
+            0x9030 => return self.opll.write(data),
+            _ => {}
+        }
+
+        let high = (addr & self.line) != 0;
+        match (addr & 0xF000, high) {
+            (0x8000, false) => self.prg_regs[0] = data & 0x3F,
+            (0x8000, true)  => self.prg_regs[1] = data & 0x3F,
+            (0x9000, false) => self.prg_regs[2] = data & 0x3F,
+
+            (0xA000..=0xD000, _) => {
+                let index = (((addr & 0xF000) - 0xA000) >> 11) as usize + high as usize;
+                self.chr_regs[index] = data;
+            }
+
+            (0xE000, false) => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+
+                self.ram_enabled = (data & 0x80) != 0;
+                self.silenced = (data & 0x40) != 0;
+            }
+            (0xE000, true)  => self.irq.write_latch(data),
+            (0xF000, false) => self.irq.write_control(data),
+            (0xF000, true)  => self.irq.acknowledge(),
+
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Word) -> Byte {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: Word, data: Byte) {
+        self.chr.write(self.chr_offset(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &PrgRam {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut PrgRam {
+        &mut self.prg_ram
+    }
+
+    fn chr(&self) -> &ChrMemory {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut ChrMemory {
+        &mut self.chr
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+        self.opll.tick();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.silenced {
+            0.0
+        } else {
+            self.opll.output()
+        }
+    }
+}
+
+impl Vrc7 {
+    #[inline(always)]
+    fn chr_offset(&self, addr: Word) -> usize {
+        let slot = (addr as usize & 0x1FFF) / CHR_BANK_1K;
+        let bank = self.chr_regs[slot] as usize;
+
+        bank_offset(bank, CHR_BANK_1K, self.chr.len()) + (addr as usize & (CHR_BANK_1K - 1))
+    }
+
+    // VRC7b (submapper 1) decodes A3, VRC7a (submapper 2) decodes A4
+    pub fn new(rom: Rom) -> Self {
+        let header = rom.header;
+        let line = match header.submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+
+        Self { chr: ChrMemory::from_rom(rom.chr, &header),
+               prg: rom.prg,
+               prg_ram: PrgRam::from_header(&header),
+
+               line,
+
+               prg_regs: [0, 1, 2],
+               chr_regs: [0, 1, 2, 3, 4, 5, 6, 7],
+
+               ram_enabled: true,
+               silenced: false,
+               mirroring: header.mirroring,
+
+               irq: VrcIrq::default(),
+               opll: Opll::new() }
+    }
+}
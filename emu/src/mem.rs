@@ -1,13 +1,82 @@
+use crate::{
+    cartridge::*,
+    ppu::*,
+    apu::{
+        self,
+        Apu,
+        APU_STATUS,
+    },
+    dma::*,
+    region::*,
+    input::*,
+    state::*,
+    error::*,
+};
+
 pub type Byte       = u8;
 pub type Signed     = i8;
 pub type Word       = u16;
 pub type SignedWord = i16;
 
+pub const CARTRIDGE_SPACE: Word = 0x4020;
+
 pub struct Memory {
-    inner: Vec<Byte>
+    inner: Box<[Byte]>,
+    cartridge: Option<Cartridge>,
+
+    ppu: Ppu,
+    // Dots owed to the PPU, in CPU cycle fractions for PAL's 3.2 ratio
+    ppu_phase: u8,
+
+    apu: Apu,
+    pub input: Input,
+
+    pub dma: Dma,
+    cycles: u64,
+}
+
+// The region goes first since loading it resets the PPU and APU timing,
+// the cartridge is whatever is inserted, `Nes::load_state` checks it's
+// the same ROM
+impl Savestate for Memory {
+    fn save(&self, state: &mut StateWriter) {
+        self.region().save(state);
+        self.inner.save(state);
+
+        self.ppu.save(state);
+        self.ppu_phase.save(state);
+        self.apu.save(state);
+        self.input.save(state);
+        self.dma.save(state);
+        self.cycles.save(state);
+
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut region = Region::default();
+        region.load(state)?;
+        self.set_region(region);
+
+        self.inner.load(state)?;
+
+        self.ppu.load(state)?;
+        self.ppu_phase.load(state)?;
+        self.apu.load(state)?;
+        self.input.load(state)?;
+        self.dma.load(state)?;
+        self.cycles.load(state)?;
+
+        match &mut self.cartridge {
+            Some(cartridge) => cartridge.load(state),
+            None => Ok(()),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AddrMode {
     Immediate,
     ZeroPage,
@@ -27,31 +96,87 @@ pub enum AddrMode {
     IndirectY,
 
     Accumulator,
+    Implied,
+}
+
+impl AddrMode {
+    // Bytes following the opcode
+    pub fn operand_size(self) -> Word {
+        match self {
+            AddrMode::Accumulator | AddrMode::Implied => 0,
+            AddrMode::Absolute | AddrMode::AbsoluteX | AddrMode::AbsoluteY | AddrMode::Indirect => 2,
+
+            _ => 1,
+        }
+    }
 }
 
 impl Memory {
     pub fn read_word(&self, addr: Word) -> Word {
-        (self.read(addr + 1) as Word) << 8 | (self.read(addr) as Word)
+        (self.peek(addr.wrapping_add(1)) as Word) << 8 | (self.peek(addr) as Word)
     }
 
     pub fn write_word(&mut self, addr: Word, data: Word) {
         self.write(addr, (data & 0xff) as u8);
-        self.write(addr + 1, (data >> 8) as u8);
+        self.write(addr.wrapping_add(1), (data >> 8) as u8);
     }
 
     #[inline(always)]
-    pub fn read(&self, addr: Word) -> Byte {
-        self.inner[addr as usize]
+    pub fn read(&mut self, addr: Word) -> Byte {
+        match &mut self.cartridge {
+            Some(cartridge) if addr >= CARTRIDGE_SPACE => cartridge.cpu_read(addr),
+            cartridge if (PPU_SPACE..=PPU_SPACE_END).contains(&addr) => {
+                self.ppu.cpu_read(addr, cartridge.as_mut())
+            }
+            _ if addr == APU_STATUS => self.apu.cpu_read(addr),
+            _ if addr == JOYPAD1 || addr == JOYPAD2 => self.input.cpu_read(addr, &self.ppu),
+            _ => self.inner[addr as usize],
+        }
+    }
+
+    #[inline(always)]
+    pub fn peek(&self, addr: Word) -> Byte {
+        match &self.cartridge {
+            Some(cartridge) if addr >= CARTRIDGE_SPACE => cartridge.cpu_peek(addr),
+            _ if (PPU_SPACE..=PPU_SPACE_END).contains(&addr) => self.ppu.cpu_peek(addr),
+            _ if addr == APU_STATUS => self.apu.cpu_peek(addr),
+            _ if addr == JOYPAD1 || addr == JOYPAD2 => self.input.cpu_peek(addr, &self.ppu),
+            _ => self.inner[addr as usize],
+        }
     }
 
     #[inline(always)]
     pub fn read_signed(&self, addr: Word) -> Signed {
-        self.inner[addr as usize] as Signed
+        self.peek(addr) as Signed
     }
 
     #[inline(always)]
     pub fn write(&mut self, addr: Word, data: Byte) {
-        self.inner[addr as usize] = data;
+        if (PPU_SPACE..=PPU_SPACE_END).contains(&addr) {
+            if let Some(cartridge) = &mut self.cartridge {
+                cartridge.snoop_write(addr, data);
+            }
+
+            return self.ppu.cpu_write(addr, data, self.cartridge.as_mut());
+        }
+
+        if addr == OAM_DMA {
+            self.dma.request_oam(data);
+        } else if addr == JOYPAD1 {
+            self.input.cpu_write(addr, data);
+        } else if apu::is_register(addr) {
+            self.apu.cpu_write(addr, data);
+        }
+
+        match &mut self.cartridge {
+            Some(cartridge) if addr >= CARTRIDGE_SPACE => cartridge.cpu_write(addr, data),
+            Some(cartridge) => {
+                cartridge.snoop_write(addr, data);
+                self.inner[addr as usize] = data;
+            }
+
+            None => self.inner[addr as usize] = data,
+        }
     }
 
     pub fn copy_from(
@@ -74,11 +199,127 @@ impl Memory {
             .copy_from_slice(data)
     }
 
+    #[inline(always)]
+    pub fn irq_asserted(&self) -> bool {
+        let cartridge = self.cartridge
+            .as_ref()
+            .is_some_and(Cartridge::irq);
+
+        cartridge || self.apu.irq()
+    }
+
+    // One CPU (M2) cycle on the bus, everything else on the bus catches
+    // up with the CPU here
+    #[inline(always)]
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.cpu_tick();
+        }
+
+        // The DMC gets its byte once the DMA the CPU ran for it is done
+        if self.apu.dmc.fetching() {
+            if let Some(sample) = self.dma.take_dmc_sample() {
+                self.apu.dmc.fill(sample);
+            }
+        }
+
+        self.apu.tick();
+
+        let expansion = self.cartridge
+            .as_ref()
+            .map_or(0.0, Cartridge::audio_output);
+        self.apu.mix(expansion);
+
+        if let Some(addr) = self.apu.dmc.take_fetch() {
+            self.dma.request_dmc(addr);
+        }
+
+        let (dots, cycles) = self.ppu.region.ppu_ratio();
+        self.ppu_phase += dots;
+
+        while self.ppu_phase >= cycles {
+            self.ppu_phase -= cycles;
+            self.ppu_tick();
+        }
+    }
+
+    #[inline(always)]
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    #[inline(always)]
+    pub fn take_nmi(&mut self) -> bool {
+        self.ppu.take_nmi()
+    }
+
+    #[inline(always)]
+    pub fn ppu_tick(&mut self) {
+        self.ppu.tick(self.cartridge.as_mut());
+    }
+
+    #[inline(always)]
+    pub fn region(&self) -> Region {
+        self.ppu.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.ppu.region = region;
+        self.apu.set_region(region);
+        self.ppu_phase = 0;
+    }
+
+    // CPU RAM, all of $0000-$1FFF since the 2K isn't mirrored here
+    pub fn ram(&self) -> &[Byte] {
+        &self.inner[..PPU_SPACE as usize]
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) -> Option<Cartridge> {
+        self.cartridge.replace(cartridge)
+    }
+
+    pub fn remove_cartridge(&mut self) -> Option<Cartridge> {
+        self.cartridge.take()
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
     pub fn new(mem: Vec<u8>) -> Self {
-        Self { inner: mem }
+        Self { inner: mem.into_boxed_slice(),
+               cartridge: None,
+               ppu: Ppu::new(),
+               ppu_phase: 0,
+               apu: Apu::new(),
+               input: Input::default(),
+               dma: Dma::default(),
+               cycles: 0 }
     }
 
     pub fn zeroed() -> Self {
-        Self { inner: vec![0; 0xFF_FF] }
+        Self::new(vec![0; 0x1_00_00])
     }
 }
@@ -0,0 +1,538 @@
+use {
+    crate::{
+        mem::*,
+        nes::*,
+        error::*,
+        region::*,
+        input::*,
+        cartridge::Rom,
+    },
+
+    std::{
+        fs,
+        path::Path,
+        fmt::Write as _,
+        io::{
+            self,
+            Write,
+        },
+        time::{
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+};
+
+// `emuVersion` is left out of written movies, its numbers are FCEUX
+// releases and mean nothing for another emulator
+pub const FM2_VERSION: u32 = 3;
+
+// Commands at the start of an input line, applied before the frame runs
+pub const COMMAND_RESET: Byte = 1 << 0;
+pub const COMMAND_POWER: Byte = 1 << 1;
+
+// Comment lines carrying the state hash after each frame, FM2 allows any
+// number of comments so other emulators still load the movie
+pub const HASH_COMMENT: &str = "stateHash";
+
+// Pad buttons as FM2 spells them, first character is the highest bit
+pub const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+// Zapper aim read back as off-screen
+pub const ZAPPER_OFF_SCREEN: u8 = 0xFF;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PortInput {
+    #[default]
+    None,
+    Joypad(Byte),
+    FourScore([Byte; 2]),
+    Zapper {
+        aim: Option<(u8, u8)>,
+        trigger: bool,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInput {
+    pub commands: Byte,
+    pub ports: [PortInput; PORT_COUNT],
+}
+
+// First frame whose state doesn't match the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+// An input log in FCEUX's FM2 text format. Movies always start from
+// power on, frame `n` is the input held while the `n`th frame runs
+#[derive(Debug, Clone)]
+pub struct Movie {
+    pub region: Region,
+    pub devices: [Device; PORT_COUNT],
+
+    pub rom_filename: String,
+    pub rom_checksum: String,
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+
+    pub frames: Vec<FrameInput>,
+    // State after each recorded frame, see `Nes::state_hash`
+    pub hashes: Vec<u64>,
+}
+
+impl PortInput {
+    pub fn capture(port: &dyn ControllerPort) -> Self {
+        if let Some(pad) = port.downcast_ref::<Joypad>() {
+            PortInput::Joypad(pad.buttons())
+        } else if let Some(four_score) = port.downcast_ref::<FourScore>() {
+            PortInput::FourScore(four_score.pads.map(|pad| pad.buttons()))
+        } else if let Some(zapper) = port.downcast_ref::<Zapper>() {
+            PortInput::Zapper { aim: zapper.aim,
+                                trigger: zapper.trigger }
+        } else {
+            PortInput::None
+        }
+    }
+
+    pub fn apply(&self, port: &mut dyn ControllerPort) {
+        match *self {
+            PortInput::None => {}
+            PortInput::Joypad(buttons) => port.set_pad(0, buttons),
+            PortInput::FourScore(pads) => {
+                for (index, buttons) in pads.into_iter().enumerate() {
+                    port.set_pad(index, buttons);
+                }
+            }
+            PortInput::Zapper { aim, trigger } => {
+                if let Some(zapper) = port.downcast_mut::<Zapper>() {
+                    zapper.aim = aim;
+                    zapper.trigger = trigger;
+                }
+            }
+        }
+    }
+}
+
+impl FrameInput {
+    pub fn capture(input: &Input, commands: Byte) -> Self {
+        Self { commands,
+               ports: [0, 1].map(|port| PortInput::capture(input.port(port))) }
+    }
+
+    pub fn apply(&self, input: &mut Input) {
+        for (port, state) in self.ports.iter().enumerate() {
+            state.apply(input.port_mut(port));
+        }
+    }
+}
+
+impl Movie {
+    // An empty movie for the machine's region and devices, the ROM fields
+    // are for the frontend to fill in, see `rom_checksum`
+    pub fn new(nes: &Nes) -> Result<Self, MovieError> {
+        let devices = [0, 1].map(|port| nes.input().port(port).device());
+
+        for device in devices {
+            if device == Device::Paddle {
+                return Err(MovieError::UnsupportedDevice(device));
+            }
+        }
+
+        Ok(Self { region: nes.region(),
+                  devices,
+                  rom_filename: String::new(),
+                  rom_checksum: String::new(),
+                  guid: new_guid(),
+                  rerecord_count: 0,
+                  comments: Vec::new(),
+                  frames: Vec::new(),
+                  hashes: Vec::new() })
+    }
+
+    #[inline(always)]
+    pub fn four_score(&self) -> bool {
+        self.devices == [Device::FourScore; PORT_COUNT]
+    }
+
+    // Plugs in the movie's devices and powers the console on, recording
+    // and playback both start from here
+    pub fn start(&self, nes: &mut Nes) {
+        nes.set_region(self.region);
+
+        if self.four_score() {
+            nes.input_mut().connect_four_score();
+        } else {
+            for (port, device) in self.devices.into_iter().enumerate() {
+                let device: Box<dyn ControllerPort> = match device {
+                    Device::Zapper => Box::new(Zapper::default()),
+                    Device::Unplugged => Box::new(Unplugged),
+                    _ => Box::new(Joypad::default()),
+                };
+
+                nes.input_mut().connect(port, device);
+            }
+        }
+
+        nes.power();
+    }
+
+    // Runs a frame with whatever the frontend has set on the ports and
+    // appends it, `commands` are the reset or power presses before it
+    pub fn record_frame(&mut self, nes: &mut Nes, commands: Byte) -> Result<(), ExecError> {
+        let frame = FrameInput::capture(nes.input(), commands);
+        nes.run_frame_with(&frame)?;
+
+        self.frames.push(frame);
+        self.hashes.push(nes.state_hash());
+
+        Ok(())
+    }
+
+    pub fn play_frame(&self, nes: &mut Nes, frame: usize) -> Result<(), ExecError> {
+        nes.run_frame_with(&self.frames[frame])
+    }
+
+    pub fn play(&self, nes: &mut Nes) -> Result<(), ExecError> {
+        self.start(nes);
+
+        for frame in 0..self.frames.len() {
+            self.play_frame(nes, frame)?;
+        }
+
+        Ok(())
+    }
+
+    // Plays the movie back from power on and checks the state after every
+    // frame that has a recorded hash
+    pub fn verify(&self, nes: &mut Nes) -> Result<Option<Desync>, ExecError> {
+        self.start(nes);
+
+        for frame in 0..self.frames.len() {
+            self.play_frame(nes, frame)?;
+
+            let Some(&expected) = self.hashes.get(frame) else {
+                continue;
+            };
+
+            let actual = nes.state_hash();
+            if actual != expected {
+                return Ok(Some(Desync { frame, expected, actual }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut movie = Self { region: Region::Ntsc,
+                               devices: [Device::Joypad; PORT_COUNT],
+                               rom_filename: String::new(),
+                               rom_checksum: String::new(),
+                               guid: String::new(),
+                               rerecord_count: 0,
+                               comments: Vec::new(),
+                               frames: Vec::new(),
+                               hashes: Vec::new() };
+        let mut four_score = false;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            let error = MovieError::Parse(index + 1);
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('|') {
+                let devices = if four_score { [Device::FourScore; PORT_COUNT] } else { movie.devices };
+                let frame = parse_frame(line, devices).ok_or(error)?;
+
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || value.parse::<u32>().map_err(|_| error);
+
+            match key {
+                "version" if number()? != FM2_VERSION => return Err(error),
+                "rerecordCount" => movie.rerecord_count = number()?,
+                "palFlag" if number()? != 0 => movie.region = Region::Pal,
+                "dendy" if number()? != 0 => movie.region = Region::Dendy,
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => movie.rom_checksum = value.to_string(),
+                "guid" => movie.guid = value.to_string(),
+                "fourscore" => four_score = number()? != 0,
+                "port0" | "port1" => {
+                    let port = (key == "port1") as usize;
+                    movie.devices[port] = match number()? {
+                        0 => Device::Unplugged,
+                        1 => Device::Joypad,
+                        2 => Device::Zapper,
+
+                        _ => return Err(error),
+                    };
+                }
+                "comment" => match value.strip_prefix(HASH_COMMENT) {
+                    Some(hash) => {
+                        let hash = u64::from_str_radix(hash.trim(), 16).map_err(|_| error)?;
+                        movie.hashes.push(hash);
+                    }
+                    None => movie.comments.push(value.to_string()),
+                },
+
+                _ => {}
+            }
+        }
+
+        if four_score {
+            movie.devices = [Device::FourScore; PORT_COUNT];
+        }
+
+        Ok(movie)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MovieError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let port = |device: Device| match device {
+            Device::Unplugged => 0,
+            Device::Zapper => 2,
+            _ => 1,
+        };
+
+        writeln!(out, "version {FM2_VERSION}")?;
+        writeln!(out, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(out, "palFlag {}", (self.region == Region::Pal) as u8)?;
+        if self.region == Region::Dendy {
+            writeln!(out, "dendy 1")?;
+        }
+        writeln!(out, "romFilename {}", self.rom_filename)?;
+        writeln!(out, "romChecksum {}", self.rom_checksum)?;
+        writeln!(out, "guid {}", self.guid)?;
+        writeln!(out, "fourscore {}", self.four_score() as u8)?;
+        writeln!(out, "microphone 0")?;
+        writeln!(out, "port0 {}", port(self.devices[0]))?;
+        writeln!(out, "port1 {}", port(self.devices[1]))?;
+        writeln!(out, "port2 0")?;
+        writeln!(out, "FDS 0")?;
+        writeln!(out, "NewPPU 0")?;
+
+        for comment in &self.comments {
+            writeln!(out, "comment {comment}")?;
+        }
+        for hash in &self.hashes {
+            writeln!(out, "comment {HASH_COMMENT} {hash:016x}")?;
+        }
+
+        for frame in &self.frames {
+            writeln!(out, "{}", format_frame(frame))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        self.write(&mut out)?;
+
+        out.flush()
+    }
+}
+
+impl Nes {
+    // Presses reset or power if asked to, sets the ports and runs a frame
+    pub fn run_frame_with(&mut self, frame: &FrameInput) -> Result<(), ExecError> {
+        if (frame.commands & COMMAND_POWER) != 0 {
+            self.power();
+        } else if (frame.commands & COMMAND_RESET) != 0 {
+            self.reset();
+        }
+
+        frame.apply(self.input_mut());
+        self.run_frame()
+    }
+}
+
+fn parse_buttons(field: &str) -> Option<Byte> {
+    if field.len() != FM2_BUTTONS.len() {
+        return None;
+    }
+
+    // Anything but a dot or a space counts as held
+    let buttons = field.bytes().enumerate()
+                       .filter(|&(_, c)| c != b'.' && c != b' ')
+                       .fold(0, |buttons, (index, _)| buttons | (0x80 >> index));
+    Some(buttons)
+}
+
+fn format_buttons(buttons: Byte) -> String {
+    FM2_BUTTONS.iter().enumerate()
+               .map(|(index, &c)| if (buttons & (0x80 >> index)) != 0 { c as char } else { '.' })
+               .collect()
+}
+
+// Zapper fields are `x y trigger` followed by values we don't use
+fn parse_zapper(field: &str) -> Option<PortInput> {
+    let mut values = field.split_whitespace().map(|value| value.parse::<u32>().ok());
+    let (x, y, trigger) = (values.next()??, values.next()??, values.next()??);
+
+    let aim = (x < 256 && y < 240).then_some((x as u8, y as u8));
+    Some(PortInput::Zapper { aim, trigger: trigger != 0 })
+}
+
+fn parse_frame(line: &str, devices: [Device; PORT_COUNT]) -> Option<FrameInput> {
+    let fields: Vec<&str> = line.split('|').collect();
+    let commands = fields.get(1)?.trim().parse().ok()?;
+    let field = |index: usize| fields.get(index).copied();
+
+    let ports = if devices == [Device::FourScore; PORT_COUNT] {
+        let players = [field(2)?, field(3)?, field(4)?, field(5)?].map(parse_buttons);
+        [PortInput::FourScore([players[0]?, players[2]?]), PortInput::FourScore([players[1]?, players[3]?])]
+    } else {
+        let mut ports = [PortInput::None; PORT_COUNT];
+
+        for (port, device) in devices.into_iter().enumerate() {
+            let field = field(2 + port)?;
+            ports[port] = match device {
+                Device::Joypad => PortInput::Joypad(parse_buttons(field)?),
+                Device::Zapper => parse_zapper(field)?,
+                _ => PortInput::None,
+            };
+        }
+
+        ports
+    };
+
+    Some(FrameInput { commands, ports })
+}
+
+fn format_frame(frame: &FrameInput) -> String {
+    let mut line = format!("|{}|", frame.commands);
+
+    match frame.ports {
+        [PortInput::FourScore(odd), PortInput::FourScore(even)] => {
+            for buttons in [odd[0], even[0], odd[1], even[1]] {
+                line += &format_buttons(buttons);
+                line.push('|');
+            }
+        }
+        ports => {
+            for port in ports {
+                match port {
+                    PortInput::Joypad(buttons) => line += &format_buttons(buttons),
+                    PortInput::Zapper { aim, trigger } => {
+                        let (x, y) = aim.unwrap_or((ZAPPER_OFF_SCREEN, ZAPPER_OFF_SCREEN));
+                        let _ = write!(line, "{x} {y} {} 0 0", trigger as u8);
+                    }
+                    _ => {}
+                }
+
+                line.push('|');
+            }
+        }
+    }
+
+    // The expansion port is never used
+    line.push('|');
+    line
+}
+
+// FCEUX's checksum of a ROM file, the MD5 of PRG and CHR as base64
+pub fn rom_checksum(raw: &[Byte]) -> Result<String, RomError> {
+    let rom = Rom::parse(raw)?;
+    let digest = md5(&[rom.prg, rom.chr].concat());
+
+    Ok(format!("base64:{}", base64(&digest)))
+}
+
+fn new_guid() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos()).unwrap_or(0);
+    let hex: String = md5(&nanos.to_le_bytes()).iter().map(|byte| format!("{byte:02X}")).collect();
+
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64(data: &[u8]) -> String {
+    let mut out = String::new();
+
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, &byte)| bits | (byte as u32) << (16 - index * 8));
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                out.push(BASE64_ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+const MD5_CONSTANTS: [u32; 64] = [
+    0xD76AA478, 0xE8C7B756, 0x242070DB, 0xC1BDCEEE, 0xF57C0FAF, 0x4787C62A, 0xA8304613, 0xFD469501,
+    0x698098D8, 0x8B44F7AF, 0xFFFF5BB1, 0x895CD7BE, 0x6B901122, 0xFD987193, 0xA679438E, 0x49B40821,
+    0xF61E2562, 0xC040B340, 0x265E5A51, 0xE9B6C7AA, 0xD62F105D, 0x02441453, 0xD8A1E681, 0xE7D3FBC8,
+    0x21E1CDE6, 0xC33707D6, 0xF4D50D87, 0x455A14ED, 0xA9E3E905, 0xFCEFA3F8, 0x676F02D9, 0x8D2A4C8A,
+    0xFFFA3942, 0x8771F681, 0x6D9D6122, 0xFDE5380C, 0xA4BEEA44, 0x4BDECFA9, 0xF6BB4B60, 0xBEBFBC70,
+    0x289B7EC6, 0xEAA127FA, 0xD4EF3085, 0x04881D05, 0xD9D4D039, 0xE6DB99E5, 0x1FA27CF8, 0xC4AC5665,
+    0xF4292244, 0x432AFF97, 0xAB9423A7, 0xFC93A039, 0x655B59C3, 0x8F0CCC92, 0xFFEFF47D, 0x85845DD1,
+    0x6FA87E4F, 0xFE2CE6E0, 0xA3014314, 0x4E0811A1, 0xF7537E82, 0xBD3AF235, 0x2AD7D2BB, 0xEB86D391,
+];
+
+pub(crate) fn md5(data: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks_exact(64) {
+        let words: Vec<u32> = block.chunks_exact(4)
+                                   .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                                   .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for round in 0..64 {
+            let (f, index) = match round / 16 {
+                0 => ((b & c) | (!b & d), round),
+                1 => ((d & b) | (!d & c), (5 * round + 1) % 16),
+                2 => (b ^ c ^ d, (3 * round + 5) % 16),
+                _ => (c ^ (b | !d), (7 * round) % 16),
+            };
+
+            let shift = MD5_SHIFTS[(round / 16) * 4 + round % 4];
+            let sum = a.wrapping_add(f).wrapping_add(MD5_CONSTANTS[round]).wrapping_add(words[index]);
+
+            (a, d, c) = (d, c, b);
+            b = b.wrapping_add(sum.rotate_left(shift));
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (out, word) in digest.chunks_exact_mut(4).zip(state) {
+        out.copy_from_slice(&word.to_le_bytes());
+    }
+
+    digest
+}
@@ -0,0 +1,183 @@
+use {
+    crate::{
+        cpu::*,
+        mem::*,
+        status::*,
+        error::*,
+        cartridge::*,
+        region::*,
+        input::*,
+        consts::*,
+        apu::APU_STATUS,
+        state::*,
+    },
+
+    std::{
+        io,
+        mem,
+    },
+};
+
+// The whole console. The CPU drives the clock, every cycle it spends on
+// the bus also steps the PPU and the cartridge, see `Memory::tick`
+pub struct Nes {
+    pub cpu: Cpu,
+}
+
+impl Nes {
+    pub fn new(region: Region) -> Self {
+        let mut cpu = Cpu::default();
+        cpu.reset();
+        cpu.mem.set_region(region);
+
+        Self { cpu }
+    }
+
+    pub fn with_cartridge(cartridge: Cartridge, region: Region) -> Self {
+        let mut nes = Self::new(region);
+        nes.cpu.load_cartridge(cartridge);
+
+        nes
+    }
+
+    #[inline(always)]
+    pub fn region(&self) -> Region {
+        self.cpu.mem.region()
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.cpu.mem.set_region(region);
+    }
+
+    // The reset button. RAM and the cartridge keep their contents, the
+    // CPU goes through its reset sequence and the PPU and APU are silenced
+    pub fn reset(&mut self) {
+        let cpu = &mut self.cpu;
+
+        cpu.sp = cpu.sp.wrapping_sub(3);
+        cpu.status.set_on(CpuStatus::INTERRUPT);
+
+        cpu.mem.write(APU_STATUS, 0);
+        cpu.mem.ppu_mut().ctrl = 0;
+        cpu.mem.ppu_mut().mask = 0;
+
+        cpu.pc = cpu.mem.read_word(RESET_VECTOR);
+    }
+
+    // Power cycle. Everything starts over, the cartridge included except
+    // for its battery RAM. What's plugged into the ports and the output
+    // sample rate stay
+    pub fn power(&mut self) {
+        let region = self.region();
+        let sample_rate = self.sample_rate();
+
+        let input = mem::take(&mut self.cpu.mem.input);
+        let cartridge = self.cpu.mem.remove_cartridge();
+
+        *self = Self::new(region);
+        self.set_sample_rate(sample_rate);
+        self.cpu.mem.input = input;
+
+        if let Some(mut cartridge) = cartridge {
+            cartridge.power();
+            self.cpu.load_cartridge(cartridge);
+        }
+    }
+
+    // Writes the cartridge's battery RAM out, see `Cartridge::flush`
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.cpu.mem.cartridge_mut().map_or(Ok(()), Cartridge::flush)
+    }
+
+    // Runs a single instruction along with any interrupt or DMA around it
+    #[inline(always)]
+    pub fn step(&mut self) -> Result<ExecStatus, ExecError> {
+        self.cpu.exec_next()
+    }
+
+    // Runs until the PPU wraps around to the next frame, the framebuffer
+    // then holds the picture that was just finished
+    pub fn run_frame(&mut self) -> Result<(), ExecError> {
+        let frame = self.frame();
+
+        while self.frame() == frame {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn frame(&self) -> u64 {
+        self.cpu.mem.ppu().frame
+    }
+
+    #[inline(always)]
+    pub fn cycles(&self) -> u64 {
+        self.cpu.mem.cycles()
+    }
+
+    pub fn framebuffer(&self) -> &[Word] {
+        self.cpu.mem.ppu().framebuffer()
+    }
+
+    // FNV-1a over the CPU registers, RAM and what the PPU has drawn, cheap
+    // enough to take every frame and stable across builds and platforms
+    pub fn state_hash(&self) -> u64 {
+        let cpu = &self.cpu;
+        let ppu = cpu.mem.ppu();
+
+        let registers = [cpu.acc, cpu.x, cpu.y, cpu.sp, cpu.status.to_stack(false)];
+        let position = [ppu.scanline, ppu.dot];
+
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[Byte]| hash = fnv1a(hash, bytes);
+
+        write(&registers);
+        write(&cpu.pc.to_le_bytes());
+        write(&self.cycles().to_le_bytes());
+        write(cpu.mem.ram());
+
+        write(&ppu.frame.to_le_bytes());
+        position.iter().for_each(|value| write(&value.to_le_bytes()));
+        write(&ppu.oam);
+        write(&ppu.palette);
+        write(&ppu.nametables);
+        ppu.framebuffer().iter().for_each(|entry| write(&entry.to_le_bytes()));
+
+        hash
+    }
+
+    // Held buttons for a port, see the `BUTTON_*` masks
+    pub fn set_buttons(&mut self, port: usize, buttons: Byte) {
+        self.cpu.mem.input.set_buttons(port, buttons);
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.cpu.mem.input
+    }
+
+    pub fn input_mut(&mut self) -> &mut Input {
+        &mut self.cpu.mem.input
+    }
+
+    // Audio produced so far, at the rate set with `set_sample_rate`
+    pub fn drain_samples(&mut self, out: &mut [f32]) -> usize {
+        self.cpu.mem.apu_mut().drain_samples(out)
+    }
+
+    #[inline(always)]
+    pub fn sample_rate(&self) -> u32 {
+        self.cpu.mem.apu().sample_rate()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.mem.apu_mut().set_sample_rate(sample_rate);
+    }
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Self::new(Region::default())
+    }
+}
@@ -26,6 +26,10 @@ pub enum Opcode {
     Tax,
     Tay,
 
+    Cli,
+    Sei,
+    Rti,
+
     Bcs,
     Bcc,
     Beq,
@@ -43,6 +47,80 @@ pub enum Opcode {
     Lda(AddrMode, Word),
 }
 
+// Base cycle counts, without page crossing or taken branch penalties
+pub const CYCLES: [u8; 256] = [
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+];
+
+const IMP: AddrMode = AddrMode::Implied;
+const ACC: AddrMode = AddrMode::Accumulator;
+const IMM: AddrMode = AddrMode::Immediate;
+const REL: AddrMode = AddrMode::Relative;
+const ZP:  AddrMode = AddrMode::ZeroPage;
+const ZPX: AddrMode = AddrMode::ZeroPageX;
+const ZPY: AddrMode = AddrMode::ZeroPageY;
+const ABS: AddrMode = AddrMode::Absolute;
+const ABX: AddrMode = AddrMode::AbsoluteX;
+const ABY: AddrMode = AddrMode::AbsoluteY;
+const IND: AddrMode = AddrMode::Indirect;
+const IZX: AddrMode = AddrMode::IndirectX;
+const IZY: AddrMode = AddrMode::IndirectY;
+
+// Every official instruction, `OPCODES` is built from this for the
+// instructions the CPU emulates. Empty names are the unofficial opcodes
+pub const MNEMONICS: [&str; 256] = [
+    "BRK", "ORA", "",    "",    "",    "ORA", "ASL", "",    "PHP", "ORA", "ASL", "",    "",    "ORA", "ASL", "",
+    "BPL", "ORA", "",    "",    "",    "ORA", "ASL", "",    "CLC", "ORA", "",    "",    "",    "ORA", "ASL", "",
+    "JSR", "AND", "",    "",    "BIT", "AND", "ROL", "",    "PLP", "AND", "ROL", "",    "BIT", "AND", "ROL", "",
+    "BMI", "AND", "",    "",    "",    "AND", "ROL", "",    "SEC", "AND", "",    "",    "",    "AND", "ROL", "",
+    "RTI", "EOR", "",    "",    "",    "EOR", "LSR", "",    "PHA", "EOR", "LSR", "",    "JMP", "EOR", "LSR", "",
+    "BVC", "EOR", "",    "",    "",    "EOR", "LSR", "",    "CLI", "EOR", "",    "",    "",    "EOR", "LSR", "",
+    "RTS", "ADC", "",    "",    "",    "ADC", "ROR", "",    "PLA", "ADC", "ROR", "",    "JMP", "ADC", "ROR", "",
+    "BVS", "ADC", "",    "",    "",    "ADC", "ROR", "",    "SEI", "ADC", "",    "",    "",    "ADC", "ROR", "",
+    "",    "STA", "",    "",    "STY", "STA", "STX", "",    "DEY", "",    "TXA", "",    "STY", "STA", "STX", "",
+    "BCC", "STA", "",    "",    "STY", "STA", "STX", "",    "TYA", "STA", "TXS", "",    "",    "STA", "",    "",
+    "LDY", "LDA", "LDX", "",    "LDY", "LDA", "LDX", "",    "TAY", "LDA", "TAX", "",    "LDY", "LDA", "LDX", "",
+    "BCS", "LDA", "",    "",    "LDY", "LDA", "LDX", "",    "CLV", "LDA", "TSX", "",    "LDY", "LDA", "LDX", "",
+    "CPY", "CMP", "",    "",    "CPY", "CMP", "DEC", "",    "INY", "CMP", "DEX", "",    "CPY", "CMP", "DEC", "",
+    "BNE", "CMP", "",    "",    "",    "CMP", "DEC", "",    "CLD", "CMP", "",    "",    "",    "CMP", "DEC", "",
+    "CPX", "SBC", "",    "",    "CPX", "SBC", "INC", "",    "INX", "SBC", "NOP", "",    "CPX", "SBC", "INC", "",
+    "BEQ", "SBC", "",    "",    "",    "SBC", "INC", "",    "SED", "SBC", "",    "",    "",    "SBC", "INC", "",
+];
+
+pub const MODES: [AddrMode; 256] = [
+    IMP, IZX, IMP, IMP, IMP, ZP,  ZP,  IMP, IMP, IMM, ACC, IMP, IMP, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP,
+    ABS, IZX, IMP, IMP, ZP,  ZP,  ZP,  IMP, IMP, IMM, ACC, IMP, ABS, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP,
+    IMP, IZX, IMP, IMP, IMP, ZP,  ZP,  IMP, IMP, IMM, ACC, IMP, ABS, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP,
+    IMP, IZX, IMP, IMP, IMP, ZP,  ZP,  IMP, IMP, IMM, ACC, IMP, IND, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP,
+    IMP, IZX, IMP, IMP, ZP,  ZP,  ZP,  IMP, IMP, IMP, IMP, IMP, ABS, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, ZPX, ZPX, ZPY, IMP, IMP, ABY, IMP, IMP, IMP, ABX, IMP, IMP,
+    IMM, IZX, IMM, IMP, ZP,  ZP,  ZP,  IMP, IMP, IMM, IMP, IMP, ABS, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, ZPX, ZPX, ZPY, IMP, IMP, ABY, IMP, IMP, ABX, ABX, ABY, IMP,
+    IMM, IZX, IMP, IMP, ZP,  ZP,  ZP,  IMP, IMP, IMM, IMP, IMP, ABS, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP,
+    IMM, IZX, IMP, IMP, ZP,  ZP,  ZP,  IMP, IMP, IMM, IMP, IMP, ABS, ABS, ABS, IMP,
+    REL, IZY, IMP, IMP, IMP, ZPX, ZPX, IMP, IMP, ABY, IMP, IMP, IMP, ABX, ABX, IMP,
+];
+
 static __INITIALIZED: AtomicBool = AtomicBool::new(false);
 static mut OPCODES: [Opcode; 256] = [Opcode::Uninitialized; 256];
 
@@ -51,104 +129,60 @@ pub fn lookup_opcode(code: Byte) -> Opcode {
     unsafe { OPCODES[code as usize] }
 }
 
-unsafe fn init() {    
-    OPCODES[0x00] = Opcode::Brk;
-
-    // BEQ
-
-    OPCODES[0xF0] = Opcode::Beq;
-
-    // BCC
-
-    OPCODES[0x90] = Opcode::Bcc;
-
-    // BCS
-    
-    OPCODES[0xB0] = Opcode::Bcs;
-
-    // ASL
-
-    OPCODES[0x0A] = Opcode::Asl(AddrMode::Accumulator, 0);
-
-    OPCODES[0x06] = Opcode::Asl(AddrMode::ZeroPage, 1);
-    OPCODES[0x16] = Opcode::Asl(AddrMode::ZeroPageX, 1);
-
-    OPCODES[0x0E] = Opcode::Asl(AddrMode::Absolute, 2);
-    OPCODES[0x1E] = Opcode::Asl(AddrMode::AbsoluteX, 2);
-
-    // AND
-
-    OPCODES[0x29] = Opcode::And(AddrMode::Immediate, 1);
-
-    OPCODES[0x25] = Opcode::And(AddrMode::ZeroPage, 1);
-    OPCODES[0x35] = Opcode::And(AddrMode::ZeroPageX, 1);
-
-    OPCODES[0x2D] = Opcode::And(AddrMode::Absolute, 2);
-    OPCODES[0x3D] = Opcode::And(AddrMode::AbsoluteX, 2);
-    OPCODES[0x39] = Opcode::And(AddrMode::AbsoluteY, 2);
-
-    OPCODES[0x21] = Opcode::And(AddrMode::IndirectX, 1);
-    OPCODES[0x31] = Opcode::And(AddrMode::IndirectY, 1);
-
-    // ADC
-
-    OPCODES[0x69] = Opcode::Adc(AddrMode::Immediate, 1);
-
-    OPCODES[0x65] = Opcode::Adc(AddrMode::ZeroPage, 1);
-    OPCODES[0x75] = Opcode::Adc(AddrMode::ZeroPageX, 1);
-    
-    OPCODES[0x6D] = Opcode::Adc(AddrMode::Absolute, 2);
-    OPCODES[0x7D] = Opcode::Adc(AddrMode::AbsoluteX, 2);
-    OPCODES[0x79] = Opcode::Adc(AddrMode::AbsoluteY, 2);
+#[inline(always)]
+pub fn lookup_cycles(code: Byte) -> u8 {
+    CYCLES[code as usize]
+}
 
-    OPCODES[0x61] = Opcode::Adc(AddrMode::IndirectX, 1);
-    OPCODES[0x71] = Opcode::Adc(AddrMode::IndirectY, 1);
+// Name and addressing mode of an official instruction
+#[inline(always)]
+pub fn lookup_instruction(code: Byte) -> Option<(&'static str, AddrMode)> {
+    match MNEMONICS[code as usize] {
+        "" => None,
+        mnemonic => Some((mnemonic, MODES[code as usize])),
+    }
+}
 
-    // STA
-    OPCODES[0x85] = Opcode::Sta(AddrMode::ZeroPage, 1);
-    OPCODES[0x95] = Opcode::Sta(AddrMode::ZeroPageX, 1);
+// Every instruction the CPU emulates, with the mode and operand length
+// taken from the shared table
+unsafe fn init() {
+    for code in 0..=0xFF {
+        let Some((mnemonic, mode)) = lookup_instruction(code) else {
+            continue;
+        };
 
-    OPCODES[0x8D] = Opcode::Sta(AddrMode::Absolute, 2);
-    OPCODES[0x9D] = Opcode::Sta(AddrMode::AbsoluteX, 2);
-    OPCODES[0x99] = Opcode::Sta(AddrMode::AbsoluteY, 2);
-    
-    OPCODES[0x81] = Opcode::Sta(AddrMode::IndirectX, 1);
-    OPCODES[0x91] = Opcode::Sta(AddrMode::IndirectY, 1);
+        let length = mode.operand_size();
 
-    // STY
-    OPCODES[0x84] = Opcode::Sty(AddrMode::ZeroPage, 1);
-    OPCODES[0x94] = Opcode::Sty(AddrMode::ZeroPageY, 1);
-    OPCODES[0x8C] = Opcode::Sty(AddrMode::Absolute, 2);
+        OPCODES[code as usize] = match mnemonic {
+            "BRK" => Opcode::Brk,
 
-    // STX
-    OPCODES[0x86] = Opcode::Stx(AddrMode::ZeroPage, 1);
-    OPCODES[0x96] = Opcode::Stx(AddrMode::ZeroPageY, 1);
-    OPCODES[0x8E] = Opcode::Stx(AddrMode::Absolute, 2);
+            "INX" => Opcode::Inx,
+            "INY" => Opcode::Iny,
 
-    // TAX
-    OPCODES[0xAA] = Opcode::Tax;
+            "TAX" => Opcode::Tax,
+            "TAY" => Opcode::Tay,
 
-    // TAY
-    OPCODES[0xA8] = Opcode::Tay;
+            "CLI" => Opcode::Cli,
+            "SEI" => Opcode::Sei,
+            "RTI" => Opcode::Rti,
 
-    // INX
-    OPCODES[0xE8] = Opcode::Inx;
+            "BCS" => Opcode::Bcs,
+            "BCC" => Opcode::Bcc,
+            "BEQ" => Opcode::Beq,
 
-    // INY
-    OPCODES[0xC8] = Opcode::Iny;
+            "ASL" => Opcode::Asl(mode, length),
+            "AND" => Opcode::And(mode, length),
 
-    // LDA
-    OPCODES[0xA9] = Opcode::Lda(AddrMode::Immediate, 1);
+            "STX" => Opcode::Stx(mode, length),
+            "STY" => Opcode::Sty(mode, length),
+            "STA" => Opcode::Sta(mode, length),
 
-    OPCODES[0xA5] = Opcode::Lda(AddrMode::ZeroPage, 1);
-    OPCODES[0xB5] = Opcode::Lda(AddrMode::ZeroPageX, 1);
-    
-    OPCODES[0xAD] = Opcode::Lda(AddrMode::Absolute, 2);
-    OPCODES[0xBD] = Opcode::Lda(AddrMode::AbsoluteX, 2);
-    OPCODES[0xB9] = Opcode::Lda(AddrMode::AbsoluteY, 2);
+            "ADC" => Opcode::Adc(mode, length),
+            "LDA" => Opcode::Lda(mode, length),
 
-    OPCODES[0xA1] = Opcode::Lda(AddrMode::IndirectX, 1);
-    OPCODES[0xB1] = Opcode::Lda(AddrMode::IndirectY, 1);
+            _ => continue,
+        };
+    }
 }
 
 pub fn try_init() -> InitializeState {
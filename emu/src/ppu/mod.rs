@@ -0,0 +1,355 @@
+use {
+    crate::{
+        mem::*,
+        cartridge::*,
+        region::*,
+        state::*,
+    },
+};
+
+pub mod scroll;
+pub mod render;
+pub mod sprites;
+pub mod palette;
+
+#[cfg(test)]
+mod tests;
+
+pub use {
+    scroll::Scroll,
+    render::*,
+    sprites::*,
+    palette::*,
+};
+
+pub const PPU_SPACE: Word     = 0x2000;
+pub const PPU_SPACE_END: Word = 0x3FFF;
+
+pub const PPUCTRL: Word   = 0x2000;
+pub const PPUMASK: Word   = 0x2001;
+pub const PPUSTATUS: Word = 0x2002;
+pub const OAMADDR: Word   = 0x2003;
+pub const OAMDATA: Word   = 0x2004;
+pub const PPUSCROLL: Word = 0x2005;
+pub const PPUADDR: Word   = 0x2006;
+pub const PPUDATA: Word   = 0x2007;
+
+pub const NAMETABLE_SPACE: Word = 0x2000;
+pub const PALETTE_SPACE: Word   = 0x3F00;
+
+pub const OAM_SIZE: usize     = 0x100;
+pub const PALETTE_SIZE: usize = 0x20;
+
+// The console's CIRAM, four-screen boards bring the other 2K
+pub const NAMETABLE_RAM_SIZE: usize = 0x0800;
+
+pub const CTRL_NAMETABLE: Byte        = 0x03;
+pub const CTRL_INCREMENT: Byte        = 1 << 2;
+pub const CTRL_SPRITE_TABLE: Byte     = 1 << 3;
+pub const CTRL_BACKGROUND_TABLE: Byte = 1 << 4;
+pub const CTRL_SPRITE_SIZE: Byte      = 1 << 5;
+pub const CTRL_NMI: Byte              = 1 << 7;
+
+pub const MASK_GREYSCALE: Byte        = 1 << 0;
+pub const MASK_BACKGROUND_LEFT: Byte  = 1 << 1;
+pub const MASK_SPRITES_LEFT: Byte     = 1 << 2;
+pub const MASK_BACKGROUND: Byte       = 1 << 3;
+pub const MASK_SPRITES: Byte          = 1 << 4;
+pub const MASK_EMPHASIS: Byte         = 0xE0;
+
+pub const STATUS_OVERFLOW: Byte       = 1 << 5;
+pub const STATUS_SPRITE_ZERO: Byte    = 1 << 6;
+pub const STATUS_VBLANK: Byte         = 1 << 7;
+
+pub struct Ppu {
+    pub ctrl: Byte,
+    pub mask: Byte,
+    pub status: Byte,
+
+    pub scroll: Scroll,
+
+    pub oam_addr: Byte,
+    pub oam: [Byte; OAM_SIZE],
+
+    pub palette: [Byte; PALETTE_SIZE],
+    pub nametables: [Byte; NAMETABLE_RAM_SIZE],
+
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+
+    pub region: Region,
+
+    // Lifts the 8 sprites per line limit, overflow is still reported
+    pub unlimited_sprites: bool,
+
+    background: Background,
+    framebuffer: Box<[Word]>,
+
+    secondary_oam: Vec<SpriteEntry>,
+    sprites: Vec<Sprite>,
+    sprite_latch: Byte,
+
+    // Last value driven onto the PPU data bus, write-only registers
+    // and the unused PPUSTATUS bits read back from it
+    latch: Byte,
+    read_buffer: Byte,
+
+    nmi_pending: bool,
+}
+
+savestate!(Ppu {
+    ctrl, mask, status, scroll, oam_addr, oam, palette, nametables, scanline, dot, frame,
+    background, framebuffer, secondary_oam, sprites, sprite_latch, latch, read_buffer,
+    nmi_pending,
+});
+
+impl Ppu {
+    pub fn cpu_read(&mut self, addr: Word, cartridge: Option<&mut Cartridge>) -> Byte {
+        let data = match register(addr) {
+            PPUSTATUS => {
+                let data = (self.status & 0xE0) | (self.latch & 0x1F);
+
+                self.status &= !STATUS_VBLANK;
+                self.scroll.reset_toggle();
+
+                data
+            }
+
+            OAMDATA => self.oam_data(),
+
+            PPUDATA => {
+                let addr = self.scroll.v & 0x3FFF;
+                let data = if addr >= PALETTE_SPACE {
+                    // Palette reads skip the buffer, which is refilled
+                    // with the nametable byte "underneath" instead
+                    self.read_buffer = self.vram_read(addr - 0x1000, cartridge);
+                    (self.palette_read(addr) & 0x3F) | (self.latch & 0xC0)
+                } else {
+                    let buffered = self.read_buffer;
+                    self.read_buffer = self.vram_read(addr, cartridge);
+                    buffered
+                };
+
+                self.advance_vram_addr();
+                data
+            }
+
+            _ => self.latch,
+        };
+
+        self.latch = data;
+        data
+    }
+
+    pub fn cpu_peek(&self, addr: Word) -> Byte {
+        match register(addr) {
+            PPUSTATUS => (self.status & 0xE0) | (self.latch & 0x1F),
+            OAMDATA => self.oam_data(),
+
+            PPUDATA if (self.scroll.v & 0x3FFF) >= PALETTE_SPACE => {
+                (self.palette_read(self.scroll.v) & 0x3F) | (self.latch & 0xC0)
+            }
+            PPUDATA => self.read_buffer,
+
+            _ => self.latch,
+        }
+    }
+
+    pub fn cpu_write(&mut self, addr: Word, data: Byte, cartridge: Option<&mut Cartridge>) {
+        self.latch = data;
+
+        match register(addr) {
+            PPUCTRL => {
+                // Enabling NMI in the middle of vblank fires one right away
+                let nmi_before = self.nmi_output();
+                self.ctrl = data;
+                self.scroll.write_ctrl(data);
+
+                if !nmi_before && self.nmi_output() {
+                    self.nmi_pending = true;
+                }
+            }
+
+            PPUMASK => self.mask = data,
+
+            OAMADDR => self.oam_addr = data,
+            OAMDATA => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+
+            PPUSCROLL => self.scroll.write_scroll(data),
+            PPUADDR => self.scroll.write_addr(data),
+
+            PPUDATA => {
+                self.vram_write(self.scroll.v & 0x3FFF, data, cartridge);
+                self.advance_vram_addr();
+            }
+
+            _ => {}
+        }
+    }
+
+    pub fn vram_read(&mut self, addr: Word, cartridge: Option<&mut Cartridge>) -> Byte {
+        let addr = addr & 0x3FFF;
+
+        match (addr, cartridge) {
+            (PALETTE_SPACE.., _) => self.palette_read(addr),
+
+            (NAMETABLE_SPACE.., Some(cartridge)) => {
+                let mirroring = cartridge.mirroring();
+                cartridge.nametable_read(addr)
+                    .unwrap_or_else(|| self.nametables[nametable_offset(addr, mirroring)])
+            }
+            (NAMETABLE_SPACE.., None) => {
+                self.nametables[nametable_offset(addr, Mirroring::Horizontal)]
+            }
+
+            (_, Some(cartridge)) => cartridge.ppu_read(addr),
+            (_, None) => 0,
+        }
+    }
+
+    pub fn vram_write(&mut self, addr: Word, data: Byte, cartridge: Option<&mut Cartridge>) {
+        let addr = addr & 0x3FFF;
+
+        match (addr, cartridge) {
+            (PALETTE_SPACE.., _) => self.palette[palette_offset(addr)] = data,
+
+            (NAMETABLE_SPACE.., Some(cartridge)) => {
+                if !cartridge.nametable_write(addr, data) {
+                    self.nametables[nametable_offset(addr, cartridge.mirroring())] = data;
+                }
+            }
+            (NAMETABLE_SPACE.., None) => {
+                self.nametables[nametable_offset(addr, Mirroring::Horizontal)] = data;
+            }
+
+            (_, Some(cartridge)) => cartridge.ppu_write(addr, data),
+            (_, None) => {}
+        }
+    }
+
+    #[inline(always)]
+    pub fn palette_read(&self, addr: Word) -> Byte {
+        let data = self.palette[palette_offset(addr)];
+
+        if (self.mask & MASK_GREYSCALE) != 0 {
+            data & 0x30
+        } else {
+            data
+        }
+    }
+
+    #[inline(always)]
+    pub fn nmi_output(&self) -> bool {
+        (self.ctrl & CTRL_NMI) != 0 && (self.status & STATUS_VBLANK) != 0
+    }
+
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    pub fn enter_vblank(&mut self) {
+        self.status |= STATUS_VBLANK;
+
+        if self.nmi_output() {
+            self.nmi_pending = true;
+        }
+    }
+
+    // The pre-render line clears all three flags at once
+    pub fn leave_vblank(&mut self) {
+        self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO | STATUS_OVERFLOW);
+    }
+
+    // While rendering, $2007 bumps coarse X and Y together instead
+    fn advance_vram_addr(&mut self) {
+        if self.rendering_active() {
+            self.scroll.increment_x();
+            self.scroll.increment_y();
+        } else {
+            let step = if (self.ctrl & CTRL_INCREMENT) != 0 { 32 } else { 1 };
+            self.scroll.increment(step);
+        }
+    }
+
+    // Bits 2-4 of the attribute byte don't exist and read back as 0
+    fn oam_data(&self) -> Byte {
+        let data = self.oam[self.oam_addr as usize];
+
+        if (self.oam_addr & 0x03) == 0x02 {
+            data & 0xE3
+        } else {
+            data
+        }
+    }
+
+    pub fn new() -> Self {
+        Self { ctrl: 0,
+               mask: 0,
+               status: 0,
+
+               scroll: Scroll::default(),
+
+               oam_addr: 0,
+               oam: [0; OAM_SIZE],
+
+               palette: [0; PALETTE_SIZE],
+               nametables: [0; NAMETABLE_RAM_SIZE],
+
+               scanline: 0,
+               dot: 0,
+               frame: 0,
+
+               region: Region::default(),
+
+               unlimited_sprites: false,
+
+               background: Background::default(),
+               framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+
+               secondary_oam: Vec::with_capacity(SPRITE_COUNT),
+               sprites: Vec::with_capacity(SPRITE_COUNT),
+               sprite_latch: 0,
+
+               latch: 0,
+               read_buffer: 0,
+
+               nmi_pending: false }
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The eight registers repeat every 8 bytes up to $3FFF
+#[inline(always)]
+pub fn register(addr: Word) -> Word {
+    PPU_SPACE | (addr & 0x0007)
+}
+
+// $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries below them
+#[inline(always)]
+pub fn palette_offset(addr: Word) -> usize {
+    let index = (addr & 0x1F) as usize;
+
+    if (index & 0x13) == 0x10 {
+        index & 0x0F
+    } else {
+        index
+    }
+}
+
+// Offset into CIRAM, the cartridge has already claimed any access that
+// goes to its own VRAM
+pub fn nametable_offset(addr: Word, mirroring: Mirroring) -> usize {
+    let slot = ((addr >> 10) & 0x03) as usize;
+    let offset = addr as usize & (NAMETABLE_PAGE_SIZE - 1);
+
+    (mirroring.page(slot) & 0x01) * NAMETABLE_PAGE_SIZE + offset
+}
@@ -0,0 +1,138 @@
+use {
+    crate::{
+        mem::*,
+        error::*,
+    },
+
+    std::{
+        fs,
+        path::Path,
+    },
+};
+
+pub const COLOR_COUNT: usize    = 64;
+pub const EMPHASIS_COUNT: usize = 8;
+
+pub const PAL_BASE_SIZE: usize  = COLOR_COUNT * 3;
+pub const PAL_FULL_SIZE: usize  = COLOR_COUNT * EMPHASIS_COUNT * 3;
+
+// Emphasis darkens the channels that aren't emphasized, all three at
+// once darken everything
+pub const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+pub const EMPHASIS_RED: Word   = 1 << 6;
+pub const EMPHASIS_GREEN: Word = 1 << 7;
+pub const EMPHASIS_BLUE: Word  = 1 << 8;
+
+// The usual 2C02 approximation
+pub const NTSC_PALETTE: [[u8; 3]; COLOR_COUNT] = [
+    [0x54, 0x54, 0x54], [0x00, 0x1E, 0x74], [0x08, 0x10, 0x90], [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64], [0x5C, 0x00, 0x30], [0x54, 0x04, 0x00], [0x3C, 0x18, 0x00],
+    [0x20, 0x2A, 0x00], [0x08, 0x3A, 0x00], [0x00, 0x40, 0x00], [0x00, 0x3C, 0x00],
+    [0x00, 0x32, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+
+    [0x98, 0x96, 0x98], [0x08, 0x4C, 0xC4], [0x30, 0x32, 0xEC], [0x5C, 0x1E, 0xE4],
+    [0x88, 0x14, 0xB0], [0xA0, 0x14, 0x64], [0x98, 0x22, 0x20], [0x78, 0x3C, 0x00],
+    [0x54, 0x5A, 0x00], [0x28, 0x72, 0x00], [0x08, 0x7C, 0x00], [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+
+    [0xEC, 0xEE, 0xEC], [0x4C, 0x9A, 0xEC], [0x78, 0x7C, 0xEC], [0xB0, 0x62, 0xEC],
+    [0xE4, 0x54, 0xEC], [0xEC, 0x58, 0xB4], [0xEC, 0x6A, 0x64], [0xD4, 0x88, 0x20],
+    [0xA0, 0xAA, 0x00], [0x74, 0xC4, 0x00], [0x4C, 0xD0, 0x20], [0x38, 0xCC, 0x6C],
+    [0x38, 0xB4, 0xCC], [0x3C, 0x3C, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+
+    [0xEC, 0xEE, 0xEC], [0xA8, 0xCC, 0xEC], [0xBC, 0xBC, 0xEC], [0xD4, 0xB2, 0xEC],
+    [0xEC, 0xAE, 0xEC], [0xEC, 0xAE, 0xD4], [0xEC, 0xB4, 0xB0], [0xE4, 0xC4, 0x90],
+    [0xCC, 0xD2, 0x78], [0xB4, 0xDE, 0x78], [0xA8, 0xE2, 0x90], [0x98, 0xE2, 0xB4],
+    [0xA0, 0xD6, 0xE4], [0xA0, 0xA2, 0xA0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+// All 512 colour and emphasis combinations, indexed like framebuffer entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    pub fn ntsc() -> Self {
+        Self::from_colors(&NTSC_PALETTE)
+    }
+
+    // Derives the emphasized variants from 64 base colours
+    pub fn from_colors(base: &[[u8; 3]; COLOR_COUNT]) -> Self {
+        let mut colors = Vec::with_capacity(COLOR_COUNT * EMPHASIS_COUNT);
+
+        for emphasis in 0..EMPHASIS_COUNT {
+            let emphasis = (emphasis << 6) as Word;
+
+            colors.extend(base.iter().map(|&[red, green, blue]| {
+                if emphasis == 0 {
+                    return [red, green, blue];
+                }
+
+                let all = EMPHASIS_RED | EMPHASIS_GREEN | EMPHASIS_BLUE;
+                let attenuate = |channel: u8, bit: Word| {
+                    if (emphasis & bit) != 0 && emphasis != all {
+                        channel
+                    } else {
+                        (channel as f32 * EMPHASIS_ATTENUATION) as u8
+                    }
+                };
+
+                [attenuate(red, EMPHASIS_RED),
+                 attenuate(green, EMPHASIS_GREEN),
+                 attenuate(blue, EMPHASIS_BLUE)]
+            }));
+        }
+
+        Self { colors }
+    }
+
+    // Raw RGB triplets, either 64 base colours or all 512 combinations
+    pub fn from_pal(raw: &[u8]) -> Result<Self, PaletteError> {
+        match raw.len() {
+            PAL_BASE_SIZE => {
+                let mut base = [[0; 3]; COLOR_COUNT];
+                for (color, rgb) in base.iter_mut().zip(raw.chunks_exact(3)) {
+                    color.copy_from_slice(rgb);
+                }
+
+                Ok(Self::from_colors(&base))
+            }
+
+            PAL_FULL_SIZE => {
+                let colors = raw.chunks_exact(3)
+                                .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+                                .collect();
+
+                Ok(Self { colors })
+            }
+
+            size => Err(PaletteError::InvalidSize(size)),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PaletteError> {
+        let raw = fs::read(path)?;
+        Self::from_pal(&raw)
+    }
+
+    #[inline(always)]
+    pub fn rgb(&self, entry: Word) -> [u8; 3] {
+        self.colors[entry as usize & (COLOR_COUNT * EMPHASIS_COUNT - 1)]
+    }
+
+    // Writes 4 bytes (R, G, B, 0xFF) for every framebuffer entry
+    pub fn to_rgba(&self, framebuffer: &[Word], out: &mut [u8]) {
+        for (&entry, pixel) in framebuffer.iter().zip(out.chunks_exact_mut(4)) {
+            let [red, green, blue] = self.rgb(entry);
+            pixel.copy_from_slice(&[red, green, blue, 0xFF]);
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::ntsc()
+    }
+}
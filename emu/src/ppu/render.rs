@@ -0,0 +1,247 @@
+use {
+    super::*,
+};
+
+pub const SCREEN_WIDTH: usize  = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+pub const DOTS_PER_LINE: u16   = 341;
+
+// Tile data moves through 16-bit shifters, the high byte is the tile
+// being drawn and the low byte the one fetched next
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Background {
+    tile: Byte,
+    attribute: Byte,
+    pattern_low: Byte,
+    pattern_high: Byte,
+
+    shift_pattern_low: Word,
+    shift_pattern_high: Word,
+    shift_attribute_low: Word,
+    shift_attribute_high: Word,
+}
+
+savestate!(Background {
+    tile, attribute, pattern_low, pattern_high, shift_pattern_low, shift_pattern_high,
+    shift_attribute_low, shift_attribute_high,
+});
+
+impl Background {
+    fn reload(&mut self) {
+        self.shift_pattern_low = (self.shift_pattern_low & 0xFF00) | self.pattern_low as Word;
+        self.shift_pattern_high = (self.shift_pattern_high & 0xFF00) | self.pattern_high as Word;
+
+        let low = if (self.attribute & 0x01) != 0 { 0xFF } else { 0x00 };
+        let high = if (self.attribute & 0x02) != 0 { 0xFF } else { 0x00 };
+
+        self.shift_attribute_low = (self.shift_attribute_low & 0xFF00) | low;
+        self.shift_attribute_high = (self.shift_attribute_high & 0xFF00) | high;
+    }
+
+    fn shift(&mut self) {
+        self.shift_pattern_low <<= 1;
+        self.shift_pattern_high <<= 1;
+        self.shift_attribute_low <<= 1;
+        self.shift_attribute_high <<= 1;
+    }
+
+    // Returns (palette, pixel), pixel 0 being transparent
+    fn pixel(&self, fine_x: Byte) -> (Byte, Byte) {
+        let bit = 0x8000 >> fine_x;
+        let pick = |shifter: Word| (shifter & bit != 0) as Byte;
+
+        let pixel = pick(self.shift_pattern_high) << 1 | pick(self.shift_pattern_low);
+        let palette = pick(self.shift_attribute_high) << 1 | pick(self.shift_attribute_low);
+
+        (palette, pixel)
+    }
+}
+
+impl Ppu {
+    // Advances the PPU by a single dot
+    pub fn tick(&mut self, mut cartridge: Option<&mut Cartridge>) {
+        let visible = (self.scanline as usize) < SCREEN_HEIGHT;
+        let pre_render = self.scanline == self.region.pre_render_line();
+        let rendering = self.rendering_enabled();
+
+        if pre_render && self.dot == 1 {
+            self.leave_vblank();
+        }
+
+        if (visible || pre_render) && rendering {
+            self.fetch_background(pre_render, cartridge.as_deref_mut());
+
+            if self.dot == 257 {
+                self.sprites.clear();
+
+                if visible {
+                    self.evaluate_sprites();
+                } else {
+                    self.secondary_oam.clear();
+                }
+            }
+
+            if (257..=320).contains(&self.dot) {
+                self.oam_addr = 0;
+                self.fetch_sprites(cartridge);
+            }
+        }
+
+        if visible && (1..=SCREEN_WIDTH as u16).contains(&self.dot) {
+            self.output_pixel();
+        }
+
+        if self.scanline == self.region.vblank_line() && self.dot == 1 {
+            self.enter_vblank();
+        }
+
+        self.advance(rendering);
+    }
+
+    #[inline(always)]
+    pub fn rendering_enabled(&self) -> bool {
+        (self.mask & (MASK_BACKGROUND | MASK_SPRITES)) != 0
+    }
+
+    // Visible and pre-render lines while rendering is on, where $2007
+    // accesses and fetches fight over `v`
+    #[inline(always)]
+    pub fn rendering_active(&self) -> bool {
+        self.rendering_enabled()
+            && ((self.scanline as usize) < SCREEN_HEIGHT || self.scanline == self.region.pre_render_line())
+    }
+
+    // Each entry is a colour index in the low 6 bits with the PPUMASK
+    // emphasis bits above it, see `Palette::to_rgba`
+    pub fn framebuffer(&self) -> &[Word] {
+        &self.framebuffer
+    }
+
+    #[inline(always)]
+    pub fn odd_frame(&self) -> bool {
+        (self.frame & 1) != 0
+    }
+
+    // Each tile takes 8 dots starting at dot 1 (and 321 for the two
+    // prefetched ones), it enters the shifters on the dot after its last
+    // fetch
+    fn fetch_background(&mut self, pre_render: bool, mut cartridge: Option<&mut Cartridge>) {
+        let dot = self.dot;
+
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.background.shift();
+
+            if (dot - 1).is_multiple_of(8) {
+                self.background.reload();
+            }
+        }
+
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match (dot - 1) % 8 {
+                0 => {
+                    let addr = self.scroll.tile_addr();
+                    self.background.tile = self.vram_read(addr, cartridge.as_deref_mut());
+                }
+                2 => {
+                    let addr = self.scroll.attribute_addr();
+                    let attribute = self.vram_read(addr, cartridge.as_deref_mut());
+                    self.background.attribute = (attribute >> self.scroll.attribute_shift()) & 0x03;
+                }
+                4 => {
+                    let addr = self.pattern_addr();
+                    self.background.pattern_low = self.vram_read(addr, cartridge.as_deref_mut());
+                }
+                6 => {
+                    let addr = self.pattern_addr() + 8;
+                    self.background.pattern_high = self.vram_read(addr, cartridge.as_deref_mut());
+                }
+                7 => self.scroll.increment_x(),
+
+                _ => {}
+            }
+        }
+
+        match dot {
+            256 => self.scroll.increment_y(),
+            257 => self.scroll.copy_x(),
+            280..=304 if pre_render => self.scroll.copy_y(),
+
+            // Two unused nametable fetches end every line, both of the
+            // tile dot 1 of the next line fetches again
+            337 | 339 => {
+                let addr = self.scroll.tile_addr();
+                self.vram_read(addr, cartridge);
+            }
+
+            _ => {}
+        }
+    }
+
+    #[inline(always)]
+    fn pattern_addr(&self) -> Word {
+        let table = if (self.ctrl & CTRL_BACKGROUND_TABLE) != 0 { 0x1000 } else { 0 };
+        table + self.background.tile as Word * 16 + self.scroll.fine_y()
+    }
+
+    fn output_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+        let y = self.scanline as usize;
+
+        let show_background = (self.mask & MASK_BACKGROUND) != 0
+            && (x >= 8 || (self.mask & MASK_BACKGROUND_LEFT) != 0);
+        let show_sprites = (self.mask & MASK_SPRITES) != 0
+            && (x >= 8 || (self.mask & MASK_SPRITES_LEFT) != 0);
+
+        let (palette, pixel) = if show_background {
+            self.background.pixel(self.scroll.x)
+        } else {
+            (0, 0)
+        };
+
+        let sprite = if show_sprites { self.sprite_pixel(x) } else { None };
+
+        // Sprite 0 hit needs both pixels opaque and never happens at X 255
+        if sprite.is_some_and(|sprite| sprite.zero) && pixel != 0 && x != 255 {
+            self.status |= STATUS_SPRITE_ZERO;
+        }
+
+        let addr = match sprite {
+            Some(sprite) if !sprite.behind || pixel == 0 => {
+                PALETTE_SPACE | 0x10 | (sprite.palette as Word) << 2 | sprite.pixel as Word
+            }
+            _ if pixel != 0 => PALETTE_SPACE | (palette as Word) << 2 | pixel as Word,
+
+            // With rendering off and `v` pointing into the palette the PPU
+            // shows that entry instead of the backdrop
+            _ if !self.rendering_enabled() && (self.scroll.v & 0x3F00) == PALETTE_SPACE => {
+                self.scroll.v
+            }
+            _ => PALETTE_SPACE,
+        };
+
+        let emphasis = ((self.mask & MASK_EMPHASIS) as Word) << 1;
+        self.framebuffer[y * SCREEN_WIDTH + x] = emphasis | (self.palette_read(addr) & 0x3F) as Word;
+    }
+
+    // NTSC odd frames skip the last dot of the pre-render line when rendering
+    fn advance(&mut self, rendering: bool) {
+        let pre_render = self.region.pre_render_line();
+        self.dot += 1;
+
+        let skip = self.scanline == pre_render && self.dot == DOTS_PER_LINE - 1;
+        if skip && rendering && self.odd_frame() && self.region.skips_odd_dot() {
+            self.dot = DOTS_PER_LINE;
+        }
+
+        if self.dot >= DOTS_PER_LINE {
+            self.dot = 0;
+            self.scanline += 1;
+
+            if self.scanline > pre_render {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+}
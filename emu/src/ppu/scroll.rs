@@ -0,0 +1,136 @@
+use {
+    crate::{
+        mem::*,
+        state::*,
+    },
+};
+
+pub const COARSE_X: Word   = 0x001F;
+pub const COARSE_Y: Word   = 0x03E0;
+pub const NAMETABLE: Word  = 0x0C00;
+pub const FINE_Y: Word     = 0x7000;
+
+// The "loopy" registers: `v` is the current VRAM address, `t` the one
+// latched by $2000/$2005/$2006 writes, `x` the fine X scroll and `w` the
+// write toggle shared by $2005 and $2006
+//
+//     yyy NN YYYYY XXXXX
+//     ||| || ||||| +++++-- coarse X
+//     ||| || +++++-------- coarse Y
+//     ||| ++-------------- nametable
+//     +++----------------- fine Y
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Scroll {
+    pub v: Word,
+    pub t: Word,
+    pub x: Byte,
+    pub w: bool,
+}
+
+savestate!(Scroll { v, t, x, w });
+
+impl Scroll {
+    // $2000
+    pub fn write_ctrl(&mut self, data: Byte) {
+        self.t = (self.t & !NAMETABLE) | ((data & 0x03) as Word) << 10;
+    }
+
+    // $2002 read
+    pub fn reset_toggle(&mut self) {
+        self.w = false;
+    }
+
+    // $2005
+    pub fn write_scroll(&mut self, data: Byte) {
+        if !self.w {
+            self.t = (self.t & !COARSE_X) | (data >> 3) as Word;
+            self.x = data & 0x07;
+        } else {
+            self.t = (self.t & !(COARSE_Y | FINE_Y))
+                   | ((data & 0xF8) as Word) << 2
+                   | ((data & 0x07) as Word) << 12;
+        }
+
+        self.w = !self.w;
+    }
+
+    // $2006, the first write also clears bit 14 of `t`
+    pub fn write_addr(&mut self, data: Byte) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | ((data & 0x3F) as Word) << 8;
+        } else {
+            self.t = (self.t & 0xFF00) | data as Word;
+            self.v = self.t;
+        }
+
+        self.w = !self.w;
+    }
+
+    // $2007 outside of rendering
+    pub fn increment(&mut self, step: Word) {
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    // Wraps into the horizontally adjacent nametable after tile 31
+    pub fn increment_x(&mut self) {
+        if (self.v & COARSE_X) == 31 {
+            self.v &= !COARSE_X;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // Row 29 wraps into the vertically adjacent nametable, rows 30 and 31
+    // are attribute bytes and wrap back to 0 without switching
+    pub fn increment_y(&mut self) {
+        if (self.v & FINE_Y) != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !FINE_Y;
+
+        let coarse_y = match (self.v & COARSE_Y) >> 5 {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            y => y + 1,
+        };
+
+        self.v = (self.v & !COARSE_Y) | coarse_y << 5;
+    }
+
+    pub fn copy_x(&mut self) {
+        let mask = COARSE_X | 0x0400;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    pub fn copy_y(&mut self) {
+        let mask = FINE_Y | COARSE_Y | 0x0800;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    #[inline(always)]
+    pub fn fine_y(&self) -> Word {
+        (self.v & FINE_Y) >> 12
+    }
+
+    #[inline(always)]
+    pub fn tile_addr(&self) -> Word {
+        0x2000 | (self.v & 0x0FFF)
+    }
+
+    #[inline(always)]
+    pub fn attribute_addr(&self) -> Word {
+        0x23C0 | (self.v & NAMETABLE) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07)
+    }
+
+    // Which 2-bit quadrant of the attribute byte covers the current tile
+    #[inline(always)]
+    pub fn attribute_shift(&self) -> Byte {
+        (((self.v >> 4) & 0x04) | (self.v & 0x02)) as Byte
+    }
+}
@@ -0,0 +1,191 @@
+use {
+    super::*,
+};
+
+pub const SPRITES_PER_LINE: usize = 8;
+pub const SPRITE_COUNT: usize     = OAM_SIZE / 4;
+
+pub const ATTR_PALETTE: Byte  = 0x03;
+pub const ATTR_BEHIND: Byte   = 1 << 5;
+pub const ATTR_FLIP_X: Byte   = 1 << 6;
+pub const ATTR_FLIP_Y: Byte   = 1 << 7;
+
+// A sprite picked for the next line, as copied into secondary OAM
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteEntry {
+    pub y: Byte,
+    pub tile: Byte,
+    pub attribute: Byte,
+    pub x: Byte,
+
+    pub zero: bool,
+}
+
+savestate!(SpriteEntry { y, tile, attribute, x, zero });
+
+// A sprite whose pattern has been fetched and is ready to be drawn
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    pub x: Byte,
+    pub attribute: Byte,
+
+    pub pattern_low: Byte,
+    pub pattern_high: Byte,
+
+    pub zero: bool,
+}
+
+savestate!(Sprite { x, attribute, pattern_low, pattern_high, zero });
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpritePixel {
+    pub palette: Byte,
+    pub pixel: Byte,
+
+    pub behind: bool,
+    pub zero: bool,
+}
+
+impl Sprite {
+    fn pixel(&self, x: usize) -> Option<SpritePixel> {
+        let column = x.checked_sub(self.x as usize).filter(|&column| column < 8)?;
+        let shift = if (self.attribute & ATTR_FLIP_X) != 0 { column } else { 7 - column };
+
+        let pixel = ((self.pattern_high >> shift) & 1) << 1 | ((self.pattern_low >> shift) & 1);
+        if pixel == 0 {
+            return None;
+        }
+
+        Some(SpritePixel { palette: self.attribute & ATTR_PALETTE,
+                           pixel,
+                           behind: (self.attribute & ATTR_BEHIND) != 0,
+                           zero: self.zero })
+    }
+}
+
+impl Ppu {
+    #[inline(always)]
+    pub fn sprite_height(&self) -> u16 {
+        if (self.ctrl & CTRL_SPRITE_SIZE) != 0 { 16 } else { 8 }
+    }
+
+    // Picks the sprites for the next line from OAM, including the
+    // hardware's broken overflow search: once eight sprites are found it
+    // keeps comparing, but also advances the byte index within each entry
+    pub(super) fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height();
+        let line = self.scanline;
+        let in_range = |y: Byte| line.wrapping_sub(y as u16) < height;
+
+        self.secondary_oam.clear();
+
+        let mut n = 0;
+        while n < SPRITE_COUNT && self.secondary_oam.len() < SPRITES_PER_LINE {
+            self.select_sprite(n, &in_range);
+            n += 1;
+        }
+
+        let mut m = 0;
+        let mut overflow_checked = n;
+        while overflow_checked < SPRITE_COUNT {
+            if in_range(self.oam[overflow_checked * 4 + m]) {
+                self.status |= STATUS_OVERFLOW;
+                break;
+            }
+
+            overflow_checked += 1;
+            m = (m + 1) & 0x03;
+        }
+
+        if self.unlimited_sprites {
+            for n in n..SPRITE_COUNT {
+                self.select_sprite(n, &in_range);
+            }
+        }
+    }
+
+    fn select_sprite(&mut self, n: usize, in_range: &impl Fn(Byte) -> bool) {
+        let entry = &self.oam[n * 4..n * 4 + 4];
+
+        if in_range(entry[0]) {
+            self.secondary_oam.push(SpriteEntry { y: entry[0],
+                                                  tile: entry[1],
+                                                  attribute: entry[2],
+                                                  x: entry[3],
+                                                  zero: n == 0 });
+        }
+    }
+
+    // Dots 257-320: two garbage nametable reads and two pattern reads per
+    // slot, empty slots still fetch tile $FF
+    pub(super) fn fetch_sprites(&mut self, mut cartridge: Option<&mut Cartridge>) {
+        let phase = (self.dot - 257) as usize;
+        let slot = phase / 8;
+
+        let entry = self.secondary_oam.get(slot).copied();
+        let addr = self.sprite_pattern_addr(entry);
+
+        match phase % 8 {
+            0 | 2 => {
+                let addr = self.scroll.tile_addr();
+                self.vram_read(addr, cartridge);
+            }
+            4 => self.sprite_latch = self.vram_read(addr, cartridge),
+            6 => {
+                let pattern_high = self.vram_read(addr + 8, cartridge.as_deref_mut());
+                if let Some(entry) = entry {
+                    self.sprites.push(self.fetched_sprite(entry, self.sprite_latch, pattern_high));
+                }
+
+                // Sprites past the eighth are fetched all at once after the last slot
+                if slot == SPRITES_PER_LINE - 1 && self.unlimited_sprites {
+                    for entry in self.secondary_oam.clone().into_iter().skip(SPRITES_PER_LINE) {
+                        let addr = self.sprite_pattern_addr(Some(entry));
+                        let pattern_low = self.vram_read(addr, cartridge.as_deref_mut());
+                        let pattern_high = self.vram_read(addr + 8, cartridge.as_deref_mut());
+
+                        self.sprites.push(self.fetched_sprite(entry, pattern_low, pattern_high));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn fetched_sprite(&self, entry: SpriteEntry, pattern_low: Byte, pattern_high: Byte) -> Sprite {
+        Sprite { x: entry.x,
+                 attribute: entry.attribute,
+                 pattern_low,
+                 pattern_high,
+                 zero: entry.zero }
+    }
+
+    fn sprite_pattern_addr(&self, entry: Option<SpriteEntry>) -> Word {
+        let height = self.sprite_height();
+
+        let (tile, mut row) = match entry {
+            Some(entry) => (entry.tile, self.scanline.wrapping_sub(entry.y as u16) & (height - 1)),
+            None => (0xFF, 0),
+        };
+
+        if entry.is_some_and(|entry| (entry.attribute & ATTR_FLIP_Y) != 0) {
+            row = height - 1 - row;
+        }
+
+        if height == 16 {
+            let table = (tile as Word & 0x01) * 0x1000;
+            let tile = (tile & 0xFE) as Word + (row >> 3);
+
+            table + tile * 16 + (row & 0x07)
+        } else {
+            let table = if (self.ctrl & CTRL_SPRITE_TABLE) != 0 { 0x1000 } else { 0 };
+            table + tile as Word * 16 + row
+        }
+    }
+
+    // First opaque sprite in OAM order wins, whatever its priority
+    pub(super) fn sprite_pixel(&self, x: usize) -> Option<SpritePixel> {
+        self.sprites.iter().find_map(|sprite| sprite.pixel(x))
+    }
+}
@@ -0,0 +1,582 @@
+use {
+    super::*,
+    crate::{
+        cpu::*,
+        consts::*,
+        error::*,
+    },
+};
+
+fn write_addr(ppu: &mut Ppu, addr: Word) {
+    ppu.cpu_write(PPUADDR, (addr >> 8) as Byte, None);
+    ppu.cpu_write(PPUADDR, addr as Byte, None);
+}
+
+// NROM with CHR-RAM, tile 1 is solid colour 1, tile 2 solid colour 3
+// and tile 3 a single colour 1 dot in its top-left corner
+fn cartridge() -> Cartridge {
+    let mut raw = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    raw.resize(raw.len() + 0x4000, 0);
+
+    let mut cartridge = Cartridge::from_bytes(&raw).unwrap();
+    for row in 0..8 {
+        cartridge.ppu_write(0x10 + row, 0xFF);
+        cartridge.ppu_write(0x20 + row, 0xFF);
+        cartridge.ppu_write(0x28 + row, 0xFF);
+    }
+    cartridge.ppu_write(0x30, 0x80);
+
+    cartridge
+}
+
+fn run_frame(ppu: &mut Ppu, cartridge: &mut Cartridge) -> usize {
+    let frame = ppu.frame;
+    let mut dots = 0;
+
+    while ppu.frame == frame {
+        ppu.tick(Some(cartridge));
+        dots += 1;
+    }
+
+    dots
+}
+
+// SCROLL
+
+#[test]
+fn loopy_scroll_writes() {
+    let mut ppu = Ppu::new();
+
+    ppu.cpu_write(PPUCTRL, 0x03, None);
+    assert_eq!(ppu.scroll.t, 0x0C00);
+
+    ppu.cpu_write(PPUSCROLL, 0x7D, None);  // X = 15 tiles, fine 5
+    assert_eq!(ppu.scroll.t, 0x0C0F);
+    assert_eq!(ppu.scroll.x, 5);
+    assert!(ppu.scroll.w);
+
+    ppu.cpu_write(PPUSCROLL, 0x5E, None);  // Y = 11 tiles, fine 6
+    assert_eq!(ppu.scroll.t, 0x6D6F);
+    assert!(!ppu.scroll.w);
+
+    // v is only touched by the second $2006 write
+    assert_eq!(ppu.scroll.v, 0);
+}
+
+#[test]
+fn loopy_addr_writes() {
+    let mut ppu = Ppu::new();
+    ppu.scroll.t = 0x7FFF;
+
+    ppu.cpu_write(PPUADDR, 0xFD, None);
+    assert_eq!(ppu.scroll.t, 0x3DFF);  // bit 14 cleared, top bits masked
+    assert_eq!(ppu.scroll.v, 0);
+
+    ppu.cpu_write(PPUADDR, 0x42, None);
+    assert_eq!(ppu.scroll.t, 0x3D42);
+    assert_eq!(ppu.scroll.v, 0x3D42);
+}
+
+#[test]
+fn status_resets_toggle() {
+    let mut ppu = Ppu::new();
+
+    ppu.cpu_write(PPUADDR, 0x21, None);
+    ppu.cpu_read(PPUSTATUS, None);
+    ppu.cpu_write(PPUADDR, 0x23, None);
+    ppu.cpu_write(PPUADDR, 0x45, None);
+
+    assert_eq!(ppu.scroll.v, 0x2345);
+}
+
+#[test]
+fn scroll_increments() {
+    let mut scroll = Scroll { v: 0x001F, ..Scroll::default() };
+    scroll.increment_x();
+    assert_eq!(scroll.v, 0x0400);
+
+    scroll.v = 0x73A0;  // fine Y 7, coarse Y 29
+    scroll.increment_y();
+    assert_eq!(scroll.v, 0x0800);
+
+    scroll.v = 0x73E0;  // coarse Y 31 wraps without switching
+    scroll.increment_y();
+    assert_eq!(scroll.v, 0x0000);
+
+    scroll.v = 0x7FFF;
+    scroll.t = 0x0415;
+    scroll.copy_x();
+    assert_eq!(scroll.v, 0x7FF5);
+}
+
+// REGISTERS
+
+#[test]
+fn vblank_clear_on_read() {
+    let mut ppu = Ppu::new();
+    ppu.cpu_write(PPUMASK, 0x1F, None);  // open bus
+    ppu.enter_vblank();
+
+    assert_eq!(ppu.cpu_peek(PPUSTATUS), 0x9F);
+    assert_eq!(ppu.cpu_read(PPUSTATUS, None), 0x9F);
+    assert_eq!(ppu.cpu_read(PPUSTATUS, None) & STATUS_VBLANK, 0);
+}
+
+#[test]
+fn ppudata_buffered_read() {
+    let mut ppu = Ppu::new();
+
+    write_addr(&mut ppu, 0x2400);
+    ppu.cpu_write(PPUDATA, 0x11, None);
+    ppu.cpu_write(PPUDATA, 0x22, None);
+
+    write_addr(&mut ppu, 0x2400);
+    ppu.cpu_read(PPUDATA, None);  // stale buffer
+    assert_eq!(ppu.cpu_read(PPUDATA, None), 0x11);
+    assert_eq!(ppu.cpu_read(PPUDATA, None), 0x22);
+}
+
+#[test]
+fn ppudata_palette_bypass() {
+    let mut ppu = Ppu::new();
+
+    // Horizontal mirroring puts $2F00 in the second page
+    write_addr(&mut ppu, 0x2F00);
+    ppu.cpu_write(PPUDATA, 0xAB, None);
+
+    write_addr(&mut ppu, 0x3F00);
+    ppu.cpu_write(PPUDATA, 0x2C, None);
+
+    write_addr(&mut ppu, 0x3F00);
+    assert_eq!(ppu.cpu_read(PPUDATA, None) & 0x3F, 0x2C);
+
+    write_addr(&mut ppu, 0x2000);
+    assert_eq!(ppu.cpu_read(PPUDATA, None), 0xAB);  // buffer from $2F00
+}
+
+#[test]
+fn ppudata_increment() {
+    let mut ppu = Ppu::new();
+
+    write_addr(&mut ppu, 0x2000);
+    ppu.cpu_write(PPUDATA, 0, None);
+    assert_eq!(ppu.scroll.v, 0x2001);
+
+    ppu.cpu_write(PPUCTRL, CTRL_INCREMENT, None);
+    ppu.cpu_write(PPUDATA, 0, None);
+    assert_eq!(ppu.scroll.v, 0x2021);
+}
+
+#[test]
+fn palette_mirrors() {
+    let mut ppu = Ppu::new();
+
+    write_addr(&mut ppu, 0x3F10);
+    ppu.cpu_write(PPUDATA, 0x0F, None);
+    assert_eq!(ppu.palette[0x00], 0x0F);
+
+    write_addr(&mut ppu, 0x3F05);
+    ppu.cpu_write(PPUDATA, 0x16, None);
+    assert_eq!(ppu.palette_read(0x3F25), 0x16);
+    assert_eq!(ppu.palette_read(0x3F15), 0x00);
+
+    ppu.mask = MASK_GREYSCALE;
+    assert_eq!(ppu.palette_read(0x3F05), 0x10);
+}
+
+#[test]
+fn oam_access() {
+    let mut ppu = Ppu::new();
+
+    ppu.cpu_write(OAMADDR, 0xFE, None);
+    ppu.cpu_write(OAMDATA, 0xFF, None);
+    ppu.cpu_write(OAMDATA, 0x12, None);
+    assert_eq!(ppu.oam_addr, 0x00);
+
+    ppu.cpu_write(OAMADDR, 0xFE, None);
+    assert_eq!(ppu.cpu_read(OAMDATA, None), 0xE3);
+    assert_eq!(ppu.oam_addr, 0xFE);  // reads don't increment
+    assert_eq!(ppu.oam[0xFF], 0x12);
+}
+
+#[test]
+fn nmi_on_ctrl_enable() {
+    let mut ppu = Ppu::new();
+
+    ppu.enter_vblank();
+    assert!(!ppu.take_nmi());
+
+    ppu.cpu_write(PPUCTRL, CTRL_NMI, None);
+    assert!(ppu.take_nmi());
+
+    // Rewriting the same value is not a new edge
+    ppu.cpu_write(PPUCTRL, CTRL_NMI, None);
+    assert!(!ppu.take_nmi());
+}
+
+// MIRRORING
+
+#[test]
+fn mirroring_modes() {
+    let slots = |mirroring: Mirroring| {
+        [0x2000, 0x2400, 0x2800, 0x2C00].map(|addr| nametable_offset(addr + 5, mirroring))
+    };
+
+    assert_eq!(slots(Mirroring::Horizontal), [0x005, 0x005, 0x405, 0x405]);
+    assert_eq!(slots(Mirroring::Vertical), [0x005, 0x405, 0x005, 0x405]);
+    assert_eq!(slots(Mirroring::SingleScreenLower), [0x005; 4]);
+    assert_eq!(slots(Mirroring::SingleScreenUpper), [0x405; 4]);
+    assert_eq!(slots(Mirroring::Mapped([1, 0, 0, 1])), [0x405, 0x005, 0x005, 0x405]);
+
+    // $3000-$3EFF mirrors $2000-$2EFF
+    assert_eq!(nametable_offset(0x3405, Mirroring::Vertical), 0x405);
+}
+
+#[test]
+fn mirroring_four_screen() {
+    let mut raw = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    raw.resize(raw.len() + 0x4000, 0);
+
+    let mut cartridge = Cartridge::from_bytes(&raw).unwrap();
+    let mut ppu = Ppu::new();
+    assert_eq!(cartridge.mirroring(), Mirroring::FourScreen);
+
+    for (slot, addr) in [0x2000, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+        ppu.vram_write(addr + 0x10, slot as Byte + 1, Some(&mut cartridge));
+    }
+
+    for (slot, addr) in [0x2000, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+        assert_eq!(ppu.vram_read(addr + 0x10, Some(&mut cartridge)), slot as Byte + 1);
+    }
+
+    // Only the first two live in CIRAM
+    assert_eq!(ppu.nametables[0x010], 1);
+    assert_eq!(ppu.nametables[0x410], 2);
+    assert_eq!(cartridge.nametable_read(0x2810), Some(3));
+    assert_eq!(cartridge.nametable_read(0x2010), None);
+}
+
+// BACKGROUND
+
+fn setup_background(ppu: &mut Ppu, cartridge: &mut Cartridge) {
+    ppu.vram_write(0x3F00, 0x0F, Some(cartridge));
+    ppu.vram_write(0x3F01, 0x16, Some(cartridge));
+    ppu.vram_write(0x3F07, 0x2A, Some(cartridge));
+
+    ppu.vram_write(0x2000, 0x01, Some(cartridge));
+    ppu.vram_write(0x2021, 0x02, Some(cartridge));  // row 1, column 1
+    ppu.vram_write(0x23C0, 0x01, Some(cartridge));  // top-left quadrant palette 1
+}
+
+#[test]
+fn background_tiles() {
+    let mut cartridge = cartridge();
+    let mut ppu = Ppu::new();
+    setup_background(&mut ppu, &mut cartridge);
+
+    ppu.cpu_write(PPUMASK, MASK_BACKGROUND | MASK_BACKGROUND_LEFT, None);
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    let frame = ppu.framebuffer();
+    assert_eq!(frame.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+
+    // Tile 1 uses palette 1 from the attribute byte, colour 1 is never set
+    assert_eq!(frame[0], 0x00);
+    assert_eq!(frame[7 * SCREEN_WIDTH + 7], 0x00);
+    assert_eq!(frame[8], 0x0F);
+
+    assert_eq!(frame[8 * SCREEN_WIDTH + 8], 0x2A);
+    assert_eq!(frame[15 * SCREEN_WIDTH + 15], 0x2A);
+    assert_eq!(frame[16 * SCREEN_WIDTH + 16], 0x0F);
+}
+
+#[test]
+fn background_fine_scroll() {
+    let mut cartridge = cartridge();
+    let mut ppu = Ppu::new();
+    setup_background(&mut ppu, &mut cartridge);
+    ppu.vram_write(0x23C0, 0x00, Some(&mut cartridge));
+
+    ppu.cpu_write(PPUSCROLL, 4, None);
+    ppu.cpu_write(PPUSCROLL, 0, None);
+    ppu.cpu_write(PPUMASK, MASK_BACKGROUND | MASK_BACKGROUND_LEFT, None);
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    let frame = ppu.framebuffer();
+    assert_eq!(frame[3], 0x16);
+    assert_eq!(frame[4], 0x0F);
+}
+
+#[test]
+fn background_left_mask() {
+    let mut cartridge = cartridge();
+    let mut ppu = Ppu::new();
+    setup_background(&mut ppu, &mut cartridge);
+    ppu.vram_write(0x23C0, 0x00, Some(&mut cartridge));
+
+    ppu.cpu_write(PPUMASK, MASK_BACKGROUND, None);
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    assert_eq!(ppu.framebuffer()[0], 0x0F);
+}
+
+#[test]
+fn frame_timing() {
+    let mut cartridge = cartridge();
+    let mut ppu = Ppu::new();
+
+    assert_eq!(run_frame(&mut ppu, &mut cartridge), 262 * 341);
+
+    // Rendering shortens every odd frame by a dot
+    ppu.cpu_write(PPUMASK, MASK_BACKGROUND, None);
+    assert_eq!(run_frame(&mut ppu, &mut cartridge), 262 * 341 - 1);
+    assert_eq!(run_frame(&mut ppu, &mut cartridge), 262 * 341);
+
+    // Vblank is raised at the start of line 241
+    while !(ppu.scanline == ppu.region.vblank_line() && ppu.dot == 2) {
+        ppu.tick(Some(&mut cartridge));
+    }
+    assert_ne!(ppu.status & STATUS_VBLANK, 0);
+}
+
+// SPRITES
+
+fn run_until(ppu: &mut Ppu, cartridge: &mut Cartridge, scanline: u16) {
+    while ppu.scanline != scanline || ppu.dot != 0 {
+        ppu.tick(Some(cartridge));
+    }
+}
+
+fn set_sprite(ppu: &mut Ppu, index: usize, y: Byte, tile: Byte, attribute: Byte, x: Byte) {
+    ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, tile, attribute, x]);
+}
+
+fn sprite_setup() -> (Ppu, Cartridge) {
+    let mut cartridge = cartridge();
+    let mut ppu = Ppu::new();
+
+    ppu.oam = [0xFF; OAM_SIZE];
+    ppu.vram_write(0x3F00, 0x0F, Some(&mut cartridge));
+    ppu.vram_write(0x3F01, 0x16, Some(&mut cartridge));
+    ppu.vram_write(0x3F03, 0x1A, Some(&mut cartridge));
+    ppu.vram_write(0x3F11, 0x21, Some(&mut cartridge));
+    ppu.vram_write(0x3F15, 0x2B, Some(&mut cartridge));
+    ppu.cpu_write(PPUMASK, 0x1E, None);
+
+    (ppu, cartridge)
+}
+
+fn pixel(ppu: &Ppu, x: usize, y: usize) -> Word {
+    ppu.framebuffer()[y * SCREEN_WIDTH + x]
+}
+
+#[test]
+fn sprite_rendering() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    set_sprite(&mut ppu, 0, 20, 0x01, 0x00, 16);
+    set_sprite(&mut ppu, 1, 20, 0x03, 0x01, 100);
+    set_sprite(&mut ppu, 2, 20, 0x03, ATTR_FLIP_X | ATTR_FLIP_Y, 120);
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    // Drawn one line below their Y coordinate
+    assert_eq!(pixel(&ppu, 16, 20), 0x0F);
+    assert_eq!(pixel(&ppu, 16, 21), 0x21);
+    assert_eq!(pixel(&ppu, 23, 28), 0x21);
+    assert_eq!(pixel(&ppu, 24, 28), 0x0F);
+
+    assert_eq!(pixel(&ppu, 100, 21), 0x2B);
+    assert_eq!(pixel(&ppu, 101, 21), 0x0F);
+
+    assert_eq!(pixel(&ppu, 127, 28), 0x21);
+    assert_eq!(pixel(&ppu, 120, 21), 0x0F);
+}
+
+#[test]
+fn sprite_priority() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    ppu.vram_write(0x2042, 0x02, Some(&mut cartridge));  // x 16..23, y 16..23
+
+    // The first opaque sprite decides, even when it loses to the background
+    set_sprite(&mut ppu, 0, 15, 0x01, ATTR_BEHIND, 16);
+    set_sprite(&mut ppu, 1, 15, 0x01, 0x01, 20);
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    assert_eq!(pixel(&ppu, 16, 16), 0x1A);
+    assert_eq!(pixel(&ppu, 21, 16), 0x1A);
+    assert_eq!(pixel(&ppu, 24, 16), 0x2B);
+}
+
+#[test]
+fn sprite_tall() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    ppu.ctrl = CTRL_SPRITE_SIZE;
+    set_sprite(&mut ppu, 0, 30, 0x02, 0x00, 40);
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    // Tile 2 on top, tile 3 below
+    assert_eq!(pixel(&ppu, 41, 31), 0x00);
+    assert_eq!(pixel(&ppu, 40, 39), 0x21);
+    assert_eq!(pixel(&ppu, 41, 39), 0x0F);
+}
+
+#[test]
+fn sprite_zero_hit() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    ppu.vram_write(0x2044, 0x02, Some(&mut cartridge));  // x 32..39, y 16..23
+    set_sprite(&mut ppu, 0, 19, 0x01, 0x00, 36);
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_until(&mut ppu, &mut cartridge, 20);
+    assert_eq!(ppu.status & STATUS_SPRITE_ZERO, 0);
+
+    run_until(&mut ppu, &mut cartridge, 21);
+    assert_ne!(ppu.status & STATUS_SPRITE_ZERO, 0);
+
+    // Cleared on the pre-render line
+    run_frame(&mut ppu, &mut cartridge);
+    assert_eq!(ppu.status & STATUS_SPRITE_ZERO, 0);
+}
+
+#[test]
+fn sprite_zero_hit_left_clip() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    ppu.vram_write(0x2000, 0x02, Some(&mut cartridge));
+    set_sprite(&mut ppu, 0, 0, 0x01, 0x00, 0);
+
+    ppu.cpu_write(PPUMASK, MASK_BACKGROUND | MASK_SPRITES | MASK_BACKGROUND_LEFT, None);
+    run_frame(&mut ppu, &mut cartridge);
+    run_until(&mut ppu, &mut cartridge, 10);
+    assert_eq!(ppu.status & STATUS_SPRITE_ZERO, 0);
+}
+
+#[test]
+fn sprite_limit() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    for index in 0..9 {
+        set_sprite(&mut ppu, index, 50, 0x01, 0x00, index as Byte * 10);
+    }
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_until(&mut ppu, &mut cartridge, 60);
+    assert_ne!(ppu.status & STATUS_OVERFLOW, 0);
+    assert_eq!(pixel(&ppu, 70, 51), 0x21);
+    assert_eq!(pixel(&ppu, 80, 51), 0x0F);
+
+    ppu.unlimited_sprites = true;
+    run_frame(&mut ppu, &mut cartridge);
+    run_until(&mut ppu, &mut cartridge, 60);
+    assert_ne!(ppu.status & STATUS_OVERFLOW, 0);
+    assert_eq!(pixel(&ppu, 80, 51), 0x21);
+}
+
+#[test]
+fn sprite_overflow_bug() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    for index in 0..8 {
+        set_sprite(&mut ppu, index, 50, 0x01, 0x00, 0);
+    }
+
+    // Sprite 8 is out of range, then the search reads sprite 9's tile
+    // number as if it were a Y coordinate
+    set_sprite(&mut ppu, 8, 200, 0x01, 0x00, 0);
+    set_sprite(&mut ppu, 9, 200, 50, 0x00, 0);
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_until(&mut ppu, &mut cartridge, 60);
+    assert_ne!(ppu.status & STATUS_OVERFLOW, 0);
+
+    // ...and misses a real ninth sprite the same way
+    set_sprite(&mut ppu, 9, 0xFF, 0xFF, 0xFF, 0xFF);
+    set_sprite(&mut ppu, 8, 0xFF, 0xFF, 0xFF, 0xFF);
+    set_sprite(&mut ppu, 10, 0xFF, 50, 0xFF, 0xFF);
+    set_sprite(&mut ppu, 11, 0xFF, 0xFF, 50, 0xFF);
+    set_sprite(&mut ppu, 12, 0xFF, 0xFF, 0xFF, 50);
+    set_sprite(&mut ppu, 13, 50, 0xFF, 0xFF, 0xFF);
+
+    run_frame(&mut ppu, &mut cartridge);
+    run_until(&mut ppu, &mut cartridge, 60);
+    assert_eq!(ppu.status & STATUS_OVERFLOW, 0);
+}
+
+// PALETTE
+
+#[test]
+fn palette_ntsc() {
+    let palette = Palette::ntsc();
+
+    assert_eq!(palette.rgb(0x0F), [0x00, 0x00, 0x00]);
+    assert_eq!(palette.rgb(0x16), [0x98, 0x22, 0x20]);
+    assert_eq!(palette.rgb(0x30), [0xEC, 0xEE, 0xEC]);
+
+    // Red emphasis keeps red and darkens green and blue
+    assert_eq!(palette.rgb(EMPHASIS_RED | 0x30), [0xEC, 0xC2, 0xC0]);
+    assert_eq!(palette.rgb(EMPHASIS_RED | EMPHASIS_GREEN | EMPHASIS_BLUE | 0x30), [0xC0, 0xC2, 0xC0]);
+}
+
+#[test]
+fn palette_from_pal() {
+    let raw: Vec<Byte> = (0..PAL_BASE_SIZE).map(|i| i as Byte).collect();
+    let palette = Palette::from_pal(&raw).unwrap();
+    assert_eq!(palette.rgb(0x01), [3, 4, 5]);
+    assert_eq!(palette.rgb(EMPHASIS_BLUE | 0x01), [2, 3, 5]);
+
+    let raw: Vec<Byte> = (0..PAL_FULL_SIZE).map(|i| (i / 3) as Byte).collect();
+    let palette = Palette::from_pal(&raw).unwrap();
+    assert_eq!(palette.rgb(0x01), [1, 1, 1]);
+    assert_eq!(palette.rgb(EMPHASIS_BLUE | 0x01), [1, 1, 1]);  // 0x101 truncated to a byte
+
+    assert_eq!(Palette::from_pal(&raw[..100]), Err(PaletteError::InvalidSize(100)));
+    assert_eq!(Palette::load("/nonexistent.pal"), Err(PaletteError::Io(std::io::ErrorKind::NotFound)));
+}
+
+#[test]
+fn palette_to_rgba() {
+    let palette = Palette::ntsc();
+    let mut out = [0; 8];
+
+    palette.to_rgba(&[0x16, 0x30], &mut out);
+    assert_eq!(out, [0x98, 0x22, 0x20, 0xFF, 0xEC, 0xEE, 0xEC, 0xFF]);
+}
+
+#[test]
+fn framebuffer_greyscale_and_emphasis() {
+    let (mut ppu, mut cartridge) = sprite_setup();
+    set_sprite(&mut ppu, 0, 20, 0x01, 0x00, 16);
+
+    ppu.cpu_write(PPUMASK, 0x1E | MASK_GREYSCALE | 0x20, None);
+    run_frame(&mut ppu, &mut cartridge);
+    run_frame(&mut ppu, &mut cartridge);
+
+    assert_eq!(pixel(&ppu, 16, 21), EMPHASIS_RED | 0x20);
+    assert_eq!(pixel(&ppu, 16, 20), EMPHASIS_RED);
+}
+
+// BUS
+
+#[test]
+fn registers_on_cpu_bus() {
+    let mut cpu = Cpu::default();
+
+    // Registers repeat every 8 bytes
+    cpu.mem.write(0x3FFE, 0x28);
+    cpu.mem.write(0x2006, 0x08);
+    cpu.mem.write(0x2FFF, 0x77);
+    assert_eq!(cpu.mem.ppu().nametables[0x0408], 0x77);
+
+    cpu.mem.ppu_mut().enter_vblank();
+    cpu.mem.write(PPUCTRL, CTRL_NMI);
+    cpu.pc = ROM_ENTRYPOINT;
+    cpu.mem.write_word(NMI_VECTOR, 0x9000);
+    cpu.poll_interrupts();
+
+    assert_eq!(cpu.pc, 0x9000);
+}
@@ -0,0 +1,101 @@
+use {
+    crate::{
+        ppu::*,
+        mem::*,
+        state::*,
+        error::*,
+    },
+};
+
+pub const NTSC_CPU_CLOCK: u32  = 1_789_773;
+pub const PAL_CPU_CLOCK: u32   = 1_662_607;
+pub const DENDY_CPU_CLOCK: u32 = 1_773_448;
+
+// Console timing. PAL has a longer vblank and a slower CPU, the Dendy
+// clone keeps PAL's line count with NTSC's 3:1 clock ratio and pushes
+// vblank down so it's as long as on NTSC
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Savestate for Region {
+    fn save(&self, state: &mut StateWriter) {
+        (*self as Byte).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        *self = match state.read_array::<1>()? {
+            [0] => Region::Ntsc,
+            [1] => Region::Pal,
+            [2] => Region::Dendy,
+
+            _ => return Err(StateError::Corrupted),
+        };
+
+        Ok(())
+    }
+}
+
+impl Region {
+    // Lines per frame, including the pre-render line
+    #[inline(always)]
+    pub fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    #[inline(always)]
+    pub fn vblank_line(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    #[inline(always)]
+    pub fn pre_render_line(self) -> u16 {
+        self.scanlines() - 1
+    }
+
+    // Only NTSC drops a dot on odd frames while rendering
+    #[inline(always)]
+    pub fn skips_odd_dot(self) -> bool {
+        self == Region::Ntsc
+    }
+
+    // PPU dots per CPU cycle as (dots, cycles), PAL runs 3.2
+    #[inline(always)]
+    pub fn ppu_ratio(self) -> (u8, u8) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    #[inline(always)]
+    pub fn cpu_clock(self) -> u32 {
+        match self {
+            Region::Ntsc => NTSC_CPU_CLOCK,
+            Region::Pal => PAL_CPU_CLOCK,
+            Region::Dendy => DENDY_CPU_CLOCK,
+        }
+    }
+
+    pub fn frame_rate(self) -> f64 {
+        let (dots, cycles) = self.ppu_ratio();
+        let mut frame_dots = self.scanlines() as f64 * DOTS_PER_LINE as f64;
+
+        // Every other frame is a dot short
+        if self.skips_odd_dot() {
+            frame_dots -= 0.5;
+        }
+
+        self.cpu_clock() as f64 * dots as f64 / cycles as f64 / frame_dots
+    }
+}
@@ -0,0 +1,265 @@
+use {
+    crate::{
+        mem::*,
+        nes::*,
+        error::*,
+        movie::*,
+    },
+
+    std::{
+        mem,
+        collections::VecDeque,
+    },
+};
+
+pub const DEFAULT_REWIND_INTERVAL: u64 = 10;
+pub const DEFAULT_REWIND_BUDGET: usize = 32 << 20;
+
+// Restores the snapshot before it from the one after it, as the XOR of
+// the two with runs of zeros squeezed out, see `encode_delta`
+#[derive(Debug, Clone)]
+struct Delta {
+    frame: u64,
+    len: usize,
+    data: Vec<Byte>,
+}
+
+// History of the last few seconds of play. Every `interval` frames a
+// save state is taken, the newest is kept whole and each older one as a
+// delta against its successor, so the oldest can be dropped to stay
+// within budget. Input is kept for every frame so rewinding to a frame
+// between snapshots replays forward from the one before it
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+
+    // Frames run through `run_frame`, minus whatever was rewound
+    frame: u64,
+
+    current: Option<(u64, Vec<Byte>)>,
+    deltas: VecDeque<Delta>,
+    deltas_size: usize,
+
+    // Input from the oldest snapshot on
+    inputs: VecDeque<FrameInput>,
+}
+
+impl Rewind {
+    // A snapshot every `interval` frames, with at most `budget` bytes of
+    // snapshots and input kept around as of the latest snapshot
+    pub fn new(interval: u64, budget: usize) -> Self {
+        Self { interval: interval.max(1),
+               budget,
+               frame: 0,
+               current: None,
+               deltas: VecDeque::new(),
+               deltas_size: 0,
+               inputs: VecDeque::new() }
+    }
+
+    #[inline(always)]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // How far back `rewind` can go
+    pub fn available(&self) -> u64 {
+        self.frame - self.oldest_frame()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        let current = self.current.as_ref().map_or(0, |(_, state)| state.len());
+        current + self.deltas_size + self.inputs.len() * mem::size_of::<FrameInput>()
+    }
+
+    // Runs a frame with whatever the frontend has set on the ports,
+    // taking a snapshot before it when one is due
+    pub fn run_frame(&mut self, nes: &mut Nes, commands: Byte) -> Result<(), ExecError> {
+        let due = self.frame.is_multiple_of(self.interval);
+        let taken = matches!(&self.current, Some((frame, _)) if *frame == self.frame);
+
+        if due && !taken {
+            self.snapshot(nes);
+        }
+
+        let input = FrameInput::capture(nes.input(), commands);
+        nes.run_frame_with(&input)?;
+
+        self.inputs.push_back(input);
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    // Goes back `frames` frames, or as far as the history reaches, and
+    // returns how many were actually undone. Everything after the new
+    // position is forgotten
+    pub fn rewind(&mut self, nes: &mut Nes, frames: u64) -> Result<u64, RewindError> {
+        let oldest = self.oldest_frame();
+        let target = self.frame.saturating_sub(frames).max(oldest);
+
+        let Some((mut frame, current)) = &self.current else {
+            return Ok(0);
+        };
+        if target == self.frame {
+            return Ok(0);
+        }
+
+        // Walk back to the newest snapshot at or before the target, the
+        // history is only cut once the state has loaded
+        let mut state = current.clone();
+        let mut keep = self.deltas.len();
+
+        while frame > target && keep > 0 {
+            keep -= 1;
+            decode_delta(&mut state, &self.deltas[keep]);
+            frame = self.deltas[keep].frame;
+        }
+
+        nes.load_state(&state)?;
+
+        for delta in self.deltas.drain(keep..) {
+            self.deltas_size -= delta.data.len();
+        }
+        self.current = Some((frame, state));
+        self.inputs.truncate((target - oldest) as usize);
+
+        for index in (frame - oldest) as usize..(target - oldest) as usize {
+            let input = self.inputs[index];
+            nes.run_frame_with(&input)?;
+        }
+
+        let rewound = self.frame - target;
+        self.frame = target;
+
+        Ok(rewound)
+    }
+
+    pub fn clear(&mut self) {
+        self.frame = 0;
+        self.current = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+        self.inputs.clear();
+    }
+
+    fn oldest_frame(&self) -> u64 {
+        match (self.deltas.front(), &self.current) {
+            (Some(delta), _) => delta.frame,
+            (None, Some((frame, _))) => *frame,
+            (None, None) => self.frame,
+        }
+    }
+
+    fn snapshot(&mut self, nes: &Nes) {
+        let state = nes.save_state();
+
+        if let Some((frame, previous)) = self.current.replace((self.frame, state)) {
+            let (_, state) = self.current.as_ref().unwrap();
+            let data = encode_delta(state, &previous);
+
+            self.deltas_size += data.len();
+            self.deltas.push_back(Delta { frame,
+                                          len: previous.len(),
+                                          data });
+        }
+
+        // The newest snapshot always stays, it's what every delta is
+        // applied to
+        while self.memory_used() > self.budget {
+            let Some(delta) = self.deltas.pop_front() else {
+                break;
+            };
+            self.deltas_size -= delta.data.len();
+
+            let oldest = self.oldest_frame();
+            self.inputs.drain(..(oldest - delta.frame) as usize);
+        }
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_INTERVAL, DEFAULT_REWIND_BUDGET)
+    }
+}
+
+// `older` XORed with `newer`, padded or cut to `older`'s length, as pairs
+// of a zero run and a literal run, each count as a LEB128 varint. Save
+// states barely change between frames so this is mostly zero runs
+fn encode_delta(newer: &[Byte], older: &[Byte]) -> Vec<Byte> {
+    let xor: Vec<Byte> = older.iter().enumerate()
+                              .map(|(index, byte)| byte ^ newer.get(index).copied().unwrap_or(0))
+                              .collect();
+
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < xor.len() {
+        let zeros = xor[pos..].iter().take_while(|&&byte| byte == 0).count();
+        pos += zeros;
+
+        // Short zero runs inside a literal cost more to break out than to
+        // carry along
+        let start = pos;
+        while pos < xor.len() {
+            let run = xor[pos..].iter().take(4).take_while(|&&byte| byte == 0).count();
+            if run == 4 || pos + run == xor.len() {
+                break;
+            }
+            pos += run.max(1);
+        }
+
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, pos - start);
+        out.extend_from_slice(&xor[start..pos]);
+    }
+
+    out
+}
+
+// Turns the newer state into the older one in place
+fn decode_delta(state: &mut Vec<Byte>, delta: &Delta) {
+    state.resize(delta.len, 0);
+
+    let mut data = delta.data.as_slice();
+    let mut pos = 0;
+
+    while !data.is_empty() {
+        pos += read_varint(&mut data);
+        let literal = read_varint(&mut data);
+
+        let (bytes, rest) = data.split_at(literal);
+        state[pos..pos + literal].iter_mut().zip(bytes).for_each(|(byte, xor)| *byte ^= xor);
+
+        pos += literal;
+        data = rest;
+    }
+}
+
+fn write_varint(out: &mut Vec<Byte>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as Byte & 0x7F) | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as Byte);
+}
+
+fn read_varint(data: &mut &[Byte]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    while let Some((&byte, rest)) = data.split_first() {
+        *data = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if (byte & 0x80) == 0 {
+            break;
+        }
+    }
+
+    value
+}
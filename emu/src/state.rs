@@ -0,0 +1,308 @@
+use {
+    crate::{
+        mem::*,
+        nes::*,
+        error::*,
+    },
+};
+
+pub const STATE_MAGIC: [u8; 4] = *b"NESS";
+pub const STATE_VERSION: u16   = 1;
+
+// Magic, version, ROM hash, payload checksum and payload length
+pub const STATE_HEADER_SIZE: usize = 4 + 2 + 8 + 8 + 4;
+
+pub const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+pub const FNV_PRIME: u64  = 0x0000_0100_0000_01B3;
+
+// FNV-1a, stable across builds and platforms unlike the std hashers
+pub fn fnv1a(mut hash: u64, bytes: &[Byte]) -> u64 {
+    for &byte in bytes {
+        hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+    }
+
+    hash
+}
+
+// Machine state as a flat little-endian byte stream. Each part writes its
+// fields in a fixed order and reads them back in the same order, nothing
+// is tagged so the layout is tied to `STATE_VERSION`
+pub trait Savestate {
+    fn save(&self, state: &mut StateWriter);
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+// Implements `Savestate` over the listed fields, in the module that owns
+// the type so private fields can be named
+macro_rules! savestate {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::state::Savestate for $type {
+            #[allow(unused_variables)]
+            fn save(&self, state: &mut $crate::state::StateWriter) {
+                $($crate::state::Savestate::save(&self.$field, state);)*
+            }
+
+            #[allow(unused_variables)]
+            fn load(&mut self, state: &mut $crate::state::StateReader) -> Result<(), $crate::error::StateError> {
+                $($crate::state::Savestate::load(&mut self.$field, state)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use savestate;
+
+#[derive(Debug, Default, Clone)]
+pub struct StateWriter {
+    data: Vec<Byte>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateReader<'a> {
+    data: &'a [Byte],
+    pos: usize,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub fn write(&mut self, bytes: &[Byte]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<Byte> {
+        self.data
+    }
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [Byte]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn read(&mut self, count: usize) -> Result<&'a [Byte], StateError> {
+        let bytes = self.data.get(self.pos..self.pos + count).ok_or(StateError::Truncated)?;
+        self.pos += count;
+
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[Byte; N], StateError> {
+        Ok(self.read(N)?.try_into().unwrap())
+    }
+
+    #[inline(always)]
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+macro_rules! savestate_number {
+    ($($type:ty),*) => {
+        $(
+            impl Savestate for $type {
+                fn save(&self, state: &mut StateWriter) {
+                    state.write(&self.to_le_bytes());
+                }
+
+                fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+                    *self = <$type>::from_le_bytes(state.read_array()?);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+savestate_number!(u8, u16, u32, u64, i8, i16, i32, f32, f64);
+
+// Bank numbers and table indices, all tiny
+impl Savestate for usize {
+    fn save(&self, state: &mut StateWriter) {
+        (*self as u32).save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        *self = u32::from_le_bytes(state.read_array()?) as usize;
+        Ok(())
+    }
+}
+
+impl Savestate for bool {
+    fn save(&self, state: &mut StateWriter) {
+        state.write(&[*self as Byte]);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        *self = match state.read_array::<1>()? {
+            [0] => false,
+            [1] => true,
+
+            _ => return Err(StateError::Corrupted),
+        };
+
+        Ok(())
+    }
+}
+
+impl<T: Savestate + Default> Savestate for Option<T> {
+    fn save(&self, state: &mut StateWriter) {
+        self.is_some().save(state);
+
+        if let Some(value) = self {
+            value.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut some = false;
+        some.load(state)?;
+
+        *self = if some {
+            let mut value = T::default();
+            value.load(state)?;
+            Some(value)
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+}
+
+impl<A: Savestate, B: Savestate> Savestate for (A, B) {
+    fn save(&self, state: &mut StateWriter) {
+        self.0.save(state);
+        self.1.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.0.load(state)?;
+        self.1.load(state)
+    }
+}
+
+impl<T: Savestate, const N: usize> Savestate for [T; N] {
+    fn save(&self, state: &mut StateWriter) {
+        self.iter().for_each(|value| value.save(state));
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.iter_mut().try_for_each(|value| value.load(state))
+    }
+}
+
+// Length-prefixed, loading resizes to whatever was saved
+impl<T: Savestate + Default> Savestate for Vec<T> {
+    fn save(&self, state: &mut StateWriter) {
+        self.len().save(state);
+        self.iter().for_each(|value| value.save(state));
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut len = 0usize;
+        len.load(state)?;
+
+        // Every element takes at least a byte
+        if len > state.remaining() {
+            return Err(StateError::Truncated);
+        }
+
+        self.clear();
+        self.resize_with(len, T::default);
+        self.iter_mut().try_for_each(|value| value.load(state))
+    }
+}
+
+// Buffers sized by the hardware or the ROM, same layout as `Vec` but the
+// saved length has to match since the rest of the code indexes by it
+impl<T: Savestate> Savestate for Box<[T]> {
+    fn save(&self, state: &mut StateWriter) {
+        self.len().save(state);
+        self.iter().for_each(|value| value.save(state));
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut len = 0usize;
+        len.load(state)?;
+
+        if len != self.len() {
+            return Err(StateError::Corrupted);
+        }
+
+        self.iter_mut().try_for_each(|value| value.load(state))
+    }
+}
+
+impl Nes {
+    // The whole machine behind a header naming the ROM it belongs to,
+    // see `load_state`
+    pub fn save_state(&self) -> Vec<Byte> {
+        let mut payload = StateWriter::new();
+        self.cpu.save(&mut payload);
+        let payload = payload.into_inner();
+
+        let mut state = Vec::with_capacity(STATE_HEADER_SIZE + payload.len());
+        state.extend(STATE_MAGIC);
+        state.extend(STATE_VERSION.to_le_bytes());
+        state.extend(self.rom_hash().to_le_bytes());
+        state.extend(fnv1a(FNV_OFFSET, &payload).to_le_bytes());
+        state.extend((payload.len() as u32).to_le_bytes());
+        state.extend(payload);
+
+        state
+    }
+
+    // The header and checksum are checked before anything is touched, a
+    // state for another ROM or a damaged one is refused as a whole. A
+    // payload that still fails to decode rolls the machine back to where
+    // it was
+    pub fn load_state(&mut self, data: &[Byte]) -> Result<(), StateError> {
+        let mut header = StateReader::new(data);
+
+        if header.read_array::<4>()? != STATE_MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let version = u16::from_le_bytes(header.read_array()?);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        if u64::from_le_bytes(header.read_array()?) != self.rom_hash() {
+            return Err(StateError::RomMismatch);
+        }
+
+        let checksum = u64::from_le_bytes(header.read_array()?);
+        let len = u32::from_le_bytes(header.read_array()?) as usize;
+
+        let payload = header.read(len)?;
+        if fnv1a(FNV_OFFSET, payload) != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        let mut backup = StateWriter::new();
+        self.cpu.save(&mut backup);
+        let backup = backup.into_inner();
+
+        let mut reader = StateReader::new(payload);
+        let result = self.cpu.load(&mut reader).and_then(|_| match reader.remaining() {
+            0 => Ok(()),
+            _ => Err(StateError::Corrupted),
+        });
+
+        if result.is_err() {
+            self.cpu.load(&mut StateReader::new(&backup)).expect("reloading our own state");
+        }
+
+        result
+    }
+
+    fn rom_hash(&self) -> u64 {
+        self.cpu.mem.cartridge().map_or(0, |cartridge| cartridge.rom_hash)
+    }
+}
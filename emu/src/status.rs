@@ -1,3 +1,5 @@
+use crate::state::*;
+
 pub const NEG_MASK: u8 = 1 << 7;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -6,11 +8,19 @@ pub enum ExecStatus {
     Exit,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
 #[derive(Debug)]
 pub struct CpuStatus {
     flags: u8
 }
 
+savestate!(CpuStatus { flags });
+
 impl CpuStatus {
     #[inline(always)]
     pub fn fetch(&self, mask: u8) -> bool {
@@ -27,6 +37,22 @@ impl CpuStatus {
         self.flags &= !mask;
     }
 
+    // Hardware keeps C in bit 0 and N in bit 7, the reverse of our layout
+    pub fn to_stack(&self, brk: bool) -> u8 {
+        let flags = if brk {
+            self.flags | Self::BREAK
+        } else {
+            self.flags & !Self::BREAK
+        };
+
+        (flags | Self::UNUSED).reverse_bits()
+    }
+
+    // B and the unused bit only exist on the stack
+    pub fn from_stack(value: u8) -> Self {
+        Self::new(value.reverse_bits() & !(Self::BREAK | Self::UNUSED))
+    }
+
     pub fn new(flags: u8) -> Self {
         Self { flags }
     }
@@ -39,7 +65,7 @@ impl CpuStatus {
     pub const DECIMAL: u8     = 1 << 4;
     pub const BREAK: u8       = 1 << 3;
 
-    /* *Gap* */
+    pub const UNUSED: u8      = 1 << 2;
 
     pub const OVERFLOW: u8    = 1 << 1;
     pub const NEGATIVE: u8    = 1 << 0;
@@ -2,7 +2,24 @@ use {
     crate::{
         cpu::*,
         status::*,
-    }
+        nes::*,
+        region::*,
+        ppu::*,
+        wav::*,
+        movie::*,
+        input::*,
+        error::*,
+        cartridge::*,
+        state::*,
+        rewind::*,
+        disasm::*,
+        asm::*,
+        opcode::*,
+        mem::*,
+        consts::*,
+    },
+
+    std::io::Cursor,
 };
 
 // BEQ
@@ -226,6 +243,19 @@ fn sty() {
     assert_eq!(cpu.mem.read(0xAF), 0x02);
 }
 
+#[test]
+fn sty_zero_page_x() {
+    let mut cpu = Cpu::default();
+
+    // Y and X differ so the wrong index lands elsewhere
+    cpu.interpret([
+        0xC8, 0xC8, 0xE8, 0x94, 0xAE, 0x00,
+    ]);
+
+    assert_eq!(cpu.mem.read(0xAF), 0x02);
+    assert_eq!(cpu.mem.read(0xB0), 0x00);
+}
+
 // STA
 
 #[test]
@@ -333,3 +363,1013 @@ fn lda_imm() {
     assert_eq!(cpu.status.fetch(CpuStatus::ZERO), false);
     assert_eq!(cpu.status.fetch(CpuStatus::NEGATIVE), false);
 }
+
+// INTERRUPTS
+
+#[test]
+fn nmi_pushes_state() {
+    let mut cpu = Cpu::default();
+
+    cpu.mem.write_word(0xFFFA, 0x9000);
+    cpu.pc = 0x8123;
+    cpu.status.set_on(CpuStatus::CARRY);
+    cpu.status.set_on(CpuStatus::NEGATIVE);
+
+    cpu.nmi();
+
+    assert_eq!(cpu.pc, 0x9000);
+    assert_eq!(cpu.sp, 0xFA);
+    assert_eq!(cpu.mem.read_word(0x01FC), 0x8123);
+    assert_eq!(cpu.mem.read(0x01FB), 0b1010_0001);
+    assert!(cpu.status.fetch(CpuStatus::INTERRUPT));
+}
+
+#[test]
+fn irq_respects_disable() {
+    let mut cpu = Cpu::default();
+
+    cpu.mem.write_word(0xFFFE, 0x9000);
+    cpu.pc = 0x8000;
+    cpu.status.set_on(CpuStatus::INTERRUPT);
+
+    assert!(!cpu.irq());
+    assert_eq!(cpu.pc, 0x8000);
+
+    cpu.status.set_off(CpuStatus::INTERRUPT);
+    assert!(cpu.irq());
+    assert_eq!(cpu.pc, 0x9000);
+}
+
+#[test]
+fn cli_sei() {
+    let mut cpu = Cpu::default();
+
+    // Every reset masks IRQs
+    cpu.interpret([0x00]);
+    assert!(cpu.status.fetch(CpuStatus::INTERRUPT));
+    assert!(Nes::default().cpu.status.fetch(CpuStatus::INTERRUPT));
+
+    cpu.interpret([
+        0x58,  // cli
+        0x00,  // brk
+    ]);
+    assert!(!cpu.status.fetch(CpuStatus::INTERRUPT));
+
+    cpu.interpret([
+        0x58,  // cli
+        0x78,  // sei
+        0x00,  // brk
+    ]);
+    assert!(cpu.status.fetch(CpuStatus::INTERRUPT));
+}
+
+#[test]
+fn rti_returns_from_irq() {
+    let mut cpu = Cpu::default();
+
+    cpu.mem.write_word(0xFFFE, 0x9000);
+    cpu.mem.write(0x9000, 0x40);  // rti
+    cpu.pc = 0x8123;
+    cpu.status.set_on(CpuStatus::CARRY);
+
+    assert!(cpu.irq());
+    assert!(cpu.status.fetch(CpuStatus::INTERRUPT));
+
+    assert_eq!(cpu.exec_next().unwrap(), ExecStatus::Executing);
+    assert_eq!(cpu.pc, 0x8123);
+    assert_eq!(cpu.sp, STACK_RESET);
+    assert!(cpu.status.fetch(CpuStatus::CARRY));
+    assert!(!cpu.status.fetch(CpuStatus::INTERRUPT));
+}
+
+// CYCLES
+
+#[test]
+fn instruction_cycles() {
+    let mut cpu = Cpu::default();
+    cpu.reset_load_rom(&[
+        0xA9, 0x00,        // lda 0x00 (imm)
+        0xBD, 0xFF, 0x01,  // lda 0x01FF,x
+        0xF0, 0x00,        // beq 0x00
+    ]);
+    cpu.x = 1;
+
+    cpu.exec_next().unwrap();
+    assert_eq!(cpu.mem.cycles(), 2);
+
+    // Crosses into page 0x02
+    cpu.exec_next().unwrap();
+    assert_eq!(cpu.mem.cycles(), 7);
+
+    cpu.exec_next().unwrap();
+    assert_eq!(cpu.mem.cycles(), 10);
+}
+
+// DMA
+
+fn oam_dma_program(cpu: &mut Cpu, load: &[u8]) {
+    cpu.reset_load_rom(&[load, &[0x8D, 0x14, 0x40]].concat());  // sta 0x4014
+
+    for index in 0..=0xFF {
+        cpu.mem.write(0x0200 + index, index as u8 ^ 0x5A);
+    }
+}
+
+#[test]
+fn oam_dma_copy() {
+    let mut cpu = Cpu::default();
+    oam_dma_program(&mut cpu, &[0xA9, 0x02]);  // lda 0x02 (imm)
+    cpu.mem.ppu_mut().oam_addr = 0x10;
+
+    cpu.exec_next().unwrap();
+    cpu.exec_next().unwrap();
+
+    // The copy starts at OAMADDR and wraps around
+    let oam = &cpu.mem.ppu().oam;
+    assert_eq!(oam[0x10], 0x5A);
+    assert_eq!(oam[0x0F], 0xFF ^ 0x5A);
+}
+
+#[test]
+fn oam_dma_alignment() {
+    // Instruction ends on an even cycle, one extra alignment cycle
+    let mut cpu = Cpu::default();
+    oam_dma_program(&mut cpu, &[0xA9, 0x02]);  // lda 0x02 (imm)
+
+    cpu.exec_next().unwrap();
+    cpu.exec_next().unwrap();
+    assert_eq!(cpu.mem.cycles(), 2 + 4 + 514);
+
+    let mut cpu = Cpu::default();
+    oam_dma_program(&mut cpu, &[0xA5, 0x10]);  // lda 0x10 (zp)
+    cpu.mem.write(0x10, 0x02);
+
+    cpu.exec_next().unwrap();
+    cpu.exec_next().unwrap();
+    assert_eq!(cpu.mem.cycles(), 3 + 4 + 513);
+}
+
+#[test]
+fn dmc_dma() {
+    let mut cpu = Cpu::default();
+    cpu.mem.write(0xC000, 0x77);
+
+    cpu.mem.dma.request_dmc(0xC000);
+    cpu.run_dma();
+    assert_eq!(cpu.mem.cycles(), 3);
+    assert_eq!(cpu.mem.dma.take_dmc_sample(), Some(0x77));
+
+    cpu.mem.dma.request_dmc(0xC000);
+    cpu.run_dma();
+    assert_eq!(cpu.mem.cycles(), 3 + 4);
+}
+
+#[test]
+fn dmc_during_oam_dma() {
+    let mut cpu = Cpu::default();
+    cpu.mem.write(0xC000, 0x77);
+    cpu.mem.write(0x0200, 0x42);
+
+    cpu.mem.write(0x4014, 0x02);
+    cpu.mem.dma.request_dmc(0xC000);
+    cpu.run_dma();
+
+    // The DMC steals a get slot and the copy realigns afterwards
+    assert_eq!(cpu.mem.cycles(), 514 + 2);
+    assert_eq!(cpu.mem.dma.take_dmc_sample(), Some(0x77));
+    assert_eq!(cpu.mem.ppu().oam[0], 0x42);
+}
+
+// SYSTEM
+
+#[test]
+fn ppu_clock_ratio() {
+    let mut nes = Nes::new(Region::Ntsc);
+    for _ in 0..5 {
+        nes.cpu.mem.tick();
+    }
+    assert_eq!(nes.cpu.mem.ppu().dot, 15);
+
+    // 3.2 dots per cycle on PAL
+    let mut nes = Nes::new(Region::Pal);
+    for _ in 0..5 {
+        nes.cpu.mem.tick();
+    }
+    assert_eq!(nes.cpu.mem.ppu().dot, 16);
+}
+
+#[test]
+fn run_frame_regions() {
+    // Zeroed memory runs BRK over and over, 7 cycles each, with the
+    // frame interrupt masked
+    for (region, lines, ratio) in [(Region::Ntsc, 262, 3.0), (Region::Pal, 312, 3.2), (Region::Dendy, 312, 3.0)] {
+        let mut nes = Nes::new(region);
+        nes.run_frame().unwrap();
+
+        let frame_dots = (lines * DOTS_PER_LINE as u64) as f64;
+        let dots = nes.cycles() as f64 * ratio;
+
+        assert_eq!(nes.frame(), 1);
+        assert!(dots >= frame_dots && dots < frame_dots + 7.0 * ratio, "{region:?}");
+    }
+}
+
+#[test]
+fn region_vblank() {
+    for (region, line) in [(Region::Ntsc, 241), (Region::Pal, 241), (Region::Dendy, 291)] {
+        let mut nes = Nes::new(region);
+
+        while (nes.cpu.mem.ppu().status & STATUS_VBLANK) == 0 {
+            nes.cpu.mem.tick();
+        }
+        assert_eq!(nes.cpu.mem.ppu().scanline, line, "{region:?}");
+    }
+
+    assert!((Region::Ntsc.frame_rate() - 60.0988).abs() < 1e-3);
+    assert!((Region::Pal.frame_rate() - 50.0070).abs() < 1e-3);
+    assert!((Region::Dendy.frame_rate() - 50.0070).abs() < 1e-2);
+}
+
+#[test]
+fn pal_has_no_odd_frame_skip() {
+    let mut nes = Nes::new(Region::Pal);
+    nes.cpu.mem.ppu_mut().mask = MASK_BACKGROUND;
+
+    let dots = |nes: &mut Nes| {
+        let frame = nes.frame();
+        let mut dots = 0;
+
+        while nes.frame() == frame {
+            nes.cpu.mem.ppu_tick();
+            dots += 1;
+        }
+
+        dots
+    };
+
+    dots(&mut nes);
+    assert_eq!(dots(&mut nes), 312 * 341);
+    assert_eq!(dots(&mut nes), 312 * 341);
+}
+
+// WAV
+
+#[test]
+fn wav_header_and_samples() {
+    let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+    wav.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+
+    let raw = wav.finish().unwrap().into_inner();
+    assert_eq!(raw.len(), 44 + 8);
+
+    assert_eq!(&raw[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(raw[4..8].try_into().unwrap()), 36 + 8);
+    assert_eq!(&raw[8..16], b"WAVEfmt ");
+    assert_eq!(u16::from_le_bytes(raw[22..24].try_into().unwrap()), 1);
+    assert_eq!(u32::from_le_bytes(raw[24..28].try_into().unwrap()), 44_100);
+    assert_eq!(u16::from_le_bytes(raw[34..36].try_into().unwrap()), 16);
+    assert_eq!(&raw[36..40], b"data");
+    assert_eq!(u32::from_le_bytes(raw[40..44].try_into().unwrap()), 8);
+
+    // Clamped to the 16-bit range
+    let samples: Vec<i16> = raw[44..].chunks_exact(2)
+                                     .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                                     .collect();
+    assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+}
+
+#[test]
+fn wav_recording() {
+    let mut nes = Nes::default();
+    nes.cpu.reset_load_rom(&[
+        0xA9, 0x00,  // lda 0x00 (imm)
+        0xF0, 0xFE,  // beq 0xFE (-2)
+    ]);
+    nes.set_sample_rate(44_100);
+
+    let mut wav = WavWriter::new(Cursor::new(Vec::new()), nes.sample_rate()).unwrap();
+    nes.record_wav(&mut wav, 6).unwrap();
+
+    // About 735 samples a frame, a few are still held back for the resampler
+    let samples = wav.samples();
+    assert!((4_400..=4_420).contains(&samples), "{samples} samples");
+    assert_eq!(nes.frame(), 6);
+}
+
+// MOVIE
+
+// NROM image with `program` at $8000 and the reset vector pointing at it
+fn nrom(program: &[u8]) -> Vec<u8> {
+    let mut raw = vec![
+        b'N', b'E', b'S', 0x1A,
+        1, 1, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+
+    raw.extend(prg);
+    raw.extend([0; 0x2000]);
+    raw
+}
+
+// Polls the pad over and over, A and B end up in $10 and $11 and the
+// loop count in $12. The frame interrupt is inhibited first, there's no
+// handler for it
+fn input_loop_rom() -> Vec<u8> {
+    nrom(&[
+        0xA9, 0x40,        // lda 0x40 (imm)
+        0x8D, 0x17, 0x40,  // sta 0x4017
+        0xA9, 0x01,        // lda 0x01 (imm)
+        0x8D, 0x16, 0x40,  // sta 0x4016
+        0xA9, 0x00,        // lda 0x00 (imm)
+        0x8D, 0x16, 0x40,  // sta 0x4016
+        0xAD, 0x16, 0x40,  // lda 0x4016
+        0x85, 0x10,        // sta 0x10
+        0xAD, 0x16, 0x40,  // lda 0x4016
+        0x85, 0x11,        // sta 0x11
+        0xE8,              // inx
+        0x86, 0x12,        // stx 0x12
+        0xA9, 0x00,        // lda 0x00 (imm)
+        0xF0, 0xE5,        // beq 0xE5 (-27)
+    ])
+}
+
+fn input_loop_nes() -> Nes {
+    Nes::with_cartridge(Cartridge::from_bytes(&input_loop_rom()).unwrap(), Region::Ntsc)
+}
+
+fn record_movie(nes: &mut Nes, frames: usize) -> Movie {
+    let mut movie = Movie::new(nes).unwrap();
+    movie.start(nes);
+
+    for frame in 0..frames {
+        nes.set_buttons(0, if frame % 3 == 0 { BUTTON_A } else { BUTTON_B });
+        nes.set_buttons(1, frame as u8);
+
+        let commands = if frame == 5 { COMMAND_RESET } else { 0 };
+        movie.record_frame(nes, commands).unwrap();
+    }
+
+    movie
+}
+
+#[test]
+fn movie_fm2_round_trip() {
+    let mut nes = Nes::default();
+    nes.input_mut().connect(1, Box::new(Zapper::default()));
+
+    let mut movie = Movie::new(&nes).unwrap();
+    movie.region = Region::Dendy;
+    movie.rom_filename = "game".to_string();
+    movie.comments.push("author someone".to_string());
+    movie.hashes = vec![0x0123_4567_89AB_CDEF, 1];
+    movie.frames = vec![
+        FrameInput { commands: COMMAND_POWER,
+                     ports: [PortInput::Joypad(BUTTON_A | BUTTON_RIGHT),
+                             PortInput::Zapper { aim: Some((12, 200)), trigger: true }] },
+        FrameInput { commands: 0,
+                     ports: [PortInput::Joypad(0),
+                             PortInput::Zapper { aim: None, trigger: false }] },
+    ];
+
+    let mut raw = Vec::new();
+    movie.write(&mut raw).unwrap();
+    let text = String::from_utf8(raw).unwrap();
+    assert!(text.contains("\n|2|R......A|12 200 1 0 0||\n"), "{text}");
+
+    let parsed = Movie::parse(&text).unwrap();
+    assert_eq!(parsed.region, Region::Dendy);
+    assert_eq!(parsed.devices, [Device::Joypad, Device::Zapper]);
+    assert_eq!(parsed.rom_filename, "game");
+    assert_eq!(parsed.comments, movie.comments);
+    assert_eq!(parsed.hashes, movie.hashes);
+    assert_eq!(parsed.frames, movie.frames);
+}
+
+#[test]
+fn movie_fm2_four_score() {
+    let mut nes = Nes::default();
+    nes.input_mut().connect_four_score();
+
+    let mut movie = Movie::new(&nes).unwrap();
+    movie.frames.push(FrameInput { commands: 0,
+                                   ports: [PortInput::FourScore([BUTTON_A, BUTTON_START]),
+                                           PortInput::FourScore([BUTTON_B, BUTTON_UP])] });
+
+    let mut raw = Vec::new();
+    movie.write(&mut raw).unwrap();
+    let text = String::from_utf8(raw).unwrap();
+
+    assert!(text.starts_with("version 3\nrerecordCount 0\n"), "{text}");
+
+    // Players in order, 1 and 3 are on the first port
+    assert!(text.contains("fourscore 1\n"));
+    assert!(text.contains("|0|.......A|......B.|....T...|...U....||"), "{text}");
+
+    let parsed = Movie::parse(&text).unwrap();
+    assert!(parsed.four_score());
+    assert_eq!(parsed.frames, movie.frames);
+}
+
+#[test]
+fn movie_fm2_parse() {
+    let text = "version 3\n\
+                emuVersion 22020\n\
+                palFlag 1\n\
+                port0 1\n\
+                port1 0\n\
+                someFutureKey 7\n\
+                |0|.L..T..A||||\n\
+                |1|abcdefgh|||\n";
+
+    let movie = Movie::parse(text).unwrap();
+    assert_eq!(movie.region, Region::Pal);
+    assert_eq!(movie.devices, [Device::Joypad, Device::Unplugged]);
+    assert_eq!(movie.frames[0].ports[0], PortInput::Joypad(BUTTON_LEFT | BUTTON_START | BUTTON_A));
+    assert_eq!(movie.frames[1], FrameInput { commands: COMMAND_RESET,
+                                             ports: [PortInput::Joypad(0xFF), PortInput::None] });
+
+    assert_eq!(Movie::parse("version 2\n").unwrap_err(), MovieError::Parse(1));
+    assert_eq!(Movie::parse("port0 1\n|0|RL|\n").unwrap_err(), MovieError::Parse(2));
+}
+
+#[test]
+fn movie_unsupported_device() {
+    let mut nes = Nes::default();
+    nes.input_mut().connect(1, Box::new(Paddle::default()));
+
+    assert_eq!(Movie::new(&nes).unwrap_err(), MovieError::UnsupportedDevice(Device::Paddle));
+}
+
+#[test]
+fn movie_rom_checksum() {
+    let mut raw = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    raw.extend((0..0x4000).map(|i| (i % 251) as u8));
+    raw.extend([0x55; 0x2000]);
+
+    assert_eq!(rom_checksum(&raw).unwrap(), "base64:U0wh47Z1jblTvGEUchb2FQ==");
+    assert_eq!(rom_checksum(&raw[..16]).unwrap_err(), RomError::Truncated);
+}
+
+#[test]
+fn movie_md5_vectors() {
+    let hex = |data: &[u8]| -> String { md5(data).iter().map(|byte| format!("{byte:02x}")).collect() };
+
+    // RFC 1321, the last two need a second padding block
+    assert_eq!(hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(hex(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+    assert_eq!(hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(hex(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+    assert_eq!(hex(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+    assert_eq!(hex(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"),
+               "d174ab98d277d9f5a5611c2c9f419d9f");
+    assert_eq!(hex(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"),
+               "57edf4a22be3c955ac49da2e2107b67a");
+}
+
+#[test]
+fn movie_base64_vectors() {
+    // RFC 4648
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"foob"), "Zm9vYg==");
+    assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+}
+
+#[test]
+fn movie_playback_is_deterministic() {
+    let mut nes = input_loop_nes();
+    let movie = record_movie(&mut nes, 10);
+
+    assert_eq!(movie.frames[5].commands, COMMAND_RESET);
+    assert_eq!(movie.hashes.len(), 10);
+    let last = nes.state_hash();
+
+    // Through the text format and on a machine that ran something else
+    let mut raw = Vec::new();
+    movie.write(&mut raw).unwrap();
+    let movie = Movie::parse(&String::from_utf8(raw).unwrap()).unwrap();
+
+    let mut nes = input_loop_nes();
+    nes.set_buttons(0, BUTTON_SELECT);
+    nes.run_frame().unwrap();
+
+    assert_eq!(movie.verify(&mut nes).unwrap(), None);
+    assert_eq!(nes.state_hash(), last);
+    // A was held on the last frame
+    assert_eq!(nes.cpu.mem.peek(0x10), 0x41);
+    assert_eq!(nes.cpu.mem.peek(0x11), 0x40);
+}
+
+#[test]
+fn movie_reports_desync() {
+    let mut nes = input_loop_nes();
+    let mut movie = record_movie(&mut nes, 10);
+
+    movie.frames[4].ports[0] = PortInput::Joypad(BUTTON_A | BUTTON_B);
+
+    let desync = movie.verify(&mut input_loop_nes()).unwrap().unwrap();
+    assert_eq!(desync.frame, 4);
+    assert_eq!(desync.expected, movie.hashes[4]);
+    assert_ne!(desync.actual, desync.expected);
+}
+
+// UxROM with $AA all over bank 0 and a program in the fixed bank that
+// switches bank 1 in while A is held, $10 gets the first byte of
+// whichever bank is at $8000
+fn uxrom_bank_nes() -> Nes {
+    let program = asm!(
+        "        .org $C000",
+        "loop:   lda #1",
+        "        sta $4016",
+        "        lda #0",
+        "        sta $4016",
+        "        lda $4016",
+        "        and #1",
+        "        beq keep",
+        "        sta bank1     ; the ROM holds 1 there too, no bus conflict",
+        "keep:   lda $8000",
+        "        sta $10",
+        "        lda #0",
+        "        beq loop",
+        "bank1:  .byte 1",
+        "        .org $FFFC",
+        "        .word loop, loop",
+    );
+
+    let mut raw = vec![b'N', b'E', b'S', 0x1A, 2, 1, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    raw.extend([0xAA; 0x4000]);
+    raw.extend(program);
+    raw.extend([0; 0x2000]);
+
+    Nes::with_cartridge(Cartridge::from_bytes(&raw).unwrap(), Region::Ntsc)
+}
+
+#[test]
+fn movie_power_resets_mapper() {
+    let mut nes = uxrom_bank_nes();
+
+    let mut movie = Movie::new(&nes).unwrap();
+    movie.start(&mut nes);
+    for _ in 0..5 {
+        movie.record_frame(&mut nes, 0).unwrap();
+    }
+    assert_eq!(nes.cpu.mem.peek(0x10), 0xAA);
+
+    // Leaves bank 1 switched in, power has to undo it
+    let mut played = uxrom_bank_nes();
+    played.set_buttons(0, BUTTON_A);
+    played.run_frame().unwrap();
+    played.set_buttons(0, 0);
+    assert_eq!(played.cpu.mem.peek(0x10), 0xA9);
+
+    assert_eq!(movie.verify(&mut played).unwrap(), None);
+    assert_eq!(played.cpu.mem.peek(0x10), 0xAA);
+}
+
+#[test]
+fn nes_reset_and_power() {
+    let mut nes = input_loop_nes();
+    nes.run_frame().unwrap();
+    assert_ne!(nes.cpu.mem.peek(0x12), 0);
+
+    // Reset keeps RAM, power clears it
+    nes.reset();
+    assert_eq!(nes.cpu.pc, 0x8000);
+    assert_eq!(nes.cpu.sp, 0xFD - 3);
+    assert_ne!(nes.cpu.mem.peek(0x12), 0);
+
+    nes.input_mut().connect(1, Box::new(Zapper::default()));
+    nes.power();
+    assert_eq!(nes.cpu.pc, 0x8000);
+    assert_eq!(nes.cycles(), 0);
+    assert_eq!(nes.cpu.mem.peek(0x12), 0);
+    assert_eq!(nes.input().port(1).device(), Device::Zapper);
+}
+
+// SAVE STATES
+
+fn run_frames(nes: &mut Nes, frames: usize) {
+    for frame in 0..frames {
+        nes.set_buttons(0, frame as u8);
+        nes.run_frame().unwrap();
+    }
+}
+
+#[test]
+fn save_state_round_trip() {
+    let mut nes = input_loop_nes();
+    nes.input_mut().connect(1, Box::new(Zapper::default()));
+    run_frames(&mut nes, 3);
+
+    // Mid-frame, with the pads half shifted out
+    for _ in 0..1000 {
+        nes.step().unwrap();
+    }
+
+    let state = nes.save_state();
+    let (hash, cycles) = (nes.state_hash(), nes.cycles());
+    run_frames(&mut nes, 4);
+    let after = nes.state_hash();
+
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.state_hash(), hash);
+    assert_eq!(nes.cycles(), cycles);
+
+    run_frames(&mut nes, 4);
+    assert_eq!(nes.state_hash(), after);
+}
+
+#[test]
+fn save_state_on_another_machine() {
+    let mut nes = input_loop_nes();
+    nes.set_region(Region::Pal);
+    run_frames(&mut nes, 2);
+
+    let state = nes.save_state();
+    assert_eq!(&state[..4], &STATE_MAGIC);
+
+    let mut other = input_loop_nes();
+    other.load_state(&state).unwrap();
+
+    assert_eq!(other.region(), Region::Pal);
+    assert_eq!(other.state_hash(), nes.state_hash());
+    assert_eq!(other.framebuffer(), nes.framebuffer());
+    assert_eq!(other.save_state(), state);
+}
+
+#[test]
+fn save_state_refused() {
+    let mut nes = input_loop_nes();
+    run_frames(&mut nes, 1);
+
+    let state = nes.save_state();
+    let hash = nes.state_hash();
+
+    let other_rom = nrom(&[0xA9, 0x00, 0xF0, 0xFE]);
+    let mut other = Nes::with_cartridge(Cartridge::from_bytes(&other_rom).unwrap(), Region::Ntsc);
+    assert_eq!(other.load_state(&state), Err(StateError::RomMismatch));
+
+    // Same PRG with different CHR is another ROM too
+    let mut other_rom = input_loop_rom();
+    let last = other_rom.len() - 1;
+    other_rom[last] = 1;
+    let mut other = Nes::with_cartridge(Cartridge::from_bytes(&other_rom).unwrap(), Region::Ntsc);
+    assert_eq!(other.load_state(&state), Err(StateError::RomMismatch));
+
+    assert_eq!(Nes::default().load_state(&state), Err(StateError::RomMismatch));
+
+    let mut damaged = state.clone();
+    damaged[STATE_HEADER_SIZE + 100] ^= 0x01;
+    assert_eq!(nes.load_state(&damaged), Err(StateError::ChecksumMismatch));
+
+    let mut damaged = state.clone();
+    damaged[4] = 99;
+    assert_eq!(nes.load_state(&damaged), Err(StateError::UnsupportedVersion(99)));
+
+    assert_eq!(nes.load_state(b"nope"), Err(StateError::InvalidMagic));
+    assert_eq!(nes.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+
+    // None of it touched the machine
+    assert_eq!(nes.state_hash(), hash);
+}
+
+// A payload that passes the checksum but doesn't decode, rebuilt with a
+// valid header
+fn reseal(state: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut sealed = state[..STATE_HEADER_SIZE - 12].to_vec();
+    sealed.extend(fnv1a(FNV_OFFSET, payload).to_le_bytes());
+    sealed.extend((payload.len() as u32).to_le_bytes());
+    sealed.extend(payload);
+
+    sealed
+}
+
+#[test]
+fn save_state_rolled_back() {
+    let mut pal = input_loop_nes();
+    pal.set_region(Region::Pal);
+    run_frames(&mut pal, 2);
+
+    let state = pal.save_state();
+    let payload = &state[STATE_HEADER_SIZE..];
+
+    let mut nes = input_loop_nes();
+    run_frames(&mut nes, 1);
+    let (hash, framebuffer) = (nes.state_hash(), nes.framebuffer().to_vec());
+
+    // RAM saved as 16 bytes instead of 64K
+    let mut shrunk = payload.to_vec();
+    let at = shrunk.windows(4).position(|bytes| bytes == [0x00, 0x00, 0x01, 0x00]).unwrap();
+    shrunk[at..at + 4].copy_from_slice(&16u32.to_le_bytes());
+    assert_eq!(nes.load_state(&reseal(&state, &shrunk)), Err(StateError::Corrupted));
+
+    let cut = &payload[..payload.len() - 1];
+    assert_eq!(nes.load_state(&reseal(&state, cut)), Err(StateError::Truncated));
+
+    let extra = [payload, &[0]].concat();
+    assert_eq!(nes.load_state(&reseal(&state, &extra)), Err(StateError::Corrupted));
+
+    // The region was already switched when each load failed
+    assert_eq!(nes.region(), Region::Ntsc);
+    assert_eq!(nes.state_hash(), hash);
+    assert_eq!(nes.framebuffer(), &framebuffer[..]);
+
+    run_frames(&mut nes, 2);
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.state_hash(), pal.state_hash());
+}
+
+// REWIND
+
+// Runs `frames` frames with input that changes every frame, returning the
+// state hash after each
+fn run_rewind(rewind: &mut Rewind, nes: &mut Nes, frames: u64) -> Vec<u64> {
+    (0..frames).map(|_| {
+        let frame = rewind.frame();
+        nes.set_buttons(0, if frame.is_multiple_of(3) { BUTTON_A } else { BUTTON_B | (frame as u8 & BUTTON_UP) });
+
+        rewind.run_frame(nes, 0).unwrap();
+        nes.state_hash()
+    }).collect()
+}
+
+#[test]
+fn rewind_between_snapshots() {
+    let mut nes = input_loop_nes();
+    let mut rewind = Rewind::new(10, DEFAULT_REWIND_BUDGET);
+
+    let hashes = run_rewind(&mut rewind, &mut nes, 30);
+    assert_eq!(rewind.available(), 30);
+
+    // Back to the end of frame 23, halfway between two snapshots
+    assert_eq!(rewind.rewind(&mut nes, 7).unwrap(), 7);
+    assert_eq!(rewind.frame(), 23);
+    assert_eq!(nes.state_hash(), hashes[22]);
+
+    // Same input again gives the same machine
+    let replayed = run_rewind(&mut rewind, &mut nes, 7);
+    assert_eq!(replayed, hashes[23..]);
+
+    assert_eq!(rewind.rewind(&mut nes, 0).unwrap(), 0);
+    assert_eq!(nes.state_hash(), hashes[29]);
+}
+
+#[test]
+fn rewind_clamps_to_history() {
+    let mut nes = input_loop_nes();
+    let start = nes.state_hash();
+    let mut rewind = Rewind::new(4, DEFAULT_REWIND_BUDGET);
+
+    assert_eq!(rewind.rewind(&mut nes, 5).unwrap(), 0);
+
+    run_rewind(&mut rewind, &mut nes, 9);
+    assert_eq!(rewind.rewind(&mut nes, 100).unwrap(), 9);
+    assert_eq!(rewind.frame(), 0);
+    assert_eq!(nes.state_hash(), start);
+    assert_eq!(rewind.available(), 0);
+}
+
+#[test]
+fn rewind_budget_drops_oldest() {
+    let mut nes = input_loop_nes();
+    let state = nes.save_state().len();
+
+    // Room for the current snapshot and not much else
+    let budget = state + 256;
+    let mut rewind = Rewind::new(5, budget);
+
+    let hashes = run_rewind(&mut rewind, &mut nes, 60);
+    // Input since the last snapshot comes on top until the next one
+    assert!(rewind.memory_used() <= budget + 5 * size_of::<FrameInput>());
+    assert!(rewind.available() >= 5);
+    assert!(rewind.available() < 60);
+
+    let available = rewind.available();
+    assert_eq!(rewind.rewind(&mut nes, 1000).unwrap(), available);
+    assert_eq!(nes.state_hash(), hashes[(59 - available) as usize]);
+}
+
+// DISASSEMBLER
+
+#[test]
+fn disassemble_formats() {
+    let code = [
+        0xA9, 0x00,        // lda #$00
+        0x9D, 0x00, 0x02,  // sta $0200,x
+        0x6C, 0xFC, 0xFF,  // jmp ($fffc)
+        0x0A,              // asl a
+        0xB1, 0x10,        // lda ($10),y
+        0x61, 0x20,        // adc ($20,x)
+        0x96, 0x30,        // stx $30,y
+        0xE8,              // inx
+        0xF0, 0xFE,        // beq to itself
+        0xD0, 0x02,        // bne over the next two
+        0x02,              // unofficial
+        0x20, 0x00,        // jsr cut off
+    ];
+
+    let lines: Vec<String> = disassemble_bytes(&code, 0x8000).iter().map(|insn| insn.to_string()).collect();
+    assert_eq!(lines, [
+        "LDA #$00",
+        "STA $0200,X",
+        "JMP ($FFFC)",
+        "ASL A",
+        "LDA ($10),Y",
+        "ADC ($20,X)",
+        "STX $30,Y",
+        "INX",
+        "BEQ $8010",
+        "BNE $8016",
+        ".byte $02",
+        ".byte $20",
+        "BRK",
+    ]);
+
+    let insn = disassemble_bytes(&code, 0x8000)[1];
+    assert_eq!(insn.size(), 3);
+    assert_eq!(insn.bytes(), [0x9D, 0x00, 0x02]);
+    assert_eq!(insn.listing(), "8002  9D 00 02  STA $0200,X");
+}
+
+#[test]
+fn disassemble_memory() {
+    let mut cpu = Cpu::default();
+    cpu.interpret([
+        0xA9, 0x05,  // lda #$05
+        0x85, 0x10,  // sta $10
+        0x00,        // brk
+    ]);
+
+    let listing: Vec<String> = disassemble_range(&cpu.mem, ROM_ENTRYPOINT..=ROM_ENTRYPOINT + 4)
+        .iter().map(|insn| insn.to_string()).collect();
+    assert_eq!(listing, ["LDA #$05", "STA $10", "BRK"]);
+
+    // Wraps around the end of the address space, stopping there
+    let end = disassemble_range(&cpu.mem, 0xFFFE..=0xFFFF);
+    assert_eq!(end.last().unwrap().addr, 0xFFFF);
+}
+
+#[test]
+fn disassemble_has_no_side_effects() {
+    let mut nes = Nes::default();
+    nes.set_buttons(0, BUTTON_A);
+    nes.cpu.mem.write(JOYPAD1, 1);
+    nes.cpu.mem.write(JOYPAD1, 0);
+
+    disassemble_range(&nes.cpu.mem, 0x2000..=0x4017);
+
+    // The pad still starts at A
+    assert_eq!(nes.cpu.mem.read(JOYPAD1) & 1, 1);
+    assert_eq!(nes.cpu.mem.read(JOYPAD1) & 1, 0);
+}
+
+#[test]
+fn instruction_table_matches_cpu() {
+    try_init();
+
+    for code in 0..=0xFF {
+        let (mode, length) = match lookup_opcode(code) {
+            Opcode::Uninitialized => continue,
+
+            Opcode::Asl(mode, length) | Opcode::And(mode, length) |
+            Opcode::Stx(mode, length) | Opcode::Sty(mode, length) |
+            Opcode::Sta(mode, length) | Opcode::Adc(mode, length) |
+            Opcode::Lda(mode, length) => (mode, length),
+
+            Opcode::Bcs | Opcode::Bcc | Opcode::Beq => (AddrMode::Relative, 1),
+            _ => (AddrMode::Implied, 0),
+        };
+
+        let (_, table_mode) = lookup_instruction(code).unwrap();
+        assert_eq!(table_mode, mode, "opcode {code:02X}");
+        assert_eq!(table_mode.operand_size(), length);
+    }
+}
+
+// ASSEMBLER
+
+#[test]
+fn assemble_runs() {
+    let mut cpu = Cpu::default();
+    cpu.interpret(asm!(
+        "value = $1234",
+        "        lda #<value",
+        "        sta $10",
+        "        lda #>value",
+        "        sta $11",
+        "",
+        "        lda #$FD       ; counts up to the carry",
+        "loop:   inx",
+        "        adc #1",
+        "        bcc loop",
+        "        stx $12",
+        "        brk",
+    ));
+
+    assert_eq!(cpu.mem.read(0x10), 0x34);
+    assert_eq!(cpu.mem.read(0x11), 0x12);
+    assert_eq!(cpu.mem.read(0x12), 3);
+}
+
+#[test]
+fn assemble_modes() {
+    assert_eq!(asm!(
+        "lda #$00",
+        "lda $10",
+        "lda $10,x",
+        "ldx $10,y",
+        "lda $0010",
+        "lda $1234,X",
+        "lda $1234, y",
+        "lda ($10,x)",
+        "lda ($10),y",
+        "jmp ($FFFC)",
+        "asl",
+        "asl a",
+        "nop",
+    ), [
+        0xA9, 0x00,
+        0xA5, 0x10,
+        0xB5, 0x10,
+        0xB6, 0x10,
+        0xA5, 0x10,
+        0xBD, 0x34, 0x12,
+        0xB9, 0x34, 0x12,
+        0xA1, 0x10,
+        0xB1, 0x10,
+        0x6C, 0xFC, 0xFF,
+        0x0A,
+        0x0A,
+        0xEA,
+    ]);
+
+    // Forward references can't be zero page, STX has no absolute,Y
+    assert_eq!(asm!("lda later", "stx later,y", "later = 5"), [0xAD, 0x05, 0x00, 0x96, 0x05]);
+}
+
+#[test]
+fn assemble_labels_and_directives() {
+    let program = assemble("
+        .org $C000
+start:  jmp end
+        .byte 1, $02, %11, <table, >table
+table:  .word start, table + 2 * 3
+        beq start
+        bne *
+        .org $C020
+end:    lda #(end - start) / 2
+    ").unwrap();
+
+    assert_eq!(program.origin, 0xC000);
+    assert_eq!(program.labels["table"], 0xC008);
+    assert_eq!(program.labels["end"], 0xC020);
+
+    let mut expected = vec![
+        0x4C, 0x20, 0xC0,
+        0x01, 0x02, 0x03, 0x08, 0xC0,
+        0x00, 0xC0, 0x0E, 0xC0,
+        0xF0, 0xF2,
+        0xD0, 0xFE,
+    ];
+    expected.resize(0x20, 0);
+    expected.extend([0xA9, 0x10]);
+
+    assert_eq!(program.bytes, expected);
+}
+
+#[test]
+fn assemble_errors() {
+    assert_eq!(assemble("lda #1\nfoo $10"), Err(AsmError::UnknownInstruction(2)));
+    assert_eq!(assemble("inx #1"), Err(AsmError::InvalidMode(1)));
+    assert_eq!(assemble("jmp ($10),y"), Err(AsmError::InvalidMode(1)));
+    assert_eq!(assemble("lda nowhere"), Err(AsmError::UndefinedSymbol(1)));
+    assert_eq!(assemble("a: inx\na: inx"), Err(AsmError::DuplicateSymbol(2)));
+    assert_eq!(assemble("lda #$100"), Err(AsmError::OutOfRange(1)));
+    assert_eq!(assemble("lda ($1234),y"), Err(AsmError::OutOfRange(1)));
+    assert_eq!(assemble("beq far\n.org $8100\nfar: brk"), Err(AsmError::OutOfRange(1)));
+    assert_eq!(assemble("inx\n.org $7000"), Err(AsmError::Org(2)));
+    assert_eq!(assemble("lda #(1"), Err(AsmError::Syntax(1)));
+    assert_eq!(assemble(".bytes 1"), Err(AsmError::Syntax(1)));
+}
+
+#[test]
+fn assemble_disassembly_round_trip() {
+    for code in 0..=0xFF {
+        let Some((_, mode)) = lookup_instruction(code) else {
+            continue;
+        };
+
+        let bytes = [code, 0x34, 0x12];
+        let insn = disassemble_bytes(&bytes, ROM_ENTRYPOINT)[0];
+
+        let source = insn.to_string();
+        assert_eq!(assemble(&source).unwrap().bytes, bytes[..insn.size() as usize], "{source} ({mode:?})");
+    }
+}
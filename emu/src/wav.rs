@@ -0,0 +1,138 @@
+use {
+    crate::{
+        nes::*,
+        error::*,
+    },
+
+    std::{
+        mem,
+        fs::File,
+        path::Path,
+        io::{
+            self,
+            Seek,
+            SeekFrom,
+            Write,
+            BufWriter,
+        },
+    },
+};
+
+pub const WAV_HEADER_SIZE: u32     = 44;
+pub const WAV_CHANNELS: u16        = 1;
+pub const WAV_BITS_PER_SAMPLE: u16 = 16;
+
+// The RIFF size after the first 8 bytes has to fit in 32 bits
+pub const WAV_MAX_SAMPLES: u32 = (u32::MAX - (WAV_HEADER_SIZE - 8)) / (WAV_BITS_PER_SAMPLE / 8) as u32;
+
+// Mono 16-bit PCM. The sizes in the header are left at zero until
+// `finish` goes back and fills them in
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+
+    sample_rate: u32,
+    samples: u32,
+
+    // Reused by `Nes::record_wav` from frame to frame
+    buffer: Vec<f32>,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    // Refuses the whole batch once the file would go over 4 GiB
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let total = u32::try_from(samples.len()).ok()
+            .and_then(|count| self.samples.checked_add(count))
+            .filter(|&total| total <= WAV_MAX_SAMPLES)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::FileTooLarge, "WAV data over 4 GiB"))?;
+
+        for &sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.inner.write_all(&pcm.to_le_bytes())?;
+        }
+
+        self.samples = total;
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline(always)]
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        // Can't overflow, `write_samples` stops at `WAV_MAX_SAMPLES`
+        let data_size = self.samples * (WAV_BITS_PER_SAMPLE / 8) as u32;
+        let riff_size = WAV_HEADER_SIZE - 8 + data_size;
+
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&riff_size.to_le_bytes())?;
+
+        self.inner.seek(SeekFrom::Start(40))?;
+        self.inner.write_all(&data_size.to_le_bytes())?;
+
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    pub fn new(mut inner: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = WAV_CHANNELS * WAV_BITS_PER_SAMPLE / 8;
+
+        inner.write_all(b"RIFF")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        inner.write_all(b"WAVE")?;
+
+        inner.write_all(b"fmt ")?;
+        inner.write_all(&16u32.to_le_bytes())?;
+        inner.write_all(&1u16.to_le_bytes())?;
+        inner.write_all(&WAV_CHANNELS.to_le_bytes())?;
+        inner.write_all(&sample_rate.to_le_bytes())?;
+        inner.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        inner.write_all(&block_align.to_le_bytes())?;
+        inner.write_all(&WAV_BITS_PER_SAMPLE.to_le_bytes())?;
+
+        inner.write_all(b"data")?;
+        inner.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self { inner, sample_rate,
+                  samples: 0,
+                  buffer: Vec::new() })
+    }
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl Nes {
+    // Runs `frames` frames and appends everything the APU put out, the
+    // writer should use the console's sample rate
+    pub fn record_wav<W: Write + Seek>(
+        &mut self,
+        wav: &mut WavWriter<W>,
+        frames: u64
+    ) -> Result<(), RecordError> {
+        let mut samples = mem::take(&mut wav.buffer);
+        samples.resize(self.sample_rate() as usize, 0.0);
+
+        let recorded = (0..frames).try_for_each(|_| {
+            self.run_frame()?;
+
+            let count = self.drain_samples(&mut samples);
+            wav.write_samples(&samples[..count])?;
+
+            Ok(())
+        });
+
+        wav.buffer = samples;
+        recorded
+    }
+}
@@ -1,3 +1,151 @@
-fn main() {
-    println!("Hello, world!");
+use {
+    emu::{
+        cartridge::Cartridge,
+        nes::Nes,
+        region::Region,
+        wav::WavWriter,
+        movie::Movie,
+    },
+
+    std::{
+        env,
+        path::PathBuf,
+        process::ExitCode,
+    },
+};
+
+const USAGE: &str = "\
+usage: nesa <rom.nes> [options]
+
+options:
+    --frames <n>          frames to run (default 300)
+    --region <region>     ntsc, pal or dendy (default ntsc)
+    --wav <file>          record the audio output to a 16-bit WAV file
+    --sample-rate <hz>    sample rate of the recording (default 48000)
+    --movie <file>        play back an FM2 movie and check it for desyncs";
+
+const DEFAULT_FRAMES: u64 = 300;
+
+struct Options {
+    rom: PathBuf,
+    frames: u64,
+    region: Region,
+
+    wav: Option<PathBuf>,
+    sample_rate: Option<u32>,
+
+    movie: Option<PathBuf>,
+}
+
+fn parse_region(name: &str) -> Option<Region> {
+    match name.to_ascii_lowercase().as_str() {
+        "ntsc" => Some(Region::Ntsc),
+        "pal" => Some(Region::Pal),
+        "dendy" => Some(Region::Dendy),
+
+        _ => None,
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut rom = None;
+    let mut options = Options { rom: PathBuf::new(),
+                                frames: DEFAULT_FRAMES,
+                                region: Region::default(),
+                                wav: None,
+                                sample_rate: None,
+                                movie: None };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+
+        match arg.as_str() {
+            "--frames" => {
+                options.frames = value("--frames")?.parse()
+                    .map_err(|_| "--frames needs a number".to_string())?;
+            }
+            "--region" => {
+                let name = value("--region")?;
+                options.region = parse_region(&name).ok_or(format!("unknown region {name}"))?;
+            }
+            "--wav" => options.wav = Some(value("--wav")?.into()),
+            "--movie" => options.movie = Some(value("--movie")?.into()),
+            "--sample-rate" => {
+                let rate = value("--sample-rate")?.parse()
+                    .map_err(|_| "--sample-rate needs a number".to_string())?;
+                options.sample_rate = Some(rate);
+            }
+
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+
+    options.rom = rom.ok_or("no ROM given".to_string())?;
+    if options.movie.is_some() && options.wav.is_some() {
+        return Err("--movie can't be combined with --wav".to_string());
+    }
+
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let cartridge = Cartridge::load(&options.rom)
+        .map_err(|error| format!("{}: {error:?}", options.rom.display()))?;
+
+    let mut nes = Nes::with_cartridge(cartridge, options.region);
+    if let Some(rate) = options.sample_rate {
+        nes.set_sample_rate(rate);
+    }
+
+    if let Some(path) = &options.movie {
+        let movie = Movie::load(path).map_err(|error| format!("{}: {error:?}", path.display()))?;
+
+        return match movie.verify(&mut nes) {
+            Ok(None) => Ok(()),
+            Ok(Some(desync)) => Err(format!("{}: desync at frame {}", path.display(), desync.frame)),
+            Err(error) => Err(format!("frame {}: {error:?}", nes.frame())),
+        };
+    }
+
+    let result = match &options.wav {
+        Some(path) => {
+            let mut wav = WavWriter::create(path, nes.sample_rate())
+                .map_err(|error| format!("{}: {error}", path.display()))?;
+
+            let recorded = nes.record_wav(&mut wav, options.frames);
+            let finished = wav.finish().map(|_| ()).map_err(|error| format!("{}: {error}", path.display()));
+
+            finished.and(recorded.map_err(|error| format!("frame {}: {error:?}", nes.frame())))
+        }
+        None => {
+            (0..options.frames).try_for_each(|_| {
+                nes.run_frame().map_err(|error| format!("frame {}: {error:?}", nes.frame()))
+            })
+        }
+    };
+
+    // Whatever the game saved is kept even if the run stopped on an error.
+    // Movies are only checked, they don't touch the save file
+    nes.flush().map_err(|error| format!("save file: {error}"))?;
+    result
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("nesa: {error}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("nesa: {error}");
+            ExitCode::FAILURE
+        }
+    }
 }